@@ -0,0 +1,127 @@
+//! Listening sockets passed by systemd or a parent process.
+
+use std::env;
+use std::os::unix::io::{BorrowedFd, FromRawFd, RawFd};
+
+use async_std::io;
+use socket2::{SockRef, Socket, Type};
+
+use crate::handoff::HandoffSocket;
+use crate::listener::SyncStream;
+use crate::{Listener, TcpIncoming};
+
+/// The first file descriptor passed by systemd.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+impl Listener {
+    /// Take the listening sockets passed by systemd socket activation.
+    ///
+    /// The sockets are found through the `LISTEN_PID` and `LISTEN_FDS`
+    /// environment variables, and returned in the order they are listed in
+    /// the socket unit. Returns no listeners if the process wasn't started by
+    /// socket activation.
+    ///
+    /// The variables are removed from the environment, so that the sockets are
+    /// only taken once, and child processes don't mistake them for their own.
+    /// Both TCP and Unix domain stream sockets are supported.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::{Listener, ServerBuilder};
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listeners = Listener::from_systemd()?;
+    ///     let mut server = ServerBuilder::new().build_from_listeners(listeners, service)?;
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_systemd() -> io::Result<Vec<Listener>> {
+        let pid = env::var("LISTEN_PID").ok();
+        let fds = env::var("LISTEN_FDS").ok();
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+
+        // The variables are meant for this process only, and may have been
+        // left behind by a parent that was started by socket activation.
+        match pid.map(|pid| pid.parse::<u32>()) {
+            Some(Ok(pid)) if pid == std::process::id() => {}
+            _ => return Ok(Vec::new()),
+        }
+        let fds: RawFd = fds
+            .as_deref()
+            .unwrap_or("0")
+            .parse()
+            .map_err(|_| invalid_input("LISTEN_FDS is not a number"))?;
+
+        (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + fds)
+            .map(|fd| {
+                // Safety: systemd hands these descriptors to this process, and
+                // removing the variables makes sure they are only taken once.
+                unsafe { Self::from_raw_fd(fd) }
+            })
+            .collect()
+    }
+
+    /// Take a listening socket inherited from a parent process.
+    ///
+    /// The socket must be a TCP or Unix domain stream socket, and already
+    /// listening. It is made non-blocking, and closed on `exec`. If the socket
+    /// isn't usable, an error is returned and the descriptor is left open.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor must be open, and owned by nothing else in this process;
+    /// the listener takes ownership of it, and closes it when dropped.
+    pub unsafe fn from_raw_fd(fd: RawFd) -> io::Result<Listener> {
+        let borrowed = BorrowedFd::borrow_raw(fd);
+        let socket = SockRef::from(&borrowed);
+        if socket.r#type()? != Type::STREAM {
+            return Err(invalid_input("not a stream socket"));
+        }
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            if !socket.is_listener()? {
+                return Err(invalid_input("socket is not listening"));
+            }
+        }
+        let addr = socket.local_addr()?;
+        if addr.as_socket().is_none() && !addr.is_unix() {
+            return Err(invalid_input("not a TCP or Unix domain socket"));
+        }
+        socket.set_cloexec(true)?;
+        socket.set_nonblocking(true)?;
+
+        // Nothing can fail past this point, as the descriptor would be closed
+        // along with the socket taking ownership of it.
+        let socket = Socket::from_raw_fd(fd);
+        if let Some(local_addr) = addr.as_socket() {
+            let incoming = TcpIncoming::from_nonblocking(socket.into(), local_addr, false, None);
+            Ok(Listener::tcp(incoming))
+        } else {
+            let listener = async_std::os::unix::net::UnixListener::from(
+                std::os::unix::net::UnixListener::from(socket),
+            );
+            let incoming = futures::stream::unfold(listener, |listener| async move {
+                let stream = listener.accept().await.map(|(stream, _)| stream);
+                Some((stream, listener))
+            });
+            let mut listener = Listener::new("http://localhost".into(), SyncStream::new(incoming));
+            // The socket file belongs to whoever created the socket.
+            listener.handoff = Some(HandoffSocket::new(fd, None));
+            Ok(listener)
+        }
+    }
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
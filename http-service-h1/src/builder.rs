@@ -0,0 +1,661 @@
+//! Configuring a server in one place.
+
+use std::fmt;
+use std::future::Future;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_std::io;
+use async_std::net::SocketAddr;
+use async_std::stream::Stream;
+use async_std::sync::Arc;
+use http_service::{ConnectionInfo, Error, HttpService, LocalHttpService, Request, Response};
+use http_service_server::accept::Acceptor;
+use http_service_server::error::{default_error_response, log_error};
+use http_service_server::shutdown::Signal;
+use ipnet::IpNet;
+
+use crate::conn::{ConnConfig, MAX_HEADER_SIZE};
+#[cfg(unix)]
+use crate::handoff::HandoffSocket;
+use crate::limit::Limiter;
+#[cfg(feature = "tls")]
+use crate::TlsConfig;
+use crate::{
+    AcceptErrorAction, AcceptErrorHandler, ErrorHandler, ErrorResponse, LimitAction, Listener,
+    LocalServer, Metrics, ProxyMode, Server, ServerConfig, Spawn, TcpIncoming, Transport,
+};
+
+/// A builder for a [`Server`](struct.Server.html).
+///
+/// The builder holds a [`ServerConfig`](struct.ServerConfig.html), which can be
+/// set at once or field by field, and the callbacks that can't be part of it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use http_service::{Request, Response};
+/// use http_service_h1::ServerBuilder;
+/// use std::time::Duration;
+///
+/// let service = |_req: Request| async {
+///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+/// };
+///
+/// async_std::task::block_on(async move {
+///     let mut server = ServerBuilder::new()
+///         .header_timeout(Some(Duration::from_secs(10)))
+///         .max_connections(Some(10_000))
+///         .nodelay(true)
+///         .server_header(Some("example".to_string()))
+///         .error_handler(|err| eprintln!("error: {}", err))
+///         .bind("127.0.0.1:3000".parse()?, service)?;
+///     server.run().await?;
+///     Ok::<(), Box<dyn std::error::Error>>(())
+/// })?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Default)]
+pub struct ServerBuilder {
+    config: ServerConfig,
+    accept_error_handler: Option<AcceptErrorHandler>,
+    error_handler: Option<ErrorHandler>,
+    error_response: Option<ErrorResponse>,
+    spawner: Option<Arc<dyn Spawn>>,
+    #[cfg(unix)]
+    handoff_path: Option<PathBuf>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+}
+
+impl ServerBuilder {
+    /// Create a builder with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a builder with the given configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[cfg(feature = "serde")]
+    /// # {
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::{ServerBuilder, ServerConfig};
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let config: ServerConfig = toml::from_str(&std::fs::read_to_string("server.toml")?)?;
+    ///     let mut server = ServerBuilder::from_config(config).bind("127.0.0.1:3000".parse()?, service)?;
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_config(config: ServerConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Replace the whole configuration.
+    ///
+    /// Callbacks set on the builder are kept.
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set how long a shutdown waits for in-flight requests to finish.
+    ///
+    /// See [`Server::set_shutdown_timeout`](struct.Server.html#method.set_shutdown_timeout).
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Set how long a client may take to send a request head.
+    ///
+    /// See [`Server::set_header_timeout`](struct.Server.html#method.set_header_timeout).
+    pub fn header_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.header_timeout = timeout;
+        self
+    }
+
+    /// Set how long a client may pause while sending a request body.
+    ///
+    /// See [`Server::set_body_timeout`](struct.Server.html#method.set_body_timeout).
+    pub fn body_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.body_timeout = timeout;
+        self
+    }
+
+    /// Set how long a connection may stay open without a request.
+    ///
+    /// See [`Server::set_idle_timeout`](struct.Server.html#method.set_idle_timeout).
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.idle_timeout = timeout;
+        self
+    }
+
+    /// Set how long a request may take, until its response is written.
+    ///
+    /// See [`Server::set_request_timeout`](struct.Server.html#method.set_request_timeout).
+    pub fn request_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.request_timeout = timeout;
+        self
+    }
+
+    /// Set the most connections served at once.
+    ///
+    /// See [`Server::set_max_connections`](struct.Server.html#method.set_max_connections).
+    pub fn max_connections(mut self, max: Option<usize>) -> Self {
+        self.config.max_connections = max;
+        self
+    }
+
+    /// Set what happens to connections over the limit.
+    ///
+    /// See [`Server::set_connection_limit_action`](struct.Server.html#method.set_connection_limit_action).
+    pub fn connection_limit_action(mut self, action: LimitAction) -> Self {
+        self.config.connection_limit_action = action;
+        self
+    }
+
+    /// Set the most requests served over a single connection.
+    ///
+    /// See [`Server::set_max_requests_per_connection`](struct.Server.html#method.set_max_requests_per_connection).
+    pub fn max_requests_per_connection(mut self, max: Option<usize>) -> Self {
+        self.config.max_requests_per_connection = max;
+        self
+    }
+
+    /// Set the most requests handled by the service at once.
+    ///
+    /// See [`Server::set_max_in_flight_requests`](struct.Server.html#method.set_max_in_flight_requests).
+    pub fn max_in_flight_requests(mut self, max: Option<usize>) -> Self {
+        self.config.max_in_flight_requests = max;
+        self
+    }
+
+    /// Set what happens to requests over the limit.
+    ///
+    /// See [`Server::set_request_limit_action`](struct.Server.html#method.set_request_limit_action).
+    pub fn request_limit_action(mut self, action: LimitAction) -> Self {
+        self.config.request_limit_action = action;
+        self
+    }
+
+    /// Set the longest the server waits before accepting again after an error.
+    ///
+    /// See [`Server::set_max_accept_backoff`](struct.Server.html#method.set_max_accept_backoff).
+    pub fn max_accept_backoff(mut self, max: Duration) -> Self {
+        self.config.max_accept_backoff = max;
+        self
+    }
+
+    /// Set the `Server` header added to responses.
+    ///
+    /// See [`Server::set_server_header`](struct.Server.html#method.set_server_header).
+    pub fn server_header(mut self, server: Option<String>) -> Self {
+        self.config.server_header = server;
+        self
+    }
+
+    /// Set the largest request head accepted, in bytes.
+    ///
+    /// See [`Server::set_max_header_size`](struct.Server.html#method.set_max_header_size).
+    pub fn max_header_size(mut self, size: usize) -> Self {
+        self.config.max_header_size = size;
+        self
+    }
+
+    /// Set `TCP_NODELAY` on accepted connections, disabling Nagle's algorithm.
+    ///
+    /// Only applies to servers created with [`bind`](#method.bind), and to
+    /// listeners created with [`tcp_listener`](#method.tcp_listener). Defaults
+    /// to `false`.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.config.nodelay = nodelay;
+        self
+    }
+
+    /// Enable TCP keepalive on accepted connections, sending probes after the
+    /// given idle time.
+    ///
+    /// Only applies to servers created with [`bind`](#method.bind), and to
+    /// listeners created with [`tcp_listener`](#method.tcp_listener). Defaults
+    /// to `None`, leaving the system's setting.
+    pub fn keepalive(mut self, time: Option<Duration>) -> Self {
+        self.config.keepalive = time;
+        self
+    }
+
+    /// Set `SO_REUSEPORT` on the listener, so that several listeners can bind
+    /// the same address and share its connections.
+    ///
+    /// Only applies to servers created with [`bind`](#method.bind), and to
+    /// listeners created with [`tcp_listener`](#method.tcp_listener). Only
+    /// supported on Unix; elsewhere binding fails. Defaults to `false`.
+    pub fn reuse_port(mut self, reuse_port: bool) -> Self {
+        self.config.reuse_port = reuse_port;
+        self
+    }
+
+    /// Read a PROXY protocol header at the start of each connection.
+    ///
+    /// See [`Server::set_proxy_protocol`](struct.Server.html#method.set_proxy_protocol).
+    pub fn proxy_protocol(mut self, mode: Option<ProxyMode>) -> Self {
+        self.config.proxy_protocol = mode;
+        self
+    }
+
+    /// Set the sources whose PROXY protocol headers are believed.
+    ///
+    /// See [`Server::set_trusted_proxies`](struct.Server.html#method.set_trusted_proxies).
+    pub fn trusted_proxies(mut self, trusted: Vec<IpNet>) -> Self {
+        self.config.trusted_proxies = trusted;
+        self
+    }
+
+    /// Set the number of worker threads of a thread-per-core server.
+    ///
+    /// Only applies to servers created with [`bind_workers`](#method.bind_workers)
+    /// and [`build_local`](#method.build_local). `None` runs a worker per CPU,
+    /// which is the default.
+    pub fn workers(mut self, workers: Option<usize>) -> Self {
+        self.config.workers = workers;
+        self
+    }
+
+    /// Set how the server reacts to errors while accepting connections.
+    ///
+    /// See [`Server::set_accept_error_handler`](struct.Server.html#method.set_accept_error_handler).
+    pub fn accept_error_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&io::Error) -> AcceptErrorAction + Send + Sync + 'static,
+    {
+        self.accept_error_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Set the callback that is told about errors returned by the service.
+    ///
+    /// See [`Server::set_error_handler`](struct.Server.html#method.set_error_handler).
+    pub fn error_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&Error) + Send + Sync + 'static,
+    {
+        self.error_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Set how errors returned by the service are turned into responses.
+    ///
+    /// See [`Server::set_error_response`](struct.Server.html#method.set_error_response).
+    pub fn error_response<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Error) -> Response + Send + Sync + 'static,
+    {
+        self.error_response = Some(Arc::new(f));
+        self
+    }
+
+    /// Set the executor the server spawns a task onto for each connection.
+    ///
+    /// Thread-per-core servers run an executor of their own on each worker,
+    /// and ignore it. See [`Server::set_spawner`](struct.Server.html#method.set_spawner).
+    pub fn spawner(mut self, spawner: impl Spawn) -> Self {
+        self.spawner = Some(Arc::new(spawner));
+        self
+    }
+
+    /// Set the path of the socket for handing the listening sockets over to a
+    /// replacement process.
+    ///
+    /// Thread-per-core servers can't hand their listeners over, and ignore it.
+    /// See [`Server::set_handoff_path`](struct.Server.html#method.set_handoff_path).
+    #[cfg(unix)]
+    pub fn handoff_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.handoff_path = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Serve connections over TLS.
+    ///
+    /// See [`Server::set_tls`](struct.Server.html#method.set_tls).
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Create a server for the connections yielded by `incoming`.
+    ///
+    /// The TCP options are left to whoever creates the connections.
+    pub fn build<I, S, T>(self, addr: String, incoming: I, service: S) -> Server<I, S>
+    where
+        S: HttpService,
+        <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+        <S as HttpService>::Connection: Sync,
+        I: Stream<Item = io::Result<T>> + Unpin + Send + Sync,
+        T: Transport,
+    {
+        let config = self.config;
+        let mut server = Server::new(addr, incoming, service);
+        server.set_shutdown_timeout(config.shutdown_timeout);
+        server.timeouts = config.timeouts();
+        server.limits = config.limits();
+        server.proxy = config.proxy();
+        server.set_max_accept_backoff(config.max_accept_backoff);
+        server.set_server_header(config.server_header);
+        server.set_max_header_size(config.max_header_size);
+        if let Some(handler) = self.accept_error_handler {
+            server.accept_error_handler = handler;
+        }
+        if let Some(handler) = self.error_handler {
+            server.error_handler = handler;
+        }
+        if let Some(f) = self.error_response {
+            server.error_response = f;
+        }
+        if let Some(spawner) = self.spawner {
+            server.spawner = spawner;
+        }
+        #[cfg(unix)]
+        {
+            server.handoff_path = self.handoff_path;
+        }
+        #[cfg(feature = "tls")]
+        {
+            if let Some(tls) = self.tls {
+                server.set_tls(tls);
+            }
+        }
+        server
+    }
+
+    /// Create a server for the given listeners.
+    ///
+    /// Each listener keeps its own address and TLS setting, and the builder's
+    /// TLS setting is ignored. Fails if there are no listeners.
+    pub fn build_from_listeners<S>(
+        self,
+        listeners: Vec<Listener>,
+        service: S,
+    ) -> io::Result<Server<Listener, S>>
+    where
+        S: HttpService,
+        <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+        <S as HttpService>::Connection: Sync,
+    {
+        let mut listeners = listeners.into_iter();
+        let Listener {
+            addr,
+            incoming,
+            #[cfg(feature = "tls")]
+            tls,
+            #[cfg(unix)]
+            handoff,
+        } = listeners
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no listeners"))?;
+        let first = Listener {
+            addr: addr.clone(),
+            incoming,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(unix)]
+            handoff: None,
+        };
+        let mut server = self.build(addr.clone(), first, service);
+        server.addr = addr;
+        #[cfg(unix)]
+        {
+            server.handoff = handoff;
+        }
+        #[cfg(feature = "tls")]
+        {
+            server.tls = tls;
+        }
+        for listener in listeners {
+            server.add_listener(listener);
+        }
+        Ok(server)
+    }
+
+    /// Create a server listening on the given TCP address.
+    pub fn bind<S>(self, addr: SocketAddr, service: S) -> io::Result<Server<TcpIncoming, S>>
+    where
+        S: HttpService,
+        <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+        <S as HttpService>::Connection: Sync,
+    {
+        let incoming = self.tcp_incoming(addr)?;
+        let addr = format!("http://{}", incoming.local_addr());
+        #[cfg(unix)]
+        let handoff = HandoffSocket::new(std::os::unix::io::AsRawFd::as_raw_fd(&incoming), None);
+        #[allow(unused_mut)]
+        let mut server = self.build(addr, incoming, service);
+        #[cfg(unix)]
+        {
+            server.handoff = Some(handoff);
+        }
+        Ok(server)
+    }
+
+    /// Create a thread-per-core server listening on the given TCP address,
+    /// with the service shared by all workers.
+    ///
+    /// Unlike [`bind`](#method.bind), where one loop accepts every connection
+    /// and the spawner's executor serves them, each worker thread accepts from
+    /// a listener of its own, bound with `SO_REUSEPORT`, and serves its
+    /// connections on its own executor. Connections never move between
+    /// workers. See [`LocalServer`](struct.LocalServer.html).
+    ///
+    /// As with [`build_local`](#method.build_local), the spawner and handoff
+    /// path settings are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::ServerBuilder;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// let server = ServerBuilder::new()
+    ///     .workers(Some(8))
+    ///     .bind_workers("0.0.0.0:8080".parse()?, service)?;
+    /// let metrics = server.metrics();
+    /// let handle = server.shutdown_handle();
+    /// std::thread::spawn(move || {
+    ///     std::thread::sleep(std::time::Duration::from_secs(60));
+    ///     println!("served {} requests", metrics.requests());
+    ///     handle.shutdown();
+    /// });
+    /// server.run()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn bind_workers<S>(
+        self,
+        addr: SocketAddr,
+        service: S,
+    ) -> io::Result<LocalServer<impl Fn() -> SharedService<S> + Send + Sync + 'static>>
+    where
+        S: HttpService,
+    {
+        let service = SharedService(Arc::new(service));
+        self.build_local(addr, move || service.clone())
+    }
+
+    /// Create a thread-per-core server listening on the given TCP address.
+    ///
+    /// A listener is bound for each worker with `SO_REUSEPORT`, whatever the
+    /// [`reuse_port`](#method.reuse_port) setting, and each worker calls
+    /// `make_service` once, on its own thread, for the service it runs. See
+    /// [`LocalServer`](struct.LocalServer.html).
+    ///
+    /// The [`spawner`](#method.spawner) and [`handoff_path`](#method.handoff_path)
+    /// settings don't apply to thread-per-core servers; a warning is logged if
+    /// either is set.
+    pub fn build_local<F, S>(self, addr: SocketAddr, make_service: F) -> io::Result<LocalServer<F>>
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: LocalHttpService,
+    {
+        if self.spawner.is_some() {
+            log::warn!("thread-per-core servers ignore the spawner");
+        }
+        #[cfg(unix)]
+        {
+            if self.handoff_path.is_some() {
+                log::warn!("thread-per-core servers ignore the handoff path");
+            }
+        }
+        let config = self.config;
+        let limits = config.limits();
+        limits.validate()?;
+        let bind = |addr| TcpIncoming::bind(addr, true, config.nodelay, config.keepalive);
+        // When binding port 0, the first listener picks the port the others
+        // share.
+        let first = bind(addr)?;
+        let addr = first.local_addr();
+        let mut listeners = vec![first];
+        for _ in 1..config.workers() {
+            listeners.push(bind(addr)?);
+        }
+
+        let shutdown = Signal::new();
+        #[allow(unused_mut)]
+        let mut conn = ConnConfig {
+            addr: format!("http://{}", addr),
+            shutdown: shutdown.clone(),
+            force_close: Signal::new(),
+            error_handler: self.error_handler.unwrap_or_else(|| Arc::new(log_error)),
+            error_response: self
+                .error_response
+                .unwrap_or_else(|| Arc::new(default_error_response)),
+            timeouts: config.timeouts(),
+            max_requests: limits.requests_per_connection,
+            requests: limits
+                .in_flight_requests
+                .map(|max| (Limiter::new(max), limits.request_action)),
+            server_header: config.server_header.clone(),
+            max_header_size: config.max_header_size.min(MAX_HEADER_SIZE),
+            metrics: Metrics::default(),
+            proxy: config.proxy(),
+            #[cfg(feature = "tls")]
+            tls: None,
+        };
+        #[cfg(feature = "tls")]
+        {
+            if let Some(tls) = self.tls {
+                conn.addr = format!("https://{}", addr);
+                conn.tls = Some(tls);
+            }
+        }
+        let acceptor = Acceptor {
+            shutdown,
+            connection_limit: limits.connections.map(Limiter::new),
+            connection_action: limits.connection_action,
+            error_handler: self
+                .accept_error_handler
+                .unwrap_or_else(|| Arc::new(AcceptErrorAction::classify)),
+            max_backoff: config.max_accept_backoff,
+        };
+        Ok(LocalServer::new(
+            make_service,
+            listeners,
+            conn,
+            acceptor,
+            config.shutdown_timeout,
+        ))
+    }
+
+    /// Create a listener bound to the given TCP address, with the TCP options
+    /// of the builder, to add to a server with
+    /// [`Server::add_listener`](struct.Server.html#method.add_listener).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::ServerBuilder;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let builder = ServerBuilder::new().nodelay(true);
+    ///     let ipv6 = builder.tcp_listener("[::]:8080".parse()?)?;
+    ///     let mut server = builder.bind("0.0.0.0:8080".parse()?, service)?;
+    ///     server.add_listener(ipv6);
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn tcp_listener(&self, addr: SocketAddr) -> io::Result<Listener> {
+        Ok(Listener::tcp(self.tcp_incoming(addr)?))
+    }
+
+    fn tcp_incoming(&self, addr: SocketAddr) -> io::Result<TcpIncoming> {
+        let config = &self.config;
+        TcpIncoming::bind(addr, config.reuse_port, config.nodelay, config.keepalive)
+    }
+}
+
+impl fmt::Debug for ServerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerBuilder")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+/// One service shared by all the workers of a thread-per-core server.
+///
+/// Each worker of a server created with [`ServerBuilder::bind_workers`]
+/// serves its connections with a clone of it, and every clone refers to the
+/// same service, to which it forwards the calls made to it as an
+/// `HttpService`.
+pub struct SharedService<S>(Arc<S>);
+
+impl<S> Clone for SharedService<S> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<S> fmt::Debug for SharedService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedService").finish()
+    }
+}
+
+impl<S: HttpService> HttpService for SharedService<S> {
+    type Connection = S::Connection;
+    type ConnectionError = S::ConnectionError;
+    type ConnectionFuture = S::ConnectionFuture;
+    type ResponseError = S::ResponseError;
+    type ResponseFuture = S::ResponseFuture;
+
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture {
+        self.0.connect(info)
+    }
+
+    fn respond(&self, conn: Self::Connection, req: Request) -> Self::ResponseFuture {
+        self.0.respond(conn, req)
+    }
+}
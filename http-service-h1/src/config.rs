@@ -0,0 +1,164 @@
+//! Server configuration.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use ipnet::IpNet;
+
+use crate::conn::{Timeouts, MAX_HEADER_SIZE};
+use crate::limit::{LimitAction, Limits};
+use crate::proxy::{Proxy, ProxyMode};
+
+/// The settings of a [`Server`](struct.Server.html), in one place.
+///
+/// Every field has the same default as the matching setter of `Server`, and is
+/// applied by [`ServerBuilder`](struct.ServerBuilder.html).
+///
+/// With the `serde` feature, the configuration can be deserialized, such as
+/// from a TOML file. Missing fields keep their defaults, and durations are
+/// written like `"30s"` or `"500ms"`.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use http_service_h1::{LimitAction, ServerConfig};
+/// use std::time::Duration;
+///
+/// let config: ServerConfig = toml::from_str(r#"
+///     header_timeout = "10s"
+///     max_connections = 10000
+///     connection_limit_action = "reject"
+///     nodelay = true
+///     server_header = "example"
+/// "#)?;
+///
+/// assert_eq!(config.header_timeout, Some(Duration::from_secs(10)));
+/// assert_eq!(config.max_connections, Some(10000));
+/// assert_eq!(config.connection_limit_action, LimitAction::Reject);
+/// assert_eq!(config.idle_timeout, ServerConfig::default().idle_timeout);
+/// # }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct ServerConfig {
+    /// How long a shutdown waits for in-flight requests to finish.
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub shutdown_timeout: Duration,
+    /// How long a client may take to send a request head, or to complete
+    /// the TLS handshake, up to 60 seconds.
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub header_timeout: Option<Duration>,
+    /// How long a client may pause while sending a request body.
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub body_timeout: Option<Duration>,
+    /// How long a connection may stay open without a request.
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub idle_timeout: Option<Duration>,
+    /// How long a request may take, until its response is written.
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub request_timeout: Option<Duration>,
+    /// The most connections served at once.
+    pub max_connections: Option<usize>,
+    /// What happens to connections over the limit.
+    pub connection_limit_action: LimitAction,
+    /// The most requests served over a single connection.
+    pub max_requests_per_connection: Option<usize>,
+    /// The most requests handled by the service at once.
+    pub max_in_flight_requests: Option<usize>,
+    /// What happens to requests over the limit.
+    pub request_limit_action: LimitAction,
+    /// The longest the server waits before accepting again after an error.
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub max_accept_backoff: Duration,
+    /// The `Server` header added to responses.
+    pub server_header: Option<String>,
+    /// The largest request head accepted, in bytes, up to 8,190.
+    pub max_header_size: usize,
+    /// Whether to set `TCP_NODELAY` on accepted connections.
+    pub nodelay: bool,
+    /// The idle time before TCP keepalive probes are sent on accepted
+    /// connections.
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub keepalive: Option<Duration>,
+    /// Whether to set `SO_REUSEPORT` on the listener.
+    pub reuse_port: bool,
+    /// Whether connections start with a PROXY protocol header.
+    pub proxy_protocol: Option<ProxyMode>,
+    /// The sources whose PROXY protocol headers are believed, such as
+    /// `"10.0.0.0/8"`. None are by default.
+    pub trusted_proxies: Vec<IpNet>,
+    /// The number of worker threads of a thread-per-core server, or one per
+    /// CPU if unset.
+    pub workers: Option<usize>,
+}
+
+impl ServerConfig {
+    pub(crate) fn timeouts(&self) -> Timeouts {
+        Timeouts {
+            header: Timeouts::cap_header(self.header_timeout),
+            body: self.body_timeout,
+            idle: self.idle_timeout,
+            request: self.request_timeout,
+        }
+    }
+
+    pub(crate) fn proxy(&self) -> Proxy {
+        Proxy {
+            mode: self.proxy_protocol,
+            trusted: Arc::new(self.trusted_proxies.clone()),
+        }
+    }
+
+    /// The number of worker threads of a thread-per-core server.
+    pub(crate) fn workers(&self) -> usize {
+        self.workers
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+            .max(1)
+    }
+
+    pub(crate) fn limits(&self) -> Limits {
+        Limits {
+            connections: self.max_connections,
+            connection_action: self.connection_limit_action,
+            requests_per_connection: self.max_requests_per_connection,
+            in_flight_requests: self.max_in_flight_requests,
+            request_action: self.request_limit_action,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let timeouts = Timeouts::default();
+        let limits = Limits::default();
+        Self {
+            shutdown_timeout: Duration::from_secs(30),
+            header_timeout: timeouts.header,
+            body_timeout: timeouts.body,
+            idle_timeout: timeouts.idle,
+            request_timeout: timeouts.request,
+            max_connections: limits.connections,
+            connection_limit_action: limits.connection_action,
+            max_requests_per_connection: limits.requests_per_connection,
+            max_in_flight_requests: limits.in_flight_requests,
+            request_limit_action: limits.request_action,
+            max_accept_backoff: Duration::from_secs(1),
+            server_header: None,
+            max_header_size: MAX_HEADER_SIZE,
+            nodelay: false,
+            keepalive: None,
+            reuse_port: false,
+            proxy_protocol: None,
+            trusted_proxies: Vec::new(),
+            workers: None,
+        }
+    }
+}
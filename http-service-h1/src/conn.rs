@@ -0,0 +1,761 @@
+//! Serving individual connections.
+
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use async_std::io;
+use futures::future::{self, Either};
+use http_service::{Error, LocalAddr, LocalHttpService, PeerAddr, Request, Response};
+use http_service_server::accept::ListenerConfig;
+use http_service_server::shutdown::Signal;
+use http_types::headers::HeaderName;
+use http_types::{StatusCode, Version};
+
+use crate::limit::{LimitAction, Limiter};
+use crate::metrics::Metrics;
+use crate::proxy::{self, Proxy};
+#[cfg(feature = "tls")]
+use crate::TlsConfig;
+use crate::{ErrorHandler, ErrorResponse, Transport};
+
+/// Server settings needed by each connection.
+#[derive(Clone)]
+pub(crate) struct ConnConfig {
+    pub(crate) addr: String,
+    pub(crate) shutdown: Signal,
+    pub(crate) force_close: Signal,
+    pub(crate) error_handler: ErrorHandler,
+    pub(crate) error_response: ErrorResponse,
+    pub(crate) timeouts: Timeouts,
+    pub(crate) max_requests: Option<usize>,
+    pub(crate) requests: Option<(Arc<Limiter>, LimitAction)>,
+    pub(crate) server_header: Option<String>,
+    pub(crate) max_header_size: usize,
+    pub(crate) metrics: Metrics,
+    pub(crate) proxy: Proxy,
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<TlsConfig>,
+}
+
+/// The largest request head that can be refused with a response.
+///
+/// `async_h1` gives up on a head once it has parsed 8 KiB of it, without a
+/// response. Reads stop one byte past this size, before that happens.
+pub(crate) const MAX_HEADER_SIZE: usize = 8 * 1024 - 2;
+
+/// The response sent when a request head isn't received in time.
+const HEADER_TIMEOUT_RESPONSE: &[u8] =
+    b"HTTP/1.1 408 Request Timeout\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+
+/// The response sent when a request head is too large.
+const HEADER_TOO_LARGE_RESPONSE: &[u8] = b"HTTP/1.1 431 Request Header Fields Too Large\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+
+/// How long sending a response rejecting a request head, and waiting for the
+/// client to hang up, may take.
+const REJECT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The longest `async_h1` waits for a request head, after which it closes the
+/// connection without a response.
+const MAX_HEADER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The timeouts applied to every connection.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timeouts {
+    /// From the first byte of a request until its head is read.
+    pub(crate) header: Option<Duration>,
+    /// The longest wait for the next chunk of a request body.
+    pub(crate) body: Option<Duration>,
+    /// Between requests, or before the first one.
+    pub(crate) idle: Option<Duration>,
+    /// From the first byte of a request until its response is written.
+    pub(crate) request: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Cap a header timeout to the most `async_h1` allows, so that clients
+    /// are always told when their request head took too long.
+    pub(crate) fn cap_header(timeout: Option<Duration>) -> Option<Duration> {
+        Some(timeout.map_or(MAX_HEADER_TIMEOUT, |t| t.min(MAX_HEADER_TIMEOUT)))
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            header: Some(Duration::from_secs(30)),
+            body: Some(Duration::from_secs(30)),
+            idle: Some(Duration::from_secs(60)),
+            request: None,
+        }
+    }
+}
+
+/// Which timeout cut a request short.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Timeout {
+    Header,
+    Body,
+    Request,
+}
+
+impl Timeout {
+    /// The error a timeout is reported as, when a response can still be sent.
+    fn error(self) -> Error {
+        match self {
+            Timeout::Header => Error::from_str(
+                StatusCode::RequestTimeout,
+                "timed out reading the request head",
+            ),
+            Timeout::Body => Error::from_str(
+                StatusCode::RequestTimeout,
+                "timed out reading the request body",
+            ),
+            Timeout::Request => Error::from_str(
+                StatusCode::ServiceUnavailable,
+                "timed out producing the response",
+            ),
+        }
+    }
+}
+
+impl ListenerConfig for ConnConfig {
+    fn addr(&self) -> &str {
+        &self.addr
+    }
+}
+
+impl ConnConfig {
+    /// Report an error from the service, and turn it into a response.
+    fn error_response(&self, err: Error) -> Response {
+        (self.error_handler)(&err);
+        (self.error_response)(&err)
+    }
+}
+
+/// Accept a new connection.
+///
+/// The service is shared through `P`, an `Arc` when connections are served on
+/// a thread pool, or an `Rc` when they stay on one thread.
+pub(crate) async fn accept<P, T>(
+    service: P,
+    stream: T,
+    config: Arc<ConnConfig>,
+) -> Result<(), Error>
+where
+    P: Deref + Clone,
+    P::Target: LocalHttpService,
+    T: Transport,
+{
+    // The PROXY protocol header comes before the TLS handshake.
+    if let Some(mode) = config.proxy.mode {
+        let stream = proxy::accept(stream, mode, &config.proxy, config.timeouts.header)
+            .await
+            .map_err(|err| {
+                log::debug!("error reading PROXY protocol header: {}", err);
+                err
+            })?;
+        return handshake(service, stream, config).await;
+    }
+
+    handshake(service, stream, config).await
+}
+
+/// Perform the TLS handshake, if any, and serve the connection.
+async fn handshake<P, T>(service: P, stream: T, config: Arc<ConnConfig>) -> Result<(), Error>
+where
+    P: Deref + Clone,
+    P::Target: LocalHttpService,
+    T: Transport,
+{
+    #[cfg(feature = "tls")]
+    {
+        if let Some(tls) = &config.tls {
+            // A stalled handshake is bounded like a stalled request head.
+            let accept = tls.acceptor().accept(stream);
+            let stream = match config.timeouts.header {
+                Some(timeout) => io::timeout(timeout, accept).await,
+                None => accept.await,
+            }
+            .map_err(|err| {
+                log::debug!("error during TLS handshake: {}", err);
+                err
+            })?;
+            return serve(service, stream, config).await;
+        }
+    }
+
+    serve(service, stream, config).await
+}
+
+/// Serve HTTP on an established connection.
+async fn serve<P, T>(service: P, stream: T, config: Arc<ConnConfig>) -> Result<(), Error>
+where
+    P: Deref + Clone,
+    P::Target: LocalHttpService,
+    T: Transport,
+{
+    let mut info = stream.connection_info();
+    info.set_protocol(Some(Version::Http1_1));
+
+    let state = Arc::new(ConnState::new(
+        config.shutdown.clone(),
+        config.timeouts,
+        config.max_header_size,
+    ));
+    let stream = WrapStream(Arc::new(Mutex::new(stream)), state.clone());
+
+    // If the service refuses the connection, the error is sent in response to
+    // the first request, and the connection is closed.
+    let (conn, conn_error) = match service.clone().connect(&info).await {
+        Ok(conn) => (Some(conn), Mutex::new(None)),
+        Err(err) => (None, Mutex::new(Some(err.into()))),
+    };
+
+    let endpoint = |mut req: Request| async {
+        if let Some(addr) = info.peer_addr() {
+            req.local_mut().insert(PeerAddr::new(addr));
+        }
+        if let Some(addr) = info.local_addr() {
+            req.local_mut().insert(LocalAddr::new(addr));
+        }
+        let conn = conn.clone();
+        let service = service.clone();
+        let state = state.clone();
+        let config = config.clone();
+        let conn_error = conn_error.lock().unwrap().take();
+        async move {
+            state.set_phase(Phase::Body);
+            let _request = config.metrics.request();
+            if let Some(max) = config.max_requests {
+                if state.requests.fetch_add(1, Ordering::SeqCst) + 1 >= max {
+                    state.close_after_response();
+                }
+            }
+            let mut res = match (conn, conn_error) {
+                (Some(conn), _) => {
+                    let respond = async {
+                        let _permit = match &config.requests {
+                            Some((limit, LimitAction::Wait)) => Some(limit.acquire().await),
+                            Some((limit, LimitAction::Reject)) => Some(limit.try_acquire()?),
+                            None => None,
+                        };
+                        Some(service.respond(conn, req).await)
+                    };
+                    let res = match state.time_left() {
+                        Some(left) => async_std::future::timeout(left, respond).await.ok(),
+                        None => Some(respond.await),
+                    };
+                    // A request cut short by a timeout can't be read any
+                    // further, so the connection is closed after the response.
+                    match (res, state.timed_out()) {
+                        (Some(Some(Ok(res))), _) => res,
+                        (Some(Some(Err(err))), None) => config.error_response(err.into()),
+                        (Some(Some(Err(_))), Some(timeout)) => {
+                            state.close_after_response();
+                            config.error_response(timeout.error())
+                        }
+                        (Some(None), _) => config.error_response(Error::from_str(
+                            StatusCode::ServiceUnavailable,
+                            "too many requests in flight",
+                        )),
+                        (None, _) => {
+                            log::debug!("timed out producing the response");
+                            state.set_timed_out(Timeout::Request);
+                            state.close_after_response();
+                            config.error_response(Timeout::Request.error())
+                        }
+                    }
+                }
+                (None, Some(err)) => {
+                    state.close_after_response();
+                    config.error_response(err)
+                }
+                // The connection is closed after the error is sent.
+                (None, None) => {
+                    return Err(io::Error::from(io::ErrorKind::ConnectionAborted).into())
+                }
+            };
+            if let Some(server) = &config.server_header {
+                let name: HeaderName = "Server".parse()?;
+                if res.header(&name).is_none() {
+                    res.insert_header(name, server.as_str())?;
+                }
+            }
+            if state.is_closing() {
+                res.insert_header("Connection", "close")?;
+            }
+            state.set_phase(Phase::Responded);
+            Ok(res)
+        }
+        .await
+    };
+
+    // Requests are handed to `async_h1` one at a time, once their first bytes
+    // arrive, as it closes connections whose next request head takes longer
+    // than a minute to read, time spent idle included.
+    loop {
+        if !stream.wait_for_request().await {
+            return Ok(());
+        }
+        let serve = async_h1::accept(&config.addr, stream.clone(), &endpoint);
+
+        // Once a shutdown starts, connections are left to finish on their own
+        // until they are forcibly closed.
+        let drain = async {
+            config.shutdown.wait().await;
+            config.force_close.wait().await;
+        };
+
+        futures::pin_mut!(serve, drain);
+        match future::select(serve, drain).await {
+            Either::Left((Ok(()), _)) => {}
+            Either::Left((Err(err), _)) => {
+                // A client whose request head is too slow or too large is
+                // told so, as long as it is quick to take the response.
+                let rejection = if state.head_too_large.load(Ordering::SeqCst) {
+                    Some(HEADER_TOO_LARGE_RESPONSE)
+                } else if state.timed_out() == Some(Timeout::Header) {
+                    Some(HEADER_TIMEOUT_RESPONSE)
+                } else {
+                    None
+                };
+                if let Some(rejection) = rejection {
+                    let reject = async {
+                        stream.write_raw(rejection).await?;
+                        stream.linger().await
+                    };
+                    if let Err(err) = io::timeout(REJECT_WRITE_TIMEOUT, reject).await {
+                        log::debug!("error rejecting request: {}", err);
+                    }
+                    return Ok(());
+                }
+                log::debug!("error serving connection: {}", err);
+                return Err(err);
+            }
+            Either::Right(_) => return Ok(()),
+        }
+
+        // Without a complete response, the client hung up mid-request.
+        if !state.response_written() || state.is_closing() {
+            return Ok(());
+        }
+    }
+}
+
+/// Where a connection is in the request/response cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Phase {
+    /// Waiting for the next request.
+    Idle,
+    /// Reading a request head.
+    Head,
+    /// Producing a response, and reading the request body.
+    Body,
+    /// Writing a response.
+    Responded,
+}
+
+/// State shared by all handles to a connection.
+#[derive(Debug)]
+struct ConnState {
+    phase: AtomicU8,
+    closing: AtomicBool,
+    requests: AtomicUsize,
+    head_read: AtomicUsize,
+    head_too_large: AtomicBool,
+    /// Set once the response to the current request is written.
+    response_written: AtomicBool,
+    /// The first bytes of the next request, read while waiting for it.
+    read_ahead: Mutex<Vec<u8>>,
+    max_header_size: usize,
+    shutdown: Signal,
+    timeouts: Timeouts,
+    clock: Mutex<Clock>,
+}
+
+/// When things happened on a connection, and the timers waiting on them.
+#[derive(Debug)]
+struct Clock {
+    /// When the current phase started.
+    phase_start: Instant,
+    /// When the current request started.
+    request_start: Instant,
+    /// When request data was last received.
+    last_read: Instant,
+    read_timer: Timer,
+    write_timer: Timer,
+    timed_out: Option<Timeout>,
+}
+
+impl ConnState {
+    fn new(shutdown: Signal, timeouts: Timeouts, max_header_size: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            phase: AtomicU8::new(Phase::Idle as u8),
+            closing: AtomicBool::new(false),
+            requests: AtomicUsize::new(0),
+            head_read: AtomicUsize::new(0),
+            head_too_large: AtomicBool::new(false),
+            response_written: AtomicBool::new(false),
+            read_ahead: Mutex::new(Vec::new()),
+            max_header_size,
+            shutdown,
+            timeouts,
+            clock: Mutex::new(Clock {
+                phase_start: now,
+                request_start: now,
+                last_read: now,
+                read_timer: Timer::default(),
+                write_timer: Timer::default(),
+                timed_out: None,
+            }),
+        }
+    }
+
+    /// Close the connection once the current response is sent.
+    fn close_after_response(&self) {
+        self.closing.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the connection closes once the current response is sent.
+    fn is_closing(&self) -> bool {
+        self.closing.load(Ordering::SeqCst) || self.shutdown.is_fired()
+    }
+
+    fn phase(&self) -> Phase {
+        match self.phase.load(Ordering::SeqCst) {
+            0 => Phase::Idle,
+            1 => Phase::Head,
+            2 => Phase::Body,
+            _ => Phase::Responded,
+        }
+    }
+
+    fn set_phase(&self, phase: Phase) {
+        let now = Instant::now();
+        let mut clock = self.clock.lock().unwrap();
+        clock.phase_start = now;
+        match phase {
+            Phase::Head => {
+                clock.request_start = now;
+                clock.timed_out = None;
+            }
+            Phase::Body => clock.last_read = now,
+            Phase::Idle => {
+                self.head_read.store(0, Ordering::SeqCst);
+                self.response_written.store(false, Ordering::SeqCst);
+            }
+            Phase::Responded => {}
+        }
+        self.phase.store(phase as u8, Ordering::SeqCst);
+    }
+
+    /// Whether the response to the current request is written.
+    fn response_written(&self) -> bool {
+        self.response_written.load(Ordering::SeqCst)
+    }
+
+    /// Which timeout cut the current request short, if any.
+    fn timed_out(&self) -> Option<Timeout> {
+        self.clock.lock().unwrap().timed_out
+    }
+
+    fn set_timed_out(&self, timeout: Timeout) {
+        self.clock.lock().unwrap().timed_out = Some(timeout);
+    }
+
+    /// The time left until the current request times out.
+    fn time_left(&self) -> Option<Duration> {
+        let clock = self.clock.lock().unwrap();
+        let deadline = clock.request_start + self.timeouts.request?;
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Wait for the deadline of a pending read, if there is one.
+    ///
+    /// Returns the timeout that expired.
+    fn poll_read_deadline(&self, cx: &mut Context<'_>) -> Poll<Option<Timeout>> {
+        let timeouts = &self.timeouts;
+        let mut clock = self.clock.lock().unwrap();
+        let request = timeouts
+            .request
+            .map(|t| (clock.request_start + t, Timeout::Request));
+        let deadline = match self.phase() {
+            Phase::Idle => timeouts.idle.map(|t| (clock.phase_start + t, None)),
+            Phase::Head => {
+                let header = timeouts
+                    .header
+                    .map(|t| (clock.request_start + t, Timeout::Header));
+                earliest(header, request).map(|(at, timeout)| (at, Some(timeout)))
+            }
+            Phase::Body => {
+                let body = timeouts.body.map(|t| (clock.last_read + t, Timeout::Body));
+                earliest(body, request).map(|(at, timeout)| (at, Some(timeout)))
+            }
+            Phase::Responded => None,
+        };
+        let (at, timeout) = match deadline {
+            Some(deadline) => deadline,
+            None => return Poll::Pending,
+        };
+        futures::ready!(clock.read_timer.poll(cx, at));
+        clock.timed_out = timeout;
+        Poll::Ready(timeout)
+    }
+
+    /// Wait for the deadline of a pending write, if there is one.
+    fn poll_write_deadline(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut clock = self.clock.lock().unwrap();
+        let at = match self.timeouts.request {
+            Some(timeout) => clock.request_start + timeout,
+            None => return Poll::Pending,
+        };
+        futures::ready!(clock.write_timer.poll(cx, at));
+        clock.timed_out = Some(Timeout::Request);
+        Poll::Ready(())
+    }
+
+    /// Note that request data was received.
+    fn read(&self) {
+        self.clock.lock().unwrap().last_read = Instant::now();
+    }
+}
+
+/// The deadline that comes first.
+fn earliest<T>(a: Option<(Instant, T)>, b: Option<(Instant, T)>) -> Option<(Instant, T)> {
+    match (a, b) {
+        (Some(a), Some(b)) if b.0 < a.0 => Some(b),
+        (Some(a), _) => Some(a),
+        (None, b) => b,
+    }
+}
+
+type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A timer that can be moved to a new deadline.
+#[derive(Default)]
+struct Timer {
+    sleep: Option<(Instant, Sleep)>,
+}
+
+impl Timer {
+    /// Wait until the deadline.
+    fn poll(&mut self, cx: &mut Context<'_>, deadline: Instant) -> Poll<()> {
+        let now = Instant::now();
+        if now >= deadline {
+            self.sleep = None;
+            return Poll::Ready(());
+        }
+        match &mut self.sleep {
+            Some((at, _)) if *at == deadline => {}
+            sleep => {
+                let timer = async_std::task::sleep(deadline - now);
+                *sleep = Some((deadline, Box::pin(timer)));
+            }
+        }
+        let (_, timer) = self.sleep.as_mut().unwrap();
+        futures::ready!(timer.as_mut().poll(cx));
+        self.sleep = None;
+        Poll::Ready(())
+    }
+}
+
+impl std::fmt::Debug for Timer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let deadline = self.sleep.as_ref().map(|(at, _)| at);
+        f.debug_struct("Timer")
+            .field("deadline", &deadline)
+            .finish()
+    }
+}
+
+/// A cloneable handle to a connection's transport, as `async_h1` requires.
+///
+/// Reads and writes never happen at the same time, so the lock is never
+/// contended.
+struct WrapStream<T>(Arc<Mutex<T>>, Arc<ConnState>);
+
+impl<T> Clone for WrapStream<T> {
+    fn clone(&self) -> Self {
+        WrapStream(self.0.clone(), self.1.clone())
+    }
+}
+
+impl<T: Transport> WrapStream<T> {
+    /// Wait for the first bytes of the next request, which are kept to be read
+    /// again.
+    ///
+    /// Returns `false` if the connection is to be closed instead: the client
+    /// hung up, the idle timeout expired or the server is shutting down.
+    async fn wait_for_request(&self) -> bool {
+        self.1.set_phase(Phase::Idle);
+        let mut stream = self.clone();
+        let mut buf = [0; 1024];
+        let read = future::poll_fn(|cx| io::Read::poll_read(Pin::new(&mut stream), cx, &mut buf));
+        match future::select(read, self.1.shutdown.wait()).await {
+            Either::Left((Ok(0), _)) | Either::Right(_) => false,
+            Either::Left((Ok(n), _)) => {
+                self.1
+                    .read_ahead
+                    .lock()
+                    .unwrap()
+                    .extend_from_slice(&buf[..n]);
+                true
+            }
+            Either::Left((Err(err), _)) => {
+                log::debug!("error waiting for a request: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Write directly to the transport, bypassing the timeouts.
+    async fn write_raw(&self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let n =
+                future::poll_fn(|cx| Pin::new(&mut *self.0.lock().unwrap()).poll_write(cx, buf))
+                    .await?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            buf = &buf[n..];
+        }
+        future::poll_fn(|cx| Pin::new(&mut *self.0.lock().unwrap()).poll_flush(cx)).await
+    }
+
+    /// Stop writing, and discard what the client still sends until it hangs
+    /// up.
+    ///
+    /// Closing a connection with unread data resets it, which can discard a
+    /// response the client hasn't read yet.
+    async fn linger(&self) -> io::Result<()> {
+        future::poll_fn(|cx| Pin::new(&mut *self.0.lock().unwrap()).poll_close(cx)).await?;
+        let mut buf = [0; 1024];
+        loop {
+            let read = future::poll_fn(|cx| {
+                Pin::new(&mut *self.0.lock().unwrap()).poll_read(cx, &mut buf)
+            });
+            if read.await? == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<T: Transport> io::Read for WrapStream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let state = &self.1;
+        {
+            let mut read_ahead = state.read_ahead.lock().unwrap();
+            if !read_ahead.is_empty() {
+                let n = buf.len().min(read_ahead.len());
+                buf[..n].copy_from_slice(&read_ahead[..n]);
+                read_ahead.drain(..n);
+                return Poll::Ready(Ok(n));
+            }
+        }
+        // `async_h1` reads the next request once a response is written; it is
+        // told the connection is closed instead, to hand it back to `serve`.
+        if state.response_written() {
+            return Poll::Ready(Ok(0));
+        }
+        // Close idle connections when shutting down, as if the peer had hung up.
+        if state.phase() == Phase::Idle && state.is_closing() {
+            return Poll::Ready(Ok(0));
+        }
+
+        // Reading a request head stops just past the largest size allowed, so
+        // that a head which is still incomplete is known to be too large.
+        let reading_head = matches!(state.phase(), Phase::Idle | Phase::Head);
+        let buf = if reading_head {
+            let read = state.head_read.load(Ordering::SeqCst);
+            if read > state.max_header_size {
+                log::debug!("request head too large");
+                state.head_too_large.store(true, Ordering::SeqCst);
+                return Poll::Ready(Err(io::ErrorKind::InvalidData.into()));
+            }
+            let room = state.max_header_size + 1 - read;
+            let len = buf.len().min(room);
+            &mut buf[..len]
+        } else {
+            buf
+        };
+
+        let res = Pin::new(&mut *self.0.lock().unwrap()).poll_read(cx, buf);
+        match res {
+            Poll::Ready(Ok(n)) if n > 0 => {
+                if reading_head {
+                    state.head_read.fetch_add(n, Ordering::SeqCst);
+                }
+                match state.phase() {
+                    Phase::Idle => state.set_phase(Phase::Head),
+                    Phase::Body => state.read(),
+                    Phase::Head | Phase::Responded => {}
+                }
+            }
+            Poll::Ready(_) => {}
+            // Idle connections are closed quietly once their timeout expires.
+            // Otherwise the read fails, and whoever is reading decides what
+            // the client is told.
+            Poll::Pending => match futures::ready!(state.poll_read_deadline(cx)) {
+                None => {
+                    log::debug!("closing idle connection");
+                    return Poll::Ready(Ok(0));
+                }
+                Some(timeout) => {
+                    log::debug!("{}", timeout.error());
+                    return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+                }
+            },
+        }
+        res
+    }
+}
+
+impl<T: Transport> io::Write for WrapStream<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let res = Pin::new(&mut *self.0.lock().unwrap()).poll_write(cx, buf);
+        if res.is_pending() {
+            // A response can't be taken back once it's started, so a client
+            // too slow to receive it is hung up on.
+            futures::ready!(self.1.poll_write_deadline(cx));
+            log::debug!("timed out writing the response");
+            return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+        }
+        res
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let res = Pin::new(&mut *self.0.lock().unwrap()).poll_flush(cx);
+        // `async_h1` flushes once, after writing a whole response.
+        if let Poll::Ready(Ok(())) = res {
+            if self.1.phase() == Phase::Responded {
+                self.1.response_written.store(true, Ordering::SeqCst);
+            }
+        }
+        if res.is_pending() {
+            futures::ready!(self.1.poll_write_deadline(cx));
+            log::debug!("timed out writing the response");
+            return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+        }
+        res
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.0.lock().unwrap()).poll_close(cx)
+    }
+}
@@ -0,0 +1,314 @@
+//! Handing listening sockets over to a replacement process.
+//!
+//! The running server listens on a Unix domain socket. A replacement process
+//! connects to it, and is sent the server's listening sockets with
+//! `SCM_RIGHTS`. Once the replacement acknowledges them, the running server
+//! shuts down gracefully, while the replacement accepts new connections on the
+//! same sockets.
+
+use std::io::Write;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::io;
+use async_std::os::unix::net::UnixStream;
+use async_std::prelude::*;
+use async_std::task;
+use futures::future::{self, Either};
+use http_service_server::shutdown::Signal;
+
+use crate::{Listener, Spawn, UnixListener};
+
+/// Sent along with the sockets, to recognize a handoff of this protocol.
+const MAGIC: &[u8] = b"http-service-h1 handoff 1\n";
+
+/// Sent by the replacement once it has taken the sockets.
+const ACK: u8 = b'k';
+
+/// How long either side waits for the other during a handoff.
+const HANDOFF_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most sockets handed off at once.
+const MAX_SOCKETS: usize = 64;
+
+/// A listening socket that can be handed off.
+#[derive(Debug, Clone)]
+pub(crate) struct HandoffSocket {
+    fd: RawFd,
+    /// Set to keep the socket file of a Unix domain socket once the listener
+    /// is dropped, as the replacement is still using it.
+    keep_file: Option<Arc<AtomicBool>>,
+}
+
+impl HandoffSocket {
+    pub(crate) fn new(fd: RawFd, keep_file: Option<Arc<AtomicBool>>) -> Self {
+        Self { fd, keep_file }
+    }
+}
+
+impl Listener {
+    /// Take the listening sockets of a running server.
+    ///
+    /// Connects to the handoff socket of a server configured with
+    /// [`Server::set_handoff_path`](struct.Server.html#method.set_handoff_path),
+    /// and returns its listeners in the order that server accepted from them:
+    /// its own connections first, then each added listener. Once the sockets
+    /// are taken, the running server stops accepting connections and shuts
+    /// down gracefully, while connections keep queueing on the sockets until
+    /// this process serves them.
+    ///
+    /// TLS settings aren't handed off, and have to be set on the returned
+    /// listeners again.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::{Listener, ServerBuilder};
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let builder = ServerBuilder::new().handoff_path("/run/app-handoff.sock");
+    ///     // Take over from the previous version if it's running, or bind anew.
+    ///     let mut server = match Listener::take_over("/run/app-handoff.sock") {
+    ///         Ok(listeners) => builder.build_from_listeners(listeners, service)?,
+    ///         Err(_) => {
+    ///             let listener = builder.tcp_listener("0.0.0.0:8080".parse()?)?;
+    ///             builder.build_from_listeners(vec![listener], service)?
+    ///         }
+    ///     };
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn take_over(path: impl AsRef<Path>) -> io::Result<Vec<Listener>> {
+        let mut stream = std::os::unix::net::UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(HANDOFF_TIMEOUT))?;
+        stream.set_write_timeout(Some(HANDOFF_TIMEOUT))?;
+
+        let mut fds = recv_fds(stream.as_raw_fd())?.into_iter();
+        let mut listeners = Vec::with_capacity(fds.len());
+        while let Some(fd) = fds.next() {
+            // Safety: the descriptors were just received, and are owned by
+            // nothing else in this process.
+            match unsafe { Listener::from_raw_fd(fd) } {
+                Ok(listener) => listeners.push(listener),
+                Err(err) => {
+                    // The running server keeps serving if it gets no
+                    // acknowledgement, so don't leak the remaining sockets.
+                    for fd in std::iter::once(fd).chain(fds) {
+                        unsafe { libc::close(fd) };
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        stream.write_all(&[ACK])?;
+        Ok(listeners)
+    }
+}
+
+/// Hand `sockets` off to a process connecting to `path`, then fire `shutdown`.
+///
+/// The handoff socket is bound before returning, and served by a task until
+/// `shutdown` is fired. A failed handoff is logged, and the server keeps
+/// serving.
+pub(crate) async fn listen(
+    path: PathBuf,
+    sockets: Vec<HandoffSocket>,
+    shutdown: Signal,
+    spawner: &dyn Spawn,
+) -> io::Result<()> {
+    if sockets.len() > MAX_SOCKETS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot hand off more than {} listeners", MAX_SOCKETS),
+        ));
+    }
+    let mut listener = UnixListener::bind(&path).await?;
+    spawner.spawn(Box::pin(async move {
+        loop {
+            let stream = match future::select(shutdown.wait(), Box::pin(listener.accept())).await {
+                Either::Left(_) => return,
+                Either::Right((Ok(stream), _)) => stream,
+                Either::Right((Err(err), _)) => {
+                    log::error!("handoff: failed to accept: {}", err);
+                    continue;
+                }
+            };
+            // Stop listening, so that the replacement can bind the path for
+            // its own handoff.
+            drop(listener);
+
+            match hand_off(stream, &sockets).await {
+                Ok(()) => {
+                    log::info!("handed off {} listeners, shutting down", sockets.len());
+                    for socket in &sockets {
+                        if let Some(keep_file) = &socket.keep_file {
+                            keep_file.store(true, Ordering::SeqCst);
+                        }
+                    }
+                    shutdown.fire();
+                    return;
+                }
+                Err(err) => log::error!("handoff failed: {}", err),
+            }
+
+            listener = match UnixListener::bind(&path).await {
+                Ok(listener) => listener,
+                Err(err) => {
+                    log::error!("handoff: failed to bind {}: {}", path.display(), err);
+                    return;
+                }
+            };
+        }
+    }));
+    Ok(())
+}
+
+async fn hand_off(mut stream: UnixStream, sockets: &[HandoffSocket]) -> io::Result<()> {
+    let fds: Vec<RawFd> = sockets.iter().map(|socket| socket.fd).collect();
+    // The message is small, and the connection new, so the send buffer is
+    // only full if the peer is misbehaving.
+    let send = async {
+        loop {
+            match send_fds(stream.as_raw_fd(), &fds) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    task::sleep(Duration::from_millis(10)).await
+                }
+                res => return res,
+            }
+        }
+    };
+    io::timeout(HANDOFF_TIMEOUT, send).await?;
+
+    let mut ack = [0; 1];
+    io::timeout(HANDOFF_TIMEOUT, stream.read_exact(&mut ack)).await?;
+    if ack[0] != ACK {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected handoff acknowledgement",
+        ));
+    }
+    Ok(())
+}
+
+/// A buffer for control messages, aligned as `cmsghdr` requires.
+fn control_buffer(fds: usize) -> (Vec<u64>, usize) {
+    // Safety: CMSG_SPACE only computes a size.
+    let len = unsafe { libc::CMSG_SPACE((fds * mem::size_of::<RawFd>()) as u32) } as usize;
+    (vec![0; len.div_ceil(8)], len)
+}
+
+fn send_fds(socket: RawFd, fds: &[RawFd]) -> io::Result<()> {
+    let mut iov = libc::iovec {
+        iov_base: MAGIC.as_ptr() as *mut libc::c_void,
+        iov_len: MAGIC.len(),
+    };
+    let (mut control, control_len) = control_buffer(fds.len());
+    let data_len = mem::size_of_val(fds);
+
+    // Safety: the header points to the buffers above, which outlive the call,
+    // and the control buffer has room for a message holding `fds`.
+    let sent = unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        if !fds.is_empty() {
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = control_len as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(data_len as u32) as _;
+            std::ptr::copy_nonoverlapping(
+                fds.as_ptr() as *const u8,
+                libc::CMSG_DATA(cmsg),
+                data_len,
+            );
+        }
+        libc::sendmsg(socket, &msg, 0)
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if sent as usize != MAGIC.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::WriteZero,
+            "handoff message was cut short",
+        ));
+    }
+    Ok(())
+}
+
+fn recv_fds(socket: RawFd) -> io::Result<Vec<RawFd>> {
+    let mut buf = [0u8; 64];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let (mut control, control_len) = control_buffer(MAX_SOCKETS);
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    let flags = libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    let flags = 0;
+
+    // Safety: the header points to the buffers above, which outlive the call.
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control_len as _;
+    let received = loop {
+        let received = unsafe { libc::recvmsg(socket, &mut msg, flags) };
+        if received >= 0 {
+            break received as usize;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    };
+
+    // Safety: the kernel filled in the control messages, which stay within
+    // the control buffer.
+    let mut fds = Vec::new();
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg);
+                let len = (*cmsg).cmsg_len as usize - (data as usize - cmsg as usize);
+                for i in 0..len / mem::size_of::<RawFd>() {
+                    let fd = std::ptr::read_unaligned((data as *const RawFd).add(i));
+                    fds.push(fd);
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    let err = if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        Some("too many listeners handed off")
+    } else if &buf[..received] != MAGIC {
+        Some("not a handoff socket")
+    } else {
+        None
+    };
+    if let Some(err) = err {
+        for fd in fds {
+            unsafe { libc::close(fd) };
+        }
+        return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+    }
+    Ok(fds)
+}
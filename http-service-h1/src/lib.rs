@@ -1,37 +1,136 @@
 //! `HttpService` server that uses async-h1 as backend.
 
-#![forbid(future_incompatible, rust_2018_idioms)]
-#![deny(missing_debug_implementations, nonstandard_style)]
+#![forbid(future_incompatible)]
+#![deny(missing_debug_implementations, nonstandard_style, rust_2018_idioms)]
 #![warn(missing_docs, missing_doc_code_examples)]
 #![cfg_attr(test, deny(warnings))]
 
+use std::fmt;
 use std::future::Future;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::time::Duration;
 
-use http_service::{Error, HttpService};
+use http_service::{Error, HttpService, Response};
+use http_service_server::accept::{self, Acceptor, Stopped};
+use http_service_server::error::{
+    default_error_response, log_error, AcceptErrorHandler, ErrorHandler, ErrorResponse,
+};
+use http_service_server::shutdown::{Signal, Tracker};
 
 use async_std::io;
-use async_std::net::{SocketAddr, TcpStream};
+use async_std::net::SocketAddr;
 use async_std::prelude::*;
 use async_std::stream::Stream;
 use async_std::sync::Arc;
 
+#[cfg(unix)]
+mod activation;
+mod builder;
+mod config;
+mod conn;
+#[cfg(unix)]
+mod handoff;
+mod limit;
+mod listener;
+mod local;
+mod metrics;
+mod proxy;
+mod runtime;
+mod tcp;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(unix)]
+mod unix;
+
+pub use http_service_server::error::AcceptErrorAction;
+pub use http_service_server::shutdown::ShutdownHandle;
+pub use ipnet::IpNet;
+
+pub use builder::{ServerBuilder, SharedService};
+pub use config::ServerConfig;
+pub use http_service_server::transport::Transport;
+pub use limit::LimitAction;
+pub use listener::Listener;
+pub use local::LocalServer;
+pub use metrics::Metrics;
+pub use proxy::ProxyMode;
+#[cfg(feature = "smol")]
+pub use runtime::SmolSpawner;
+#[cfg(feature = "tokio")]
+pub use runtime::TokioSpawner;
+pub use runtime::{AsyncStdSpawner, Spawn};
+pub use tcp::TcpIncoming;
+#[cfg(feature = "tls")]
+pub use tls::{SniConfig, TlsConfig};
+#[cfg(unix)]
+pub use unix::UnixListener;
+
+use conn::{accept, ConnConfig, Timeouts};
+#[cfg(unix)]
+use handoff::HandoffSocket;
+use limit::{Limiter, Limits};
+use proxy::Proxy;
+
 /// A listening HTTP server that accepts connections in HTTP1.
-#[derive(Debug)]
 pub struct Server<I, S: HttpService> {
     incoming: I,
     service: Arc<S>,
     addr: String,
+    shutdown: Signal,
+    force_close: Signal,
+    connections: Arc<Tracker>,
+    shutdown_timeout: Duration,
+    accept_error_handler: AcceptErrorHandler,
+    max_accept_backoff: Duration,
+    error_handler: ErrorHandler,
+    error_response: ErrorResponse,
+    timeouts: Timeouts,
+    limits: Limits,
+    proxy: Proxy,
+    server_header: Option<String>,
+    max_header_size: usize,
+    listeners: Vec<Listener>,
+    metrics: Metrics,
+    spawner: Arc<dyn Spawn>,
+    #[cfg(unix)]
+    handoff_path: Option<PathBuf>,
+    #[cfg(unix)]
+    handoff: Option<HandoffSocket>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
 }
 
-impl<I: Stream<Item = io::Result<TcpStream>>, S: HttpService> Server<I, S>
+impl<I, S: HttpService> fmt::Debug for Server<I, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Server")
+            .field("addr", &self.addr)
+            .field("listeners", &self.listeners)
+            .field("shutdown", &self.shutdown)
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .field("max_accept_backoff", &self.max_accept_backoff)
+            .field("timeouts", &self.timeouts)
+            .field("limits", &self.limits)
+            .field("proxy", &self.proxy)
+            .field("server_header", &self.server_header)
+            .field("max_header_size", &self.max_header_size)
+            .finish()
+    }
+}
+
+impl<I, S, T> Server<I, S>
 where
+    S: HttpService,
     <<S as HttpService>::ResponseFuture as Future>::Output: Send,
     <S as HttpService>::Connection: Sync,
-    I: Unpin + Send + Sync,
+    I: Stream<Item = io::Result<T>> + Unpin + Send + Sync,
+    T: Transport,
 {
-    /// Consume this [`Builder`], creating a [`Server`].
+    /// Create a new server, serving the connections yielded by `incoming`.
+    ///
+    /// Connections can be of any [`Transport`](trait.Transport.html), such as
+    /// `TcpStream`.
     ///
     /// # Examples
     ///
@@ -55,55 +154,631 @@ where
     /// })?;
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     pub fn new(addr: String, incoming: I, service: S) -> Self {
+        let config = ServerConfig::default();
         Server {
             service: Arc::new(service),
             incoming,
             addr,
+            shutdown: Signal::new(),
+            force_close: Signal::new(),
+            connections: Arc::new(Tracker::default()),
+            shutdown_timeout: config.shutdown_timeout,
+            accept_error_handler: Arc::new(AcceptErrorAction::classify),
+            max_accept_backoff: config.max_accept_backoff,
+            error_handler: Arc::new(log_error),
+            error_response: Arc::new(default_error_response),
+            timeouts: config.timeouts(),
+            limits: config.limits(),
+            proxy: config.proxy(),
+            server_header: config.server_header,
+            max_header_size: config.max_header_size,
+            listeners: Vec::new(),
+            metrics: Metrics::default(),
+            spawner: Arc::new(AsyncStdSpawner),
+            #[cfg(unix)]
+            handoff_path: None,
+            #[cfg(unix)]
+            handoff: None,
+            #[cfg(feature = "tls")]
+            tls: None,
         }
     }
 
-    /// Run the server forever-ish.
-    pub async fn run(&mut self) -> io::Result<()> {
-        while let Some(stream) = self.incoming.next().await {
-            let stream = stream?;
-            async_std::task::spawn(accept(self.addr.clone(), self.service.clone(), stream));
+    /// Accept connections from another listener as well.
+    ///
+    /// All listeners share the service, shutdown, limits and metrics of the
+    /// server. Each has its own address and TLS setting; the server's own TLS
+    /// setting only applies to the connections yielded by `incoming`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::{Listener, Server, TcpIncoming};
+    /// use async_std::net::TcpListener;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("127.0.0.1:8080").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///     server.add_listener(Listener::bind("[::1]:8080".parse()?)?);
+    ///     server.add_listener(Listener::bind("127.0.0.1:8081".parse()?)?);
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn add_listener(&mut self, listener: Listener) {
+        self.listeners.push(listener);
+    }
+
+    /// Set the executor the server spawns a task onto for each connection.
+    ///
+    /// Defaults to [`AsyncStdSpawner`]. The server itself runs on whatever
+    /// executor polls [`run`](#method.run), and its timers and listeners work
+    /// on any executor; the listeners of the `tokio` and `smol` features are
+    /// the exception, and need their own runtime.
+    pub fn set_spawner(&mut self, spawner: impl Spawn) {
+        self.spawner = Arc::new(spawner);
+    }
+
+    /// Get a handle to the counters describing the server's activity.
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    /// Get a handle that can be used to shut the server down.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::Server;
+    /// use async_std::net::TcpListener;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("127.0.0.1:3000").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///
+    ///     let handle = server.shutdown_handle();
+    ///     async_std::task::spawn(async move {
+    ///         async_std::task::sleep(std::time::Duration::from_secs(60)).await;
+    ///         handle.shutdown();
+    ///     });
+    ///
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(self.shutdown.clone())
+    }
+
+    /// Set how long a shutdown waits for in-flight requests to finish.
+    ///
+    /// Connections still open when the timeout expires are closed. Defaults to
+    /// 30 seconds.
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) {
+        self.shutdown_timeout = timeout;
+    }
+
+    /// Hand the listening sockets over to a replacement process that connects
+    /// to a Unix domain socket at `path`.
+    ///
+    /// This allows upgrading the server without refusing any connections: the
+    /// new process takes the sockets with
+    /// [`Listener::take_over`](struct.Listener.html#method.take_over), and
+    /// this server then shuts down gracefully, finishing in-flight requests
+    /// while the new process accepts connections.
+    ///
+    /// Only sockets created by this crate can be handed off, such as by
+    /// [`ServerBuilder::bind`](struct.ServerBuilder.html#method.bind),
+    /// [`Listener::bind`](struct.Listener.html#method.bind) or
+    /// [`Listener::bind_unix`](struct.Listener.html#method.bind_unix); other
+    /// listeners keep being served by this server until it shuts down. The
+    /// handoff socket is bound when the server starts running.
+    #[cfg(unix)]
+    pub fn set_handoff_path(&mut self, path: impl AsRef<Path>) {
+        self.handoff_path = Some(path.as_ref().to_owned());
+    }
+
+    /// Set how long a client may take to send a request head.
+    ///
+    /// The timeout starts with the first byte of the request. When it expires,
+    /// the client is sent a `408 Request Timeout` response and the connection
+    /// is closed. The same timeout bounds the TLS handshake and the PROXY
+    /// protocol header of new connections. The timeout can't exceed 60
+    /// seconds, the longest `async-h1` waits for a request head; larger values
+    /// and `None` are capped to it. Defaults to 30 seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::Server;
+    /// use async_std::net::TcpListener;
+    /// use std::time::Duration;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("127.0.0.1:3000").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///     server.set_header_timeout(Some(Duration::from_secs(5)));
+    ///     server.set_idle_timeout(Some(Duration::from_secs(15)));
+    ///     server.set_request_timeout(Some(Duration::from_secs(60)));
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_header_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeouts.header = Timeouts::cap_header(timeout);
+    }
+
+    /// Set how long a client may pause while sending a request body.
+    ///
+    /// When the timeout expires, reading the body fails with a
+    /// [`TimedOut`](https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.TimedOut)
+    /// error. If the service returns an error, the client is sent a
+    /// `408 Request Timeout` response in its place, through the error
+    /// response callback, and the connection is closed. `None` waits forever.
+    /// Defaults to 30 seconds.
+    pub fn set_body_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeouts.body = timeout;
+    }
+
+    /// Set how long a connection may stay open without a request.
+    ///
+    /// This covers the wait for the first request, and between requests of a
+    /// keep-alive connection. When it expires, the connection is closed.
+    /// `None` waits forever. Defaults to 60 seconds.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeouts.idle = timeout;
+    }
+
+    /// Set how long a request may take, from its first byte until its
+    /// response is written.
+    ///
+    /// If the service hasn't produced a response when it expires, the service
+    /// is cancelled, and the client is sent a `503 Service Unavailable`
+    /// response through the error response callback. If the response is being
+    /// written, the connection is closed. The connection is closed after
+    /// either. `None` lets requests take as long as they need, which is the
+    /// default.
+    pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeouts.request = timeout;
+    }
+
+    /// Set the most connections served at once.
+    ///
+    /// What happens to connections over the limit is set by
+    /// [`set_connection_limit_action`](#method.set_connection_limit_action).
+    /// `None` serves any number of connections, which is the default.
+    ///
+    /// A limit of zero is refused with an `InvalidInput` error when the server
+    /// starts.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::{LimitAction, Server};
+    /// use async_std::net::TcpListener;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("127.0.0.1:3000").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///     server.set_max_connections(Some(10_000));
+    ///     server.set_max_requests_per_connection(Some(1_000));
+    ///     server.set_max_in_flight_requests(Some(512));
+    ///     server.set_request_limit_action(LimitAction::Reject);
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_max_connections(&mut self, max: Option<usize>) {
+        self.limits.connections = max;
+    }
+
+    /// Set what happens to connections over the limit.
+    ///
+    /// With [`LimitAction::Wait`], the server stops accepting until a
+    /// connection is closed, leaving new connections in the listener's
+    /// backlog. With [`LimitAction::Reject`], new connections are accepted and
+    /// closed straight away. Defaults to waiting.
+    pub fn set_connection_limit_action(&mut self, action: LimitAction) {
+        self.limits.connection_action = action;
+    }
+
+    /// Set the most requests served over a single connection.
+    ///
+    /// The last response is sent with `Connection: close`, and the connection
+    /// is closed after it. `None` serves any number of requests, which is the
+    /// default.
+    ///
+    /// A limit of zero is refused with an `InvalidInput` error when the server
+    /// starts.
+    pub fn set_max_requests_per_connection(&mut self, max: Option<usize>) {
+        self.limits.requests_per_connection = max;
+    }
+
+    /// Set the most requests handled by the service at once, across all
+    /// connections.
+    ///
+    /// A request counts against the limit until the service returns its
+    /// response. What happens to requests over the limit is set by
+    /// [`set_request_limit_action`](#method.set_request_limit_action). `None`
+    /// handles any number of requests, which is the default.
+    ///
+    /// A limit of zero is refused with an `InvalidInput` error when the server
+    /// starts.
+    pub fn set_max_in_flight_requests(&mut self, max: Option<usize>) {
+        self.limits.in_flight_requests = max;
+    }
+
+    /// Set what happens to requests over the limit.
+    ///
+    /// With [`LimitAction::Wait`], requests wait for others to finish, within
+    /// the request timeout. With [`LimitAction::Reject`], they are answered
+    /// with `503 Service Unavailable`, through the error response callback.
+    /// Defaults to waiting.
+    pub fn set_request_limit_action(&mut self, action: LimitAction) {
+        self.limits.request_action = action;
+    }
+
+    /// Set the `Server` header added to responses.
+    ///
+    /// Responses that already have a `Server` header are left unchanged.
+    /// `None` adds no header, which is the default.
+    pub fn set_server_header(&mut self, server: Option<String>) {
+        self.server_header = server;
+    }
+
+    /// Set the largest request head accepted, in bytes.
+    ///
+    /// This covers the request line and all headers. Clients sending larger
+    /// heads are sent a `431 Request Header Fields Too Large` response, and
+    /// the connection is closed. The size can't exceed the default of 8,190
+    /// bytes: `async-h1` gives up on heads of 8 KiB or more, closing the
+    /// connection without a response, so larger values are capped to keep
+    /// clients informed.
+    pub fn set_max_header_size(&mut self, size: usize) {
+        self.max_header_size = size.min(conn::MAX_HEADER_SIZE);
+    }
+
+    /// Read a PROXY protocol header at the start of each connection.
+    ///
+    /// Proxies such as HAProxy send the client's address in a header of
+    /// version 1 or 2 of the PROXY protocol, before any other data. It is then
+    /// passed to `HttpService::connect` as the connection's peer address, and
+    /// the address the client connected to as its local address, along with
+    /// any TLVs the proxy sent. On TLS listeners, the header comes before the
+    /// TLS handshake.
+    ///
+    /// In [`ProxyMode::Strict`], connections without a valid header are
+    /// closed; in [`ProxyMode::Optional`], they are served as they are.
+    /// Headers are only read from the sources set by
+    /// [`set_trusted_proxies`](#method.set_trusted_proxies), of which there
+    /// are none by default. The header must
+    /// arrive within the header timeout. `None` disables the PROXY protocol,
+    /// which is the default.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{ConnectionInfo, HttpService, Request, Response};
+    /// use http_service_h1::{ProxyMode, Server};
+    /// use async_std::net::TcpListener;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("0.0.0.0:8080").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///     server.set_proxy_protocol(Some(ProxyMode::Strict));
+    ///     server.set_trusted_proxies(vec!["10.0.0.0/8".parse()?]);
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_proxy_protocol(&mut self, mode: Option<ProxyMode>) {
+        self.proxy.mode = mode;
+    }
+
+    /// Set the sources whose PROXY protocol headers are believed.
+    ///
+    /// Connections from other sources are closed in
+    /// [`ProxyMode::Strict`], and served without reading a header in
+    /// [`ProxyMode::Optional`]. Connections without a peer address, such as
+    /// over Unix domain sockets, are always trusted. An empty list, the
+    /// default, trusts no other source.
+    pub fn set_trusted_proxies(&mut self, trusted: Vec<IpNet>) {
+        self.proxy.trusted = Arc::new(trusted);
+    }
+
+    /// Set how the server reacts to errors while accepting connections.
+    ///
+    /// Defaults to [`AcceptErrorAction::classify`]. Every error is logged,
+    /// whatever the action.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::{AcceptErrorAction, Server};
+    /// use async_std::net::TcpListener;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("127.0.0.1:3000").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///     // Never give up on the listener.
+    ///     server.set_accept_error_handler(|err| match AcceptErrorAction::classify(err) {
+    ///         AcceptErrorAction::Fatal => AcceptErrorAction::Backoff,
+    ///         action => action,
+    ///     });
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_accept_error_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&io::Error) -> AcceptErrorAction + Send + Sync + 'static,
+    {
+        self.accept_error_handler = Arc::new(handler);
+    }
+
+    /// Set the longest the server waits before accepting again after an error.
+    ///
+    /// Defaults to 1 second.
+    pub fn set_max_accept_backoff(&mut self, max: Duration) {
+        self.max_accept_backoff = max;
+    }
+
+    /// Set the callback that is told about errors returned by the service.
+    ///
+    /// This is called with every connection and response error, before it is
+    /// turned into a response. Defaults to logging the error.
+    pub fn set_error_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&Error) + Send + Sync + 'static,
+    {
+        self.error_handler = Arc::new(handler);
+    }
+
+    /// Set how errors returned by the service are turned into responses.
+    ///
+    /// Defaults to an empty response with the status code carried by the
+    /// error, which is `500 Internal Server Error` unless set otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::Server;
+    /// use async_std::net::TcpListener;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("127.0.0.1:3000").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///     server.set_error_response(|err| {
+    ///         let mut res = Response::new(err.status());
+    ///         res.set_body(format!("{} error", err.status()));
+    ///         res
+    ///     });
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_error_response<F>(&mut self, f: F)
+    where
+        F: Fn(&Error) -> Response + Send + Sync + 'static,
+    {
+        self.error_response = Arc::new(f);
+    }
+
+    /// Serve connections over TLS.
+    ///
+    /// The scheme of the server's address is changed to `https`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::{Server, TlsConfig};
+    /// use async_std::net::TcpListener;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("127.0.0.1:3000").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///     server.set_tls(TlsConfig::from_pem_files("cert.pem", "key.pem")?);
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn set_tls(&mut self, tls: TlsConfig) {
+        if let Some(rest) = self.addr.strip_prefix("http://") {
+            self.addr = format!("https://{}", rest);
         }
+        self.tls = Some(tls);
+    }
 
-        Ok(())
+    /// The listening sockets to hand off, in the order they are accepted from.
+    #[cfg(unix)]
+    fn handoff_sockets(&self) -> Vec<HandoffSocket> {
+        let primary = (&self.addr, &self.handoff);
+        let listeners = self
+            .listeners
+            .iter()
+            .map(|listener| (&listener.addr, &listener.handoff));
+        std::iter::once(primary)
+            .chain(listeners)
+            .filter_map(|(addr, socket)| {
+                if socket.is_none() {
+                    log::warn!("the listener for {} cannot be handed off", addr);
+                }
+                socket.clone()
+            })
+            .collect()
     }
-}
 
-/// Accept a new connection.
-async fn accept<S>(addr: String, service: Arc<S>, stream: TcpStream) -> Result<(), Error>
-where
-    S: HttpService,
-    <<S as HttpService>::ResponseFuture as Future>::Output: Send,
-    <S as HttpService>::Connection: Sync,
-{
-    // TODO: Delete this line when we implement `Clone` for `TcpStream`.
-    let stream = WrapStream(Arc::new(stream));
-
-    let conn = service
-        .clone()
-        .connect()
-        .await
-        .map_err(|_| io::Error::from(io::ErrorKind::Other))?;
-
-    async_h1::accept(&addr, stream.clone(), |req| async {
-        let conn = conn.clone();
-        let service = service.clone();
-        async move {
-            let res = service
-                .respond(conn, req)
-                .await
-                .map_err(|_| io::Error::from(io::ErrorKind::Other))?;
-            Ok(res)
+    /// Run the server until it is shut down.
+    ///
+    /// Once a shutdown is requested through a [`ShutdownHandle`], no new
+    /// connections are accepted. Idle connections are closed, and connections
+    /// with a request in progress are closed once the response is sent. This
+    /// method returns when all connections are closed, or when the shutdown
+    /// timeout expires.
+    ///
+    /// Errors accepting connections are handled according to the accept error
+    /// handler; only errors it deems fatal are returned, once the open
+    /// connections are closed as in a shutdown.
+    pub async fn run(&mut self) -> io::Result<()> {
+        self.limits.validate()?;
+        let config = ConnConfig {
+            addr: self.addr.clone(),
+            shutdown: self.shutdown.clone(),
+            force_close: self.force_close.clone(),
+            error_handler: self.error_handler.clone(),
+            error_response: self.error_response.clone(),
+            timeouts: self.timeouts,
+            max_requests: self.limits.requests_per_connection,
+            requests: self
+                .limits
+                .in_flight_requests
+                .map(|max| (Limiter::new(max), self.limits.request_action)),
+            server_header: self.server_header.clone(),
+            max_header_size: self.max_header_size,
+            metrics: self.metrics.clone(),
+            proxy: self.proxy.clone(),
+            #[cfg(feature = "tls")]
+            tls: self.tls.clone(),
+        };
+
+        #[cfg(unix)]
+        {
+            if let Some(path) = &self.handoff_path {
+                let sockets = self.handoff_sockets();
+                let shutdown = self.shutdown.clone();
+                handoff::listen(path.clone(), sockets, shutdown, &*self.spawner).await?;
+            }
+        }
+
+        // Connections from every listener are accepted in turn, and served
+        // with the settings of the listener they came from.
+        let config = Arc::new(config);
+        let mut incoming: Vec<Pin<Box<dyn Stream<Item = _> + Send + '_>>> = Vec::new();
+        let first = config.clone();
+        incoming.push(Box::pin((&mut self.incoming).map(move |stream| {
+            (
+                first.clone(),
+                stream.map(|stream| Box::new(stream) as Box<dyn Transport>),
+            )
+        })));
+        for listener in self.listeners.iter_mut() {
+            let config = Arc::new(ConnConfig {
+                addr: listener.addr.clone(),
+                #[cfg(feature = "tls")]
+                tls: listener.tls.clone(),
+                ..(*config).clone()
+            });
+            incoming.push(Box::pin(
+                (&mut listener.incoming).map(move |stream| (config.clone(), stream)),
+            ));
+        }
+        let incoming = futures::stream::select_all(incoming);
+
+        let acceptor = Acceptor {
+            shutdown: self.shutdown.clone(),
+            connection_limit: self.limits.connections.map(Limiter::new),
+            connection_action: self.limits.connection_action,
+            error_handler: self.accept_error_handler.clone(),
+            max_backoff: self.max_accept_backoff,
+        };
+        let (service, connections, metrics, spawner) = (
+            &self.service,
+            &self.connections,
+            &self.metrics,
+            &self.spawner,
+        );
+        let stopped = acceptor
+            .run(incoming, |stream, config, permit| {
+                let token = connections.track();
+                let connection = metrics.connection();
+                let accept = accept(service.clone(), stream, config);
+                spawner.spawn(Box::pin(async move {
+                    let _token = token;
+                    let _connection = connection;
+                    let _permit = permit;
+                    let _ = accept.await;
+                }));
+            })
+            .await;
+
+        // A fatal error shuts the server down before it is returned.
+        if stopped.is_err() {
+            self.shutdown.fire();
+        }
+        if stopped.as_ref().ok() != Some(&Stopped::Closed) {
+            accept::drain(
+                self.connections.clone(),
+                self.shutdown_timeout,
+                &self.force_close,
+            )
+            .await;
         }
-        .await
-    })
-    .await?;
 
-    Ok(())
+        stopped.map(|_| ())
+    }
+}
+
+impl<S: HttpService> Server<TcpIncoming, S> {
+    /// Get the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.incoming.local_addr()
+    }
 }
 
 /// Serve the given `HttpService` at the given address, using `async-h1` as backend, and return a
@@ -114,39 +789,48 @@ where
     <S as HttpService>::Connection: Sync,
 {
     let listener = async_std::net::TcpListener::bind(addr).await?;
-    let addr = format!("http://{}", listener.local_addr()?); // TODO: https
+    let addr = format!("http://{}", listener.local_addr()?);
     let mut server = Server::<_, S>::new(addr, listener.incoming(), service);
 
     server.run().await
 }
 
-#[derive(Clone)]
-struct WrapStream(Arc<TcpStream>);
+/// Serve the given `HttpService` over HTTPS at the given address, using `async-h1` as backend,
+/// and return a `Future` that can be `await`ed on.
+#[cfg(feature = "tls")]
+pub async fn serve_tls<S: HttpService>(
+    service: S,
+    addr: SocketAddr,
+    tls: TlsConfig,
+) -> io::Result<()>
+where
+    <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+    <S as HttpService>::Connection: Sync,
+{
+    let listener = async_std::net::TcpListener::bind(addr).await?;
+    let addr = format!("https://{}", listener.local_addr()?);
+    let mut server = Server::<_, S>::new(addr, listener.incoming(), service);
+    server.set_tls(tls);
 
-impl io::Read for WrapStream {
-    fn poll_read(
-        self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &mut [u8],
-    ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut &*self.0).poll_read(cx, buf)
-    }
+    server.run().await
 }
 
-impl io::Write for WrapStream {
-    fn poll_write(
-        self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &[u8],
-    ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut &*self.0).poll_write(cx, buf)
-    }
-
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut &*self.0).poll_flush(cx)
-    }
+/// Serve the given `HttpService` on a Unix domain socket at the given path, using `async-h1` as
+/// backend, and return a `Future` that can be `await`ed on.
+///
+/// A stale socket file at the path is replaced, and the socket file is removed when the server
+/// stops. See [`UnixListener`](struct.UnixListener.html) to set the permissions of the socket.
+#[cfg(unix)]
+pub async fn serve_unix<S: HttpService>(
+    service: S,
+    path: impl AsRef<std::path::Path>,
+) -> io::Result<()>
+where
+    <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+    <S as HttpService>::Connection: Sync,
+{
+    let listener = UnixListener::bind(path).await?;
+    let mut server = Server::<_, S>::new("http://localhost".into(), listener.incoming(), service);
 
-    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut &*self.0).poll_close(cx)
-    }
+    server.run().await
 }
@@ -0,0 +1,82 @@
+//! Limits on connections and requests.
+
+use async_std::io;
+
+pub use http_service_server::limit::LimitAction;
+pub(crate) use http_service_server::limit::Limiter;
+
+/// The limits applied by a server.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Limits {
+    pub(crate) connections: Option<usize>,
+    pub(crate) connection_action: LimitAction,
+    pub(crate) requests_per_connection: Option<usize>,
+    pub(crate) in_flight_requests: Option<usize>,
+    pub(crate) request_action: LimitAction,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            connections: None,
+            connection_action: LimitAction::Wait,
+            requests_per_connection: None,
+            in_flight_requests: None,
+            request_action: LimitAction::Wait,
+        }
+    }
+}
+
+impl Limits {
+    /// Refuse limits of zero, which would never let anything through.
+    pub(crate) fn validate(&self) -> io::Result<()> {
+        let limits = [
+            ("connections", self.connections),
+            ("requests per connection", self.requests_per_connection),
+            ("in-flight requests", self.in_flight_requests),
+        ];
+        for (name, max) in limits.iter() {
+            if *max == Some(0) {
+                let msg = format!("the limit on {} must be at least 1", name);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_zero_limits() {
+        assert!(Limits::default().validate().is_ok());
+        let one = Limits {
+            connections: Some(1),
+            requests_per_connection: Some(1),
+            in_flight_requests: Some(1),
+            ..Limits::default()
+        };
+        assert!(one.validate().is_ok());
+
+        let zeros = [
+            Limits {
+                connections: Some(0),
+                ..one
+            },
+            Limits {
+                requests_per_connection: Some(0),
+                ..one
+            },
+            Limits {
+                in_flight_requests: Some(0),
+                ..one
+            },
+        ];
+        for limits in zeros.iter() {
+            let err = limits.validate().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Listening on several addresses at once.
+
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use async_std::io;
+use async_std::net::SocketAddr;
+use async_std::stream::Stream;
+use futures::stream::StreamExt;
+
+#[cfg(unix)]
+use crate::handoff::HandoffSocket;
+#[cfg(feature = "tls")]
+use crate::TlsConfig;
+use crate::{TcpIncoming, Transport};
+
+pub(crate) type BoxIncoming =
+    Pin<Box<dyn Stream<Item = io::Result<Box<dyn Transport>>> + Send + Sync>>;
+
+/// An additional source of connections for a [`Server`](struct.Server.html).
+///
+/// Added with [`Server::add_listener`](struct.Server.html#method.add_listener).
+/// A listener can yield any kind of [`Transport`](trait.Transport.html), and
+/// has its own address and TLS setting. A server can also be built from
+/// listeners alone, with
+/// [`ServerBuilder::build_from_listeners`](struct.ServerBuilder.html#method.build_from_listeners).
+///
+/// # Examples
+///
+/// ```no_run
+/// use http_service::{Request, Response};
+/// use http_service_h1::{Listener, Server};
+/// use async_std::net::TcpListener;
+///
+/// let service = |_req: Request| async {
+///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+/// };
+///
+/// async_std::task::block_on(async move {
+///     let listener = TcpListener::bind("0.0.0.0:8080").await?;
+///     let addr = format!("http://{}", listener.local_addr()?);
+///     let mut server = Server::new(addr, listener.incoming(), service);
+///     server.add_listener(Listener::bind("[::]:8080".parse()?)?);
+///     server.add_listener(Listener::bind_unix("/run/app.sock").await?);
+///     server.run().await?;
+///     Ok::<(), Box<dyn std::error::Error>>(())
+/// })?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Listener {
+    pub(crate) addr: String,
+    pub(crate) incoming: BoxIncoming,
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<TlsConfig>,
+    /// The listening socket, if it can be handed off to a replacement process.
+    #[cfg(unix)]
+    pub(crate) handoff: Option<HandoffSocket>,
+}
+
+impl Listener {
+    /// Create a listener serving the connections yielded by `incoming`.
+    ///
+    /// `addr` is the base URL of requests that carry no `Host` header.
+    pub fn new<I, T>(addr: String, incoming: I) -> Self
+    where
+        I: Stream<Item = io::Result<T>> + Send + Sync + 'static,
+        T: Transport,
+    {
+        let incoming =
+            incoming.map(|stream| stream.map(|stream| Box::new(stream) as Box<dyn Transport>));
+        Self {
+            addr,
+            incoming: Box::pin(incoming),
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(unix)]
+            handoff: None,
+        }
+    }
+
+    /// Create a listener bound to the given TCP address.
+    ///
+    /// See [`ServerBuilder::tcp_listener`](struct.ServerBuilder.html#method.tcp_listener)
+    /// to set TCP options.
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Ok(Self::tcp(TcpIncoming::bind(addr, false, false, None)?))
+    }
+
+    /// Create a listener bound to the given Unix domain socket path.
+    ///
+    /// The socket file is managed as by [`UnixListener`](struct.UnixListener.html).
+    #[cfg(unix)]
+    pub async fn bind_unix(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let listener = crate::UnixListener::bind(path).await?;
+        let handoff = listener.handoff_socket();
+        let mut listener = Self::new("http://localhost".into(), listener.into_incoming());
+        listener.handoff = Some(handoff);
+        Ok(listener)
+    }
+
+    pub(crate) fn tcp(incoming: TcpIncoming) -> Self {
+        #[cfg(unix)]
+        let handoff = HandoffSocket::new(std::os::unix::io::AsRawFd::as_raw_fd(&incoming), None);
+        #[allow(unused_mut)]
+        let mut listener = Self::new(format!("http://{}", incoming.local_addr()), incoming);
+        #[cfg(unix)]
+        {
+            listener.handoff = Some(handoff);
+        }
+        listener
+    }
+
+    /// Get the base URL of requests that carry no `Host` header.
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Serve this listener's connections over TLS.
+    ///
+    /// The scheme of the listener's address is changed to `https`.
+    #[cfg(feature = "tls")]
+    pub fn set_tls(&mut self, tls: TlsConfig) {
+        if let Some(rest) = self.addr.strip_prefix("http://") {
+            self.addr = format!("https://{}", rest);
+        }
+        self.tls = Some(tls);
+    }
+}
+
+impl Stream for Listener {
+    type Item = io::Result<Box<dyn Transport>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.incoming.as_mut().poll_next(cx)
+    }
+}
+
+impl fmt::Debug for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("Listener");
+        f.field("addr", &self.addr);
+        #[cfg(feature = "tls")]
+        f.field("tls", &self.tls.is_some());
+        f.finish()
+    }
+}
+
+/// Makes a stream `Sync`, as a server requires, by locking it when polled.
+pub(crate) struct SyncStream<S>(Mutex<Pin<Box<S>>>);
+
+impl<S: Stream> SyncStream<S> {
+    pub(crate) fn new(stream: S) -> Self {
+        SyncStream(Mutex::new(Box::pin(stream)))
+    }
+}
+
+impl<S: Stream> Stream for SyncStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        self.0.lock().unwrap().as_mut().poll_next(cx)
+    }
+}
@@ -0,0 +1,235 @@
+//! Serving connections with a thread per core.
+
+use std::fmt;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use async_std::io;
+use async_std::net::SocketAddr;
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use futures::executor::LocalPool;
+use futures::task::LocalSpawnExt;
+use http_service::LocalHttpService;
+use http_service_server::accept::{self, Acceptor, Stopped};
+use http_service_server::shutdown::{Signal, Tracker};
+
+use crate::conn::{accept, ConnConfig};
+use crate::{Metrics, ShutdownHandle, TcpIncoming};
+
+/// A server running on a thread per core.
+///
+/// Each worker thread has a listener of its own, bound to the same address
+/// with `SO_REUSEPORT` so that the kernel spreads connections among them, and
+/// runs a single-threaded executor. A connection stays on the worker that
+/// accepted it, with no work stealing between workers.
+///
+/// Created by [`ServerBuilder::bind_workers`](struct.ServerBuilder.html#method.bind_workers),
+/// where all workers share one `HttpService`, or by
+/// [`ServerBuilder::build_local`](struct.ServerBuilder.html#method.build_local),
+/// where each worker creates its own instance of a [`LocalHttpService`],
+/// which doesn't need to be `Send`. Either way, the server is configured with
+/// the builder's settings, except for its spawner and handoff path.
+///
+/// The workers share the shutdown, limits and metrics of the server.
+///
+/// [`LocalHttpService`]: ../http_service/trait.LocalHttpService.html
+///
+/// # Examples
+///
+/// ```no_run
+/// use http_service::{local_service_fn, Request, Response};
+/// use http_service_h1::ServerBuilder;
+/// use std::cell::Cell;
+/// use std::rc::Rc;
+///
+/// let server = ServerBuilder::new()
+///     .workers(Some(4))
+///     .build_local("127.0.0.1:8080".parse()?, || {
+///         // Each worker counts the requests it serves.
+///         let hits = Rc::new(Cell::new(0));
+///         local_service_fn(move |_req: Request| {
+///             let hits = hits.clone();
+///             async move {
+///                 hits.set(hits.get() + 1);
+///                 let body = format!("request {} on this worker", hits.get());
+///                 Ok::<_, http_service::Error>(Response::from(body))
+///             }
+///         })
+///     })?;
+/// server.run()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct LocalServer<F> {
+    make_service: Arc<F>,
+    listeners: Vec<TcpIncoming>,
+    config: ConnConfig,
+    acceptor: Acceptor,
+    shutdown_timeout: Duration,
+}
+
+impl<F, S> LocalServer<F>
+where
+    F: Fn() -> S + Send + Sync + 'static,
+    S: LocalHttpService,
+{
+    pub(crate) fn new(
+        make_service: F,
+        listeners: Vec<TcpIncoming>,
+        config: ConnConfig,
+        acceptor: Acceptor,
+        shutdown_timeout: Duration,
+    ) -> Self {
+        Self {
+            make_service: Arc::new(make_service),
+            listeners,
+            config,
+            acceptor,
+            shutdown_timeout,
+        }
+    }
+
+    /// Get the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listeners[0].local_addr()
+    }
+
+    /// The number of worker threads.
+    pub fn workers(&self) -> usize {
+        self.listeners.len()
+    }
+
+    /// Get a handle to the counters describing the activity of all workers.
+    pub fn metrics(&self) -> Metrics {
+        self.config.metrics.clone()
+    }
+
+    /// Get a handle that can be used to shut down all workers.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(self.acceptor.shutdown.clone())
+    }
+
+    /// Run the workers until the server is shut down, blocking the current
+    /// thread.
+    ///
+    /// Shutting down works as for [`Server::run`](struct.Server.html#method.run),
+    /// with each worker waiting for its own connections to close. A worker
+    /// that stops with an error, or panics, shuts down the others; the first
+    /// error is returned, and a panic is passed on once all workers stopped.
+    pub fn run(self) -> io::Result<()> {
+        let shutdown = self.acceptor.shutdown.clone();
+        let mut workers = Vec::with_capacity(self.listeners.len());
+        let mut result = Ok(());
+        for (i, incoming) in self.listeners.into_iter().enumerate() {
+            let make_service = self.make_service.clone();
+            // Each worker closes its own connections once its shutdown
+            // timeout expires.
+            let config = ConnConfig {
+                force_close: Signal::new(),
+                ..self.config.clone()
+            };
+            let acceptor = self.acceptor.clone();
+            let shutdown_timeout = self.shutdown_timeout;
+            let stop_others = StopOnExit(shutdown.clone());
+            let worker = thread::Builder::new()
+                .name(format!("http-worker-{}", i))
+                .spawn(move || {
+                    let _stop_others = stop_others;
+                    let service = make_service();
+                    run_worker(service, incoming, config, acceptor, shutdown_timeout)
+                });
+            match worker {
+                Ok(worker) => workers.push(worker),
+                Err(err) => {
+                    shutdown.fire();
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+
+        let mut panic = None;
+        for worker in workers {
+            match worker.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+                Err(err) => panic = Some(err),
+            }
+        }
+        if let Some(panic) = panic {
+            std::panic::resume_unwind(panic);
+        }
+        result
+    }
+}
+
+impl<F> fmt::Debug for LocalServer<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalServer")
+            .field("addr", &self.config.addr)
+            .field("workers", &self.listeners.len())
+            .field("shutdown", &self.acceptor.shutdown)
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .finish()
+    }
+}
+
+/// Shuts down the other workers once a worker stops, for whatever reason.
+struct StopOnExit(Signal);
+
+impl Drop for StopOnExit {
+    fn drop(&mut self) {
+        self.0.fire();
+    }
+}
+
+/// Serve the connections of one listener on the current thread.
+fn run_worker<S: LocalHttpService>(
+    service: S,
+    incoming: TcpIncoming,
+    config: ConnConfig,
+    acceptor: Acceptor,
+    shutdown_timeout: Duration,
+) -> io::Result<()> {
+    let mut pool = LocalPool::new();
+    let spawner = pool.spawner();
+    // The service never leaves this thread, and is only shared with the
+    // connections it serves.
+    let service = Rc::new(service);
+    let config = Arc::new(config);
+    let connections = Arc::new(Tracker::default());
+
+    pool.run_until(async {
+        let incoming = incoming.map(|stream| (config.clone(), stream));
+        let stopped = acceptor
+            .run(incoming, |stream, config, permit| {
+                let token = connections.track();
+                let connection = config.metrics.connection();
+                let accept = accept(service.clone(), stream, config);
+                let task = spawner.spawn_local(async move {
+                    let _token = token;
+                    let _connection = connection;
+                    let _permit = permit;
+                    let _ = accept.await;
+                });
+                if let Err(err) = task {
+                    log::error!("error spawning connection task: {}", err);
+                }
+            })
+            .await;
+
+        // A fatal error shuts every worker down before it is returned.
+        if stopped.is_err() {
+            acceptor.shutdown.fire();
+        }
+        if stopped.as_ref().ok() != Some(&Stopped::Closed) {
+            accept::drain(connections.clone(), shutdown_timeout, &config.force_close).await;
+        }
+        stopped.map(|_| ())
+    })
+}
@@ -0,0 +1,110 @@
+//! Counters describing a server's activity.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Counters describing the activity of a [`Server`](struct.Server.html).
+///
+/// Created by [`Server::metrics`](struct.Server.html#method.metrics). The
+/// counters cover every listener of the server, and handles can be cloned and
+/// read from other tasks at any time.
+///
+/// # Examples
+///
+/// ```no_run
+/// use http_service::{Request, Response};
+/// use http_service_h1::Server;
+/// use async_std::net::TcpListener;
+///
+/// let service = |_req: Request| async {
+///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+/// };
+///
+/// async_std::task::block_on(async move {
+///     let listener = TcpListener::bind("127.0.0.1:3000").await?;
+///     let addr = format!("http://{}", listener.local_addr()?);
+///     let mut server = Server::new(addr, listener.incoming(), service);
+///
+///     let metrics = server.metrics();
+///     async_std::task::spawn(async move {
+///         loop {
+///             async_std::task::sleep(std::time::Duration::from_secs(10)).await;
+///             println!("{} connections open", metrics.connections_open());
+///         }
+///     });
+///
+///     server.run().await?;
+///     Ok::<(), Box<dyn std::error::Error>>(())
+/// })?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    connections_accepted: AtomicU64,
+    connections_open: AtomicUsize,
+    requests: AtomicU64,
+    requests_in_flight: AtomicUsize,
+}
+
+impl Metrics {
+    /// The number of connections accepted since the server was created.
+    pub fn connections_accepted(&self) -> u64 {
+        self.counters.connections_accepted.load(Ordering::Relaxed)
+    }
+
+    /// The number of connections currently open.
+    pub fn connections_open(&self) -> usize {
+        self.counters.connections_open.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests received since the server was created.
+    pub fn requests(&self) -> u64 {
+        self.counters.requests.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests currently handled by the service.
+    pub fn requests_in_flight(&self) -> usize {
+        self.counters.requests_in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Count a new connection, which stays open until the guard is dropped.
+    pub(crate) fn connection(&self) -> ConnectionGuard {
+        let counters = &self.counters;
+        counters
+            .connections_accepted
+            .fetch_add(1, Ordering::Relaxed);
+        counters.connections_open.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard(counters.clone())
+    }
+
+    /// Count a new request, which is in flight until the guard is dropped.
+    pub(crate) fn request(&self) -> RequestGuard {
+        let counters = &self.counters;
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+        counters.requests_in_flight.fetch_add(1, Ordering::Relaxed);
+        RequestGuard(counters.clone())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ConnectionGuard(Arc<Counters>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.connections_open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RequestGuard(Arc<Counters>);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.0.requests_in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,664 @@
+//! The PROXY protocol, which passes on the client's address through a proxy.
+//!
+//! See <https://www.haproxy.org/download/2.0/doc/proxy-protocol.txt>.
+
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_std::io::{self, Read, Write};
+use async_std::prelude::*;
+use http_service::{ConnectionInfo, ProxyTlv};
+use http_service_server::error::invalid_data;
+use ipnet::IpNet;
+
+use crate::Transport;
+
+/// The start of a version 1 header.
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+/// The longest version 1 header, including the line ending.
+const V1_MAX_LEN: usize = 107;
+
+/// The start of a version 2 header.
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The length of a version 2 header before its addresses.
+const V2_HEADER_LEN: usize = 16;
+
+/// Whether connections must start with a PROXY protocol header.
+///
+/// Set with [`Server::set_proxy_protocol`](struct.Server.html#method.set_proxy_protocol).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum ProxyMode {
+    /// Close connections that don't start with a header, and connections
+    /// from untrusted sources.
+    Strict,
+    /// Read a header if the connection starts with one. Connections from
+    /// untrusted sources are served as they are.
+    Optional,
+}
+
+/// The PROXY protocol settings of a server.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Proxy {
+    pub(crate) mode: Option<ProxyMode>,
+    pub(crate) trusted: Arc<Vec<IpNet>>,
+}
+
+impl Proxy {
+    /// Whether a header from the given peer is believed.
+    ///
+    /// No peer is trusted unless listed. Connections without a peer address,
+    /// such as over Unix domain sockets, are local, and trusted.
+    fn trusts(&self, peer: Option<SocketAddr>) -> bool {
+        match peer {
+            Some(peer) => {
+                let ip = match peer.ip() {
+                    IpAddr::V6(ip) => ip
+                        .to_ipv4_mapped()
+                        .map(IpAddr::V4)
+                        .unwrap_or(IpAddr::V6(ip)),
+                    ip => ip,
+                };
+                self.trusted.iter().any(|net| net.contains(&ip))
+            }
+            None => true,
+        }
+    }
+}
+
+/// Read the PROXY protocol header a connection starts with.
+pub(crate) async fn accept<T: Transport>(
+    mut stream: T,
+    mode: ProxyMode,
+    proxy: &Proxy,
+    timeout: Option<Duration>,
+) -> io::Result<ProxyStream<T>> {
+    let mut info = stream.connection_info();
+    let (header, buf) = if proxy.trusts(info.peer_addr()) {
+        let read = read_header(&mut stream, mode);
+        match timeout {
+            Some(timeout) => io::timeout(timeout, read).await?,
+            None => read.await?,
+        }
+    } else if mode == ProxyMode::Strict {
+        return Err(invalid_data("PROXY protocol header from untrusted source"));
+    } else {
+        (None, Vec::new())
+    };
+
+    if let Some(header) = header {
+        if let Some(source) = header.source {
+            info.set_peer_addr(Some(source));
+        }
+        if let Some(destination) = header.destination {
+            info.set_local_addr(Some(destination));
+        }
+        info.set_proxy_tlvs(header.tlvs);
+    }
+    Ok(ProxyStream {
+        inner: stream,
+        buf,
+        pos: 0,
+        info,
+    })
+}
+
+/// A connection after its PROXY protocol header, with the client's
+/// connection information.
+#[derive(Debug)]
+pub(crate) struct ProxyStream<T> {
+    inner: T,
+    /// What was read past the header.
+    buf: Vec<u8>,
+    pos: usize,
+    info: ConnectionInfo,
+}
+
+impl<T: Transport> Read for ProxyStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.pos < this.buf.len() {
+            let n = buf.len().min(this.buf.len() - this.pos);
+            buf[..n].copy_from_slice(&this.buf[this.pos..this.pos + n]);
+            this.pos += n;
+            return Poll::Ready(Ok(n));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: Transport> Write for ProxyStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+impl<T: Transport> Transport for ProxyStream<T> {
+    fn connection_info(&self) -> ConnectionInfo {
+        self.info.clone()
+    }
+}
+
+/// What a PROXY protocol header says about the client's connection.
+#[derive(Debug, Default)]
+struct Header {
+    source: Option<SocketAddr>,
+    destination: Option<SocketAddr>,
+    tlvs: Vec<ProxyTlv>,
+}
+
+/// Read a header, returning it along with whatever was read past it.
+async fn read_header<T: Transport>(
+    stream: &mut T,
+    mode: ProxyMode,
+) -> io::Result<(Option<Header>, Vec<u8>)> {
+    let mut buf = Vec::new();
+    loop {
+        if buf.starts_with(V2_SIGNATURE) && buf.len() >= V2_HEADER_LEN {
+            let len = V2_HEADER_LEN + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+            if buf.len() >= len {
+                let header = parse_v2(&buf[..len])?;
+                return Ok((Some(header), buf.split_off(len)));
+            }
+        } else if buf.starts_with(V1_PREFIX) {
+            let line = &buf[..buf.len().min(V1_MAX_LEN)];
+            if let Some(end) = line.windows(2).position(|w| w == b"\r\n") {
+                let header = parse_v1(&buf[..end])?;
+                return Ok((Some(header), buf.split_off(end + 2)));
+            }
+            if buf.len() >= V1_MAX_LEN {
+                return Err(invalid_data("PROXY protocol header too long"));
+            }
+        } else if !is_prefix(&buf, V2_SIGNATURE) && !is_prefix(&buf, V1_PREFIX) {
+            return match mode {
+                ProxyMode::Strict => Err(invalid_data("missing PROXY protocol header")),
+                ProxyMode::Optional => Ok((None, buf)),
+            };
+        }
+
+        let mut chunk = [0; 256];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Whether `buf` could still be the start of `prefix`.
+fn is_prefix(buf: &[u8], prefix: &[u8]) -> bool {
+    let len = buf.len().min(prefix.len());
+    buf[..len] == prefix[..len]
+}
+
+/// Parse a version 1 header, without its line ending.
+fn parse_v1(line: &[u8]) -> io::Result<Header> {
+    let invalid = || invalid_data("invalid PROXY protocol header");
+    let line = std::str::from_utf8(line).map_err(|_| invalid())?;
+    let mut parts = line.split(' ').skip(1);
+    let family = parts.next().ok_or_else(invalid)?;
+    if family == "UNKNOWN" {
+        return Ok(Header::default());
+    }
+    let mut addrs = [""; 4];
+    for addr in &mut addrs {
+        *addr = parts.next().ok_or_else(invalid)?;
+    }
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    let (source, destination): (IpAddr, IpAddr) = match family {
+        "TCP4" => (
+            addrs[0].parse::<Ipv4Addr>().map_err(|_| invalid())?.into(),
+            addrs[1].parse::<Ipv4Addr>().map_err(|_| invalid())?.into(),
+        ),
+        "TCP6" => (
+            addrs[0].parse::<Ipv6Addr>().map_err(|_| invalid())?.into(),
+            addrs[1].parse::<Ipv6Addr>().map_err(|_| invalid())?.into(),
+        ),
+        _ => return Err(invalid()),
+    };
+    let source_port = addrs[2].parse().map_err(|_| invalid())?;
+    let destination_port = addrs[3].parse().map_err(|_| invalid())?;
+    Ok(Header {
+        source: Some(SocketAddr::new(source, source_port)),
+        destination: Some(SocketAddr::new(destination, destination_port)),
+        tlvs: Vec::new(),
+    })
+}
+
+/// Parse a version 2 header.
+fn parse_v2(buf: &[u8]) -> io::Result<Header> {
+    let invalid = || invalid_data("invalid PROXY protocol header");
+    let version = buf[12] >> 4;
+    let command = buf[12] & 0x0f;
+    let family = buf[13] >> 4;
+    let body = &buf[V2_HEADER_LEN..];
+    if version != 2 {
+        return Err(invalid());
+    }
+    match command {
+        // LOCAL: the proxy connected on its own behalf, such as for a health
+        // check, and the connection's own addresses apply.
+        0x0 => return Ok(Header::default()),
+        0x1 => {}
+        _ => return Err(invalid()),
+    }
+
+    let (source, destination, rest) = match family {
+        // AF_UNSPEC
+        0x0 => (None, None, body),
+        // AF_INET
+        0x1 if body.len() >= 12 => {
+            let ip = |at: usize| IpAddr::from(<[u8; 4]>::try_from(&body[at..at + 4]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+            (
+                Some(SocketAddr::new(ip(0), port(8))),
+                Some(SocketAddr::new(ip(4), port(10))),
+                &body[12..],
+            )
+        }
+        // AF_INET6
+        0x2 if body.len() >= 36 => {
+            let ip = |at: usize| IpAddr::from(<[u8; 16]>::try_from(&body[at..at + 16]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+            (
+                Some(SocketAddr::new(ip(0), port(32))),
+                Some(SocketAddr::new(ip(16), port(34))),
+                &body[36..],
+            )
+        }
+        // AF_UNIX, whose paths don't fit in a `SocketAddr`.
+        0x3 if body.len() >= 216 => (None, None, &body[216..]),
+        _ => return Err(invalid()),
+    };
+
+    let mut tlvs = Vec::new();
+    let mut rest = rest;
+    while !rest.is_empty() {
+        if rest.len() < 3 {
+            return Err(invalid());
+        }
+        let len = u16::from_be_bytes([rest[1], rest[2]]) as usize;
+        let value = rest.get(3..3 + len).ok_or_else(invalid)?;
+        tlvs.push(ProxyTlv::new(rest[0], value.to_vec()));
+        rest = &rest[3 + len..];
+    }
+    Ok(Header {
+        source,
+        destination,
+        tlvs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    const PEER: &str = "10.0.0.2:1234";
+    const LOCAL: &str = "10.0.0.1:80";
+
+    /// A connection from a proxy, read in chunks of the given size.
+    struct Mock {
+        data: Vec<u8>,
+        pos: usize,
+        chunk: usize,
+    }
+
+    impl Read for Mock {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let n = buf.len().min(self.chunk).min(self.data.len() - self.pos);
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    impl Write for Mock {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl Transport for Mock {
+        fn connection_info(&self) -> ConnectionInfo {
+            let mut info = ConnectionInfo::new();
+            info.set_peer_addr(Some(PEER.parse().unwrap()));
+            info.set_local_addr(Some(LOCAL.parse().unwrap()));
+            info
+        }
+    }
+
+    /// Accept a connection from a trusted proxy, returning its information
+    /// and everything read after the header, whether the connection is read
+    /// at once or a byte at a time.
+    fn accept_all(data: &[u8], mode: ProxyMode) -> io::Result<(ConnectionInfo, Vec<u8>)> {
+        let proxy = Proxy {
+            mode: Some(mode),
+            trusted: Arc::new(vec!["10.0.0.0/8".parse().unwrap()]),
+        };
+        accept_from(data, mode, &proxy)
+    }
+
+    fn accept_from(
+        data: &[u8],
+        mode: ProxyMode,
+        proxy: &Proxy,
+    ) -> io::Result<(ConnectionInfo, Vec<u8>)> {
+        let mut results = [1, 4096].iter().map(|&chunk| {
+            let mock = Mock {
+                data: data.to_vec(),
+                pos: 0,
+                chunk,
+            };
+            block_on(async {
+                let mut stream = accept(mock, mode, proxy, None).await?;
+                let mut rest = Vec::new();
+                stream.read_to_end(&mut rest).await?;
+                Ok((stream.connection_info(), rest))
+            })
+        });
+        let first: io::Result<(ConnectionInfo, Vec<u8>)> = results.next().unwrap();
+        let second = results.next().unwrap();
+        match (&first, &second) {
+            (Ok((a, rest_a)), Ok((b, rest_b))) => {
+                assert_eq!(a.peer_addr(), b.peer_addr());
+                assert_eq!(a.local_addr(), b.local_addr());
+                assert_eq!(rest_a, rest_b);
+            }
+            (Err(a), Err(b)) => assert_eq!(a.kind(), b.kind()),
+            _ => panic!("{:?} != {:?}", first.is_ok(), second.is_ok()),
+        }
+        first
+    }
+
+    fn addrs(info: &ConnectionInfo) -> (String, String) {
+        (
+            info.peer_addr().unwrap().to_string(),
+            info.local_addr().unwrap().to_string(),
+        )
+    }
+
+    fn own_addrs() -> (String, String) {
+        (PEER.to_string(), LOCAL.to_string())
+    }
+
+    /// A version 2 header with the given command, family and body.
+    fn v2(command: u8, family: u8, body: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family << 4 | 0x1);
+        header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        header.extend_from_slice(body);
+        header
+    }
+
+    fn tlv(kind: u8, value: &[u8]) -> Vec<u8> {
+        let mut tlv = vec![kind];
+        tlv.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        tlv.extend_from_slice(value);
+        tlv
+    }
+
+    #[test]
+    fn parses_v1_headers() {
+        let (info, rest) = accept_all(
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET / HTTP/1.1\r\n",
+            ProxyMode::Strict,
+        )
+        .unwrap();
+        assert_eq!(
+            addrs(&info),
+            ("192.0.2.1:56324".into(), "198.51.100.1:443".into())
+        );
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n");
+
+        let (info, rest) = accept_all(
+            b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n",
+            ProxyMode::Strict,
+        )
+        .unwrap();
+        assert_eq!(
+            addrs(&info),
+            ("[2001:db8::1]:56324".into(), "[2001:db8::2]:443".into())
+        );
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_connection_addresses_for_v1_unknown() {
+        for header in &[
+            &b"PROXY UNKNOWN\r\nGET"[..],
+            &b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\nGET"[..],
+        ] {
+            let (info, rest) = accept_all(header, ProxyMode::Strict).unwrap();
+            assert_eq!(addrs(&info), own_addrs());
+            assert_eq!(rest, b"GET");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_v1_headers() {
+        for header in &[
+            &b"PROXY TCP5 192.0.2.1 198.51.100.1 1 2\r\n"[..],
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 1\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 1 2 3\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 1 65536\r\n",
+            b"PROXY TCP4 2001:db8::1 198.51.100.1 1 2\r\n",
+            b"PROXY TCP6 192.0.2.1 2001:db8::2 1 2\r\n",
+        ] {
+            let err = accept_all(header, ProxyMode::Optional).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        let mut long = b"PROXY UNKNOWN ".to_vec();
+        long.resize(200, b'x');
+        let err = accept_all(&long, ProxyMode::Optional).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parses_v2_headers() {
+        let mut body = vec![192, 0, 2, 1, 198, 51, 100, 1];
+        body.extend_from_slice(&56324u16.to_be_bytes());
+        body.extend_from_slice(&443u16.to_be_bytes());
+        body.extend(tlv(0x01, b"h2"));
+        body.extend(tlv(0x04, b""));
+        let mut data = v2(0x1, 0x1, &body);
+        data.extend_from_slice(b"GET / HTTP/1.1\r\n");
+
+        let (info, rest) = accept_all(&data, ProxyMode::Strict).unwrap();
+        assert_eq!(
+            addrs(&info),
+            ("192.0.2.1:56324".into(), "198.51.100.1:443".into())
+        );
+        let tlvs: Vec<_> = info
+            .proxy_tlvs()
+            .iter()
+            .map(|tlv| (tlv.kind(), tlv.value().to_vec()))
+            .collect();
+        assert_eq!(tlvs, vec![(0x01, b"h2".to_vec()), (0x04, vec![])]);
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n");
+
+        let mut body = "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+        body.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        body.extend_from_slice(&56324u16.to_be_bytes());
+        body.extend_from_slice(&443u16.to_be_bytes());
+        let (info, rest) = accept_all(&v2(0x1, 0x2, &body), ProxyMode::Strict).unwrap();
+        assert_eq!(
+            addrs(&info),
+            ("[2001:db8::1]:56324".into(), "[2001:db8::2]:443".into())
+        );
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_connection_addresses_without_v2_addresses() {
+        // LOCAL, with addresses that are ignored.
+        let local = v2(0x0, 0x1, &[192, 0, 2, 1, 198, 51, 100, 1, 0, 1, 0, 2]);
+        // PROXY over AF_UNSPEC.
+        let unspec = v2(0x1, 0x0, &tlv(0x04, b"x"));
+        for data in &[local, unspec] {
+            let mut data = data.clone();
+            data.extend_from_slice(b"GET");
+            let (info, rest) = accept_all(&data, ProxyMode::Strict).unwrap();
+            assert_eq!(addrs(&info), own_addrs());
+            assert_eq!(rest, b"GET");
+        }
+    }
+
+    #[test]
+    fn skips_v2_unix_addresses() {
+        let mut body = vec![0; 216];
+        body[..9].copy_from_slice(b"/tmp/a.so");
+        body[108..117].copy_from_slice(b"/tmp/b.so");
+        body.extend(tlv(0x02, b"example.com"));
+        let mut data = v2(0x1, 0x3, &body);
+        data.extend_from_slice(b"GET");
+
+        let (info, rest) = accept_all(&data, ProxyMode::Strict).unwrap();
+        assert_eq!(addrs(&info), own_addrs());
+        assert_eq!(info.proxy_tlvs().len(), 1);
+        assert_eq!(info.proxy_tlvs()[0].value(), b"example.com");
+        assert_eq!(rest, b"GET");
+
+        let short = v2(0x1, 0x3, &[0; 215]);
+        let err = accept_all(&short, ProxyMode::Strict).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_invalid_v2_headers() {
+        let addrs = [192, 0, 2, 1, 198, 51, 100, 1, 0, 1, 0, 2];
+        let truncated_value = {
+            let mut body = addrs.to_vec();
+            body.extend_from_slice(&[0x01, 0x00, 0x05, b'h', b'2']);
+            v2(0x1, 0x1, &body)
+        };
+        let truncated_tlv = {
+            let mut body = addrs.to_vec();
+            body.extend_from_slice(&[0x01, 0x00]);
+            v2(0x1, 0x1, &body)
+        };
+        let short_addrs = v2(0x1, 0x1, &addrs[..11]);
+        let bad_command = v2(0x2, 0x1, &addrs);
+        let bad_family = v2(0x1, 0x4, &addrs);
+        let bad_version = {
+            let mut data = v2(0x1, 0x1, &addrs);
+            data[12] = 0x11;
+            data
+        };
+        for data in &[
+            truncated_value,
+            truncated_tlv,
+            short_addrs,
+            bad_command,
+            bad_family,
+            bad_version,
+        ] {
+            let err = accept_all(data, ProxyMode::Strict).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn passes_on_connections_without_a_header_when_optional() {
+        for data in &[
+            &b"GET / HTTP/1.1\r\n"[..],
+            b"PROXIED",
+            b"\r\n\r\n\0\r\nQUIZ",
+        ] {
+            let (info, rest) = accept_all(data, ProxyMode::Optional).unwrap();
+            assert_eq!(addrs(&info), own_addrs());
+            assert_eq!(rest, *data);
+
+            let err = accept_all(data, ProxyMode::Strict).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn fails_on_connections_closed_within_the_header() {
+        for data in &[
+            &b"PROX"[..],
+            b"PROXY TCP4 192.0.2.1",
+            b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c",
+        ] {
+            let err = accept_all(data, ProxyMode::Optional).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn ignores_headers_from_untrusted_sources() {
+        let proxy = Proxy {
+            mode: Some(ProxyMode::Optional),
+            trusted: Arc::new(vec!["192.0.2.0/24".parse().unwrap()]),
+        };
+        let data = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET";
+
+        let (info, rest) = accept_from(data, ProxyMode::Optional, &proxy).unwrap();
+        assert_eq!(addrs(&info), own_addrs());
+        assert_eq!(rest, &data[..]);
+
+        let err = accept_from(data, ProxyMode::Strict, &proxy).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn trusts_no_source_by_default() {
+        let proxy = Proxy::default();
+        assert!(!proxy.trusts(Some(PEER.parse().unwrap())));
+        assert!(proxy.trusts(None));
+
+        let data = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET";
+        let (info, rest) = accept_from(data, ProxyMode::Optional, &proxy).unwrap();
+        assert_eq!(addrs(&info), own_addrs());
+        assert_eq!(rest, &data[..]);
+        let err = accept_from(data, ProxyMode::Strict, &proxy).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
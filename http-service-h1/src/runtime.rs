@@ -0,0 +1,241 @@
+//! Running a server on different executors.
+
+use futures::future::BoxFuture;
+
+/// Spawns the tasks of a [`Server`](struct.Server.html) onto an executor.
+///
+/// A server spawns a task for each connection it accepts. Set with
+/// [`Server::set_spawner`](struct.Server.html#method.set_spawner); defaults to
+/// [`AsyncStdSpawner`](struct.AsyncStdSpawner.html). With the `tokio` and
+/// `smol` features, [`TokioSpawner`](struct.TokioSpawner.html) and
+/// [`SmolSpawner`](struct.SmolSpawner.html) spawn onto those executors.
+///
+/// Closures taking the task are spawners too.
+///
+/// # Examples
+///
+/// ```no_run
+/// use http_service::{Request, Response};
+/// use http_service_h1::ServerBuilder;
+///
+/// let service = |_req: Request| async {
+///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+/// };
+///
+/// // Serve each connection on a thread of its own.
+/// futures::executor::block_on(async move {
+///     let mut server = ServerBuilder::new()
+///         .spawner(|task| {
+///             std::thread::spawn(move || futures::executor::block_on(task));
+///         })
+///         .bind("127.0.0.1:8080".parse()?, service)?;
+///     server.run().await?;
+///     Ok::<(), Box<dyn std::error::Error>>(())
+/// })?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait Spawn: Send + Sync + 'static {
+    /// Spawn a task, which runs to completion in the background.
+    fn spawn(&self, task: BoxFuture<'static, ()>);
+}
+
+impl<F> Spawn for F
+where
+    F: Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+{
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        (self)(task)
+    }
+}
+
+/// Spawns tasks onto the global async-std executor.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdSpawner;
+
+impl Spawn for AsyncStdSpawner {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        async_std::task::spawn(task);
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod tokio_rt {
+    use async_std::io;
+    use tokio::net::TcpListener;
+    use tokio::runtime::Handle;
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    use super::*;
+    use crate::listener::SyncStream;
+    use crate::Listener;
+
+    /// Spawns tasks onto a tokio runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::{Listener, ServerBuilder, TokioSpawner};
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// let runtime = tokio::runtime::Builder::new_current_thread()
+    ///     .enable_all()
+    ///     .build()?;
+    /// runtime.block_on(async move {
+    ///     let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
+    ///     let mut server = ServerBuilder::new()
+    ///         .spawner(TokioSpawner::current())
+    ///         .build_from_listeners(vec![Listener::from_tokio(listener)?], service)?;
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct TokioSpawner {
+        handle: Handle,
+    }
+
+    impl TokioSpawner {
+        /// Spawn onto the runtime of the given handle.
+        pub fn new(handle: Handle) -> Self {
+            Self { handle }
+        }
+
+        /// Spawn onto the runtime this is called from.
+        ///
+        /// # Panics
+        ///
+        /// Panics if called outside of a tokio runtime.
+        pub fn current() -> Self {
+            Self::new(Handle::current())
+        }
+    }
+
+    impl Spawn for TokioSpawner {
+        fn spawn(&self, task: BoxFuture<'static, ()>) {
+            self.handle.spawn(task);
+        }
+    }
+
+    impl Listener {
+        /// Create a listener serving the connections of a tokio TCP listener.
+        ///
+        /// The listener must be used from within a tokio runtime, such as with
+        /// a [`TokioSpawner`](struct.TokioSpawner.html).
+        pub fn from_tokio(listener: TcpListener) -> io::Result<Self> {
+            let addr = format!("http://{}", listener.local_addr()?);
+            #[cfg(unix)]
+            let handoff = crate::handoff::HandoffSocket::new(
+                std::os::unix::io::AsRawFd::as_raw_fd(&listener),
+                None,
+            );
+            let incoming = futures::stream::unfold(listener, |listener| async move {
+                let stream = listener.accept().await.map(|(stream, _)| stream.compat());
+                Some((stream, listener))
+            });
+            #[allow(unused_mut)]
+            let mut listener = Self::new(addr, SyncStream::new(incoming));
+            #[cfg(unix)]
+            {
+                listener.handoff = Some(handoff);
+            }
+            Ok(listener)
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use tokio_rt::TokioSpawner;
+
+#[cfg(feature = "smol")]
+mod smol_rt {
+    use std::sync::Arc;
+
+    use async_std::io;
+    use smol::net::TcpListener;
+    use smol::Executor;
+
+    use super::*;
+    use crate::listener::SyncStream;
+    use crate::Listener;
+
+    /// Spawns tasks onto a smol executor.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h1::{Listener, ServerBuilder, SmolSpawner};
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// smol::block_on(async move {
+    ///     let listener = smol::net::TcpListener::bind("127.0.0.1:8080").await?;
+    ///     let mut server = ServerBuilder::new()
+    ///         .spawner(SmolSpawner::new())
+    ///         .build_from_listeners(vec![Listener::from_smol(listener)?], service)?;
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[derive(Debug, Clone, Default)]
+    pub struct SmolSpawner {
+        executor: Option<Arc<Executor<'static>>>,
+    }
+
+    impl SmolSpawner {
+        /// Spawn onto smol's global executor.
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Spawn onto the given executor.
+        pub fn with_executor(executor: Arc<Executor<'static>>) -> Self {
+            Self {
+                executor: Some(executor),
+            }
+        }
+    }
+
+    impl Spawn for SmolSpawner {
+        fn spawn(&self, task: BoxFuture<'static, ()>) {
+            match &self.executor {
+                Some(executor) => executor.spawn(task).detach(),
+                None => smol::spawn(task).detach(),
+            }
+        }
+    }
+
+    impl Listener {
+        /// Create a listener serving the connections of a smol TCP listener.
+        pub fn from_smol(listener: TcpListener) -> io::Result<Self> {
+            let addr = format!("http://{}", listener.local_addr()?);
+            #[cfg(unix)]
+            let handoff = crate::handoff::HandoffSocket::new(
+                std::os::unix::io::AsRawFd::as_raw_fd(&listener),
+                None,
+            );
+            let incoming = futures::stream::unfold(listener, |listener| async move {
+                let stream = listener.accept().await.map(|(stream, _)| stream);
+                Some((stream, listener))
+            });
+            #[allow(unused_mut)]
+            let mut listener = Self::new(addr, SyncStream::new(incoming));
+            #[cfg(unix)]
+            {
+                listener.handoff = Some(handoff);
+            }
+            Ok(listener)
+        }
+    }
+}
+
+#[cfg(feature = "smol")]
+pub use smol_rt::SmolSpawner;
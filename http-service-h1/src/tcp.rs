@@ -0,0 +1,174 @@
+//! TCP listeners with socket options.
+
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_std::io;
+use async_std::net::{SocketAddr, TcpListener, TcpStream};
+use async_std::stream::Stream;
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+
+/// The most connections waiting to be accepted.
+const BACKLOG: i32 = 1024;
+
+type Accept = Pin<Box<dyn Stream<Item = io::Result<TcpStream>> + Send>>;
+
+/// The connections accepted by a TCP listener, with socket options applied.
+///
+/// Created by [`ServerBuilder::bind`](struct.ServerBuilder.html#method.bind).
+pub struct TcpIncoming {
+    accept: Mutex<Accept>,
+    local_addr: SocketAddr,
+    nodelay: bool,
+    keepalive: Option<Duration>,
+    #[cfg(unix)]
+    fd: std::os::unix::io::RawFd,
+}
+
+impl TcpIncoming {
+    /// Bind a listener to the given address.
+    pub(crate) fn bind(
+        addr: SocketAddr,
+        reuse_port: bool,
+        nodelay: bool,
+        keepalive: Option<Duration>,
+    ) -> io::Result<Self> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        // Like the standard library, allow binding while old connections to
+        // the address linger.
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        if reuse_port {
+            set_reuse_port(&socket)?;
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(BACKLOG)?;
+        Self::from_std(socket.into(), nodelay, keepalive)
+    }
+
+    /// Accept connections from an existing listener.
+    pub(crate) fn from_std(
+        listener: std::net::TcpListener,
+        nodelay: bool,
+        keepalive: Option<Duration>,
+    ) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        Ok(Self::from_nonblocking(
+            listener, local_addr, nodelay, keepalive,
+        ))
+    }
+
+    /// Accept connections from a non-blocking listener bound to
+    /// `local_addr`.
+    ///
+    /// Unlike `from_std`, this can't fail, so the listener is never dropped
+    /// before it is served.
+    pub(crate) fn from_nonblocking(
+        listener: std::net::TcpListener,
+        local_addr: SocketAddr,
+        nodelay: bool,
+        keepalive: Option<Duration>,
+    ) -> Self {
+        let listener = TcpListener::from(listener);
+        #[cfg(unix)]
+        let fd = std::os::unix::io::AsRawFd::as_raw_fd(&listener);
+
+        let accept = futures::stream::unfold(listener, |listener| async move {
+            let stream = listener.accept().await.map(|(stream, _)| stream);
+            Some((stream, listener))
+        });
+        Self {
+            accept: Mutex::new(Box::pin(accept)),
+            local_addr,
+            nodelay,
+            keepalive,
+            #[cfg(unix)]
+            fd,
+        }
+    }
+
+    /// Get the address the listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Apply the socket options to an accepted connection.
+    ///
+    /// A connection whose options can't be set is still served.
+    fn configure(&self, stream: &TcpStream) {
+        if self.nodelay {
+            if let Err(err) = stream.set_nodelay(true) {
+                log::debug!("error setting TCP_NODELAY: {}", err);
+            }
+        }
+        if let Some(time) = self.keepalive {
+            if let Err(err) = set_keepalive(stream, time) {
+                log::debug!("error setting TCP keepalive: {}", err);
+            }
+        }
+    }
+}
+
+impl Stream for TcpIncoming {
+    type Item = io::Result<TcpStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let next = self.accept.lock().unwrap().as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(stream))) = &next {
+            self.configure(stream);
+        }
+        next
+    }
+}
+
+#[cfg(unix)]
+impl std::os::unix::io::AsRawFd for TcpIncoming {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        self.fd
+    }
+}
+
+impl fmt::Debug for TcpIncoming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpIncoming")
+            .field("local_addr", &self.local_addr)
+            .field("nodelay", &self.nodelay)
+            .field("keepalive", &self.keepalive)
+            .finish()
+    }
+}
+
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+fn set_reuse_port(_socket: &Socket) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SO_REUSEPORT is not supported on this platform",
+    ))
+}
+
+#[cfg(unix)]
+fn set_keepalive(stream: &TcpStream, time: Duration) -> io::Result<()> {
+    use std::os::unix::io::{AsRawFd, BorrowedFd};
+
+    // Safety: the descriptor stays open for as long as the stream is borrowed.
+    let fd = unsafe { BorrowedFd::borrow_raw(stream.as_raw_fd()) };
+    SockRef::from(&fd).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))
+}
+
+#[cfg(windows)]
+fn set_keepalive(stream: &TcpStream, time: Duration) -> io::Result<()> {
+    use std::os::windows::io::{AsRawSocket, BorrowedSocket};
+
+    // Safety: the socket stays open for as long as the stream is borrowed.
+    let socket = unsafe { BorrowedSocket::borrow_raw(stream.as_raw_socket()) };
+    SockRef::from(&socket).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))
+}
@@ -0,0 +1,154 @@
+//! TLS support, using rustls.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_std::io;
+use futures_rustls::rustls::crypto::ring;
+use futures_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use futures_rustls::rustls::sign::CertifiedKey;
+use futures_rustls::rustls::ServerConfig;
+use futures_rustls::TlsAcceptor;
+use http_service_server::error::invalid_data;
+use http_service_server::tls::parse_pem;
+
+/// TLS settings for a [`Server`](struct.Server.html).
+///
+/// # Examples
+///
+/// ```no_run
+/// use http_service_h1::TlsConfig;
+///
+/// let tls = TlsConfig::from_pem_files("cert.pem", "key.pem")?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct TlsConfig {
+    acceptor: TlsAcceptor,
+}
+
+impl TlsConfig {
+    /// Use a single certificate chain and private key, read from PEM files.
+    pub fn from_pem_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> io::Result<Self> {
+        let cert = std::fs::read(cert)?;
+        let key = std::fs::read(key)?;
+        Self::from_pem(&cert, &key)
+    }
+
+    /// Use a single certificate chain and private key, in PEM format.
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> io::Result<Self> {
+        let mut sni = SniConfig::new();
+        sni.set_default_pem(cert, key)?;
+        sni.build()
+    }
+
+    /// Use an existing rustls configuration.
+    ///
+    /// The configuration should advertise `http/1.1` through ALPN.
+    pub fn from_rustls(config: Arc<ServerConfig>) -> Self {
+        Self {
+            acceptor: TlsAcceptor::from(config),
+        }
+    }
+
+    pub(crate) fn acceptor(&self) -> &TlsAcceptor {
+        &self.acceptor
+    }
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConfig").finish()
+    }
+}
+
+/// Builds a [`TlsConfig`](struct.TlsConfig.html) that picks a certificate
+/// based on the server name the client asks for (SNI).
+///
+/// # Examples
+///
+/// ```no_run
+/// use http_service_h1::SniConfig;
+///
+/// let mut sni = SniConfig::new();
+/// sni.add_pem_files("example.com", "example.com.pem", "example.com.key")?;
+/// sni.add_pem_files("example.org", "example.org.pem", "example.org.key")?;
+/// let tls = sni.build()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct SniConfig {
+    resolver: SniResolver,
+}
+
+impl SniConfig {
+    /// Create a new `SniConfig` with no certificates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the certificate chain and private key for a server name, in PEM
+    /// format.
+    pub fn add_pem(&mut self, name: &str, cert: &[u8], key: &[u8]) -> io::Result<&mut Self> {
+        let key = certified_key(cert, key)?;
+        self.resolver.by_name.insert(name.to_ascii_lowercase(), key);
+        Ok(self)
+    }
+
+    /// Add the certificate chain and private key for a server name, read
+    /// from PEM files.
+    pub fn add_pem_files(
+        &mut self,
+        name: &str,
+        cert: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+    ) -> io::Result<&mut Self> {
+        let cert = std::fs::read(cert)?;
+        let key = std::fs::read(key)?;
+        self.add_pem(name, &cert, &key)
+    }
+
+    /// Set the certificate chain and private key used when the client sends
+    /// no server name, or one without a certificate of its own.
+    ///
+    /// Without a default, such handshakes are refused.
+    pub fn set_default_pem(&mut self, cert: &[u8], key: &[u8]) -> io::Result<&mut Self> {
+        self.resolver.default = Some(certified_key(cert, key)?);
+        Ok(self)
+    }
+
+    /// Create the `TlsConfig`.
+    pub fn build(self) -> io::Result<TlsConfig> {
+        let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_data)?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(self.resolver));
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(TlsConfig::from_rustls(Arc::new(config)))
+    }
+}
+
+#[derive(Debug, Default)]
+struct SniResolver {
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+    default: Option<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        client_hello
+            .server_name()
+            .and_then(|name| self.by_name.get(&name.to_ascii_lowercase()))
+            .or(self.default.as_ref())
+            .cloned()
+    }
+}
+
+fn certified_key(cert: &[u8], key: &[u8]) -> io::Result<Arc<CertifiedKey>> {
+    let (certs, key) = parse_pem(cert, key)?;
+    let key = ring::sign::any_supported_type(&key).map_err(invalid_data)?;
+    Ok(Arc::new(CertifiedKey::new(certs, key)))
+}
@@ -0,0 +1,153 @@
+//! Unix domain sockets.
+
+use std::fs::{self, Permissions};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use async_std::io;
+use async_std::os::unix::net::{self, Incoming, UnixStream};
+use async_std::stream::Stream;
+
+use crate::handoff::HandoffSocket;
+use crate::listener::SyncStream;
+
+/// A Unix domain socket listener that manages its socket file.
+///
+/// Binding removes a stale socket file left behind by a previous process, and
+/// dropping the listener removes the socket file again.
+///
+/// # Examples
+///
+/// ```no_run
+/// use http_service::{Request, Response};
+/// use http_service_h1::{Server, UnixListener};
+///
+/// let service = |_req: Request| async {
+///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+/// };
+///
+/// async_std::task::block_on(async move {
+///     let listener = UnixListener::bind("/run/app.sock").await?;
+///     listener.set_permissions(0o660)?;
+///     let mut server = Server::new("http://localhost".into(), listener.incoming(), service);
+///     server.run().await?;
+///     Ok::<(), Box<dyn std::error::Error>>(())
+/// })?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct UnixListener {
+    listener: net::UnixListener,
+    path: PathBuf,
+    /// The device and inode of the socket file, to tell it apart from a file
+    /// that has since replaced it.
+    id: (u64, u64),
+    /// Set once the socket has been handed off to a replacement process,
+    /// which still serves the socket file.
+    keep_file: Arc<AtomicBool>,
+}
+
+impl UnixListener {
+    /// Create a listener bound to the given path.
+    ///
+    /// If a socket file already exists at the path, but no process accepts
+    /// connections on it, it is removed first. Fails with `AddrInUse` if the
+    /// socket is still in use, or if the path is some other kind of file.
+    pub async fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        remove_stale(path).await?;
+        let listener = net::UnixListener::bind(path).await?;
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            listener,
+            path: path.to_owned(),
+            id: (metadata.dev(), metadata.ino()),
+            keep_file: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Set the permissions of the socket file, such as `0o660`.
+    ///
+    /// Connecting to a Unix domain socket requires write permission on its
+    /// file. Until this is called the file has the default permissions of the
+    /// process, as set by its umask.
+    pub fn set_permissions(&self, mode: u32) -> io::Result<()> {
+        fs::set_permissions(&self.path, Permissions::from_mode(mode))
+    }
+
+    /// Get the path the listener is bound to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get a stream of incoming connections, to pass to
+    /// [`Server::new`](struct.Server.html#method.new).
+    pub fn incoming(&self) -> Incoming<'_> {
+        self.listener.incoming()
+    }
+
+    /// Accept a single connection.
+    pub(crate) async fn accept(&self) -> io::Result<UnixStream> {
+        self.listener.accept().await.map(|(stream, _)| stream)
+    }
+
+    /// The socket, for handing it off to a replacement process.
+    pub(crate) fn handoff_socket(&self) -> HandoffSocket {
+        HandoffSocket::new(self.listener.as_raw_fd(), Some(self.keep_file.clone()))
+    }
+
+    /// Turn the listener into a stream of incoming connections, which owns
+    /// it.
+    pub(crate) fn into_incoming(self) -> SyncStream<impl Stream<Item = io::Result<UnixStream>>> {
+        SyncStream::new(futures::stream::unfold(self, |listener| async move {
+            let stream = listener.listener.accept().await.map(|(stream, _)| stream);
+            Some((stream, listener))
+        }))
+    }
+}
+
+impl Drop for UnixListener {
+    fn drop(&mut self) {
+        if self.keep_file.load(Ordering::SeqCst) {
+            return;
+        }
+        // Leave the file alone if another listener has been bound in its place.
+        match fs::symlink_metadata(&self.path) {
+            Ok(metadata) if (metadata.dev(), metadata.ino()) == self.id => {
+                if let Err(err) = fs::remove_file(&self.path) {
+                    log::warn!("failed to remove {}: {}", self.path.display(), err);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Remove the socket file at `path` if nothing is listening on it.
+async fn remove_stale(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+    match UnixStream::connect(path).await {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is in use by another process", path.display()),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+            log::debug!("removing stale socket {}", path.display());
+            fs::remove_file(path)
+        }
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,45 @@
+//! Stopping a server on a fatal error accepting connections.
+
+use std::time::Duration;
+
+use async_std::io;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use futures::channel::mpsc;
+use http_service::{Request, Response};
+use http_service_h1::Server;
+
+#[test]
+fn closes_connections_before_returning_a_fatal_error() {
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, incoming) = mpsc::unbounded::<io::Result<TcpStream>>();
+        let service =
+            |_req: Request| async { Ok::<_, http_service::Error>(Response::from("hello")) };
+        let mut server = Server::new(format!("http://{}", addr), incoming, service);
+        server.set_shutdown_timeout(Duration::from_secs(30));
+        let run = task::spawn(async move { server.run().await });
+
+        // An idle keep-alive connection is open when the listener fails.
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        sender.unbounded_send(Ok(stream)).unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 1024];
+        let n = client.read(&mut buf).await.unwrap();
+        assert!(buf[..n].starts_with(b"HTTP/1.1 200 OK\r\n"));
+
+        // The connection is closed by the time the error is returned.
+        let err = io::Error::new(io::ErrorKind::InvalidInput, "listener closed");
+        sender.unbounded_send(Err(err)).unwrap();
+        let err = run.await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let read = io::timeout(Duration::from_secs(1), client.read(&mut buf));
+        assert_eq!(read.await.unwrap(), 0);
+    });
+}
@@ -0,0 +1,203 @@
+//! Serving listening sockets inherited by a child process.
+#![cfg(unix)]
+
+use std::env;
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use async_std::task;
+use http_service::{Request, Response};
+use http_service_h1::{Listener, ServerBuilder};
+
+/// Set in the environment of the child process, to the way it takes its
+/// listening socket.
+const CHILD: &str = "HTTP_SERVICE_H1_TEST_CHILD";
+
+/// The descriptor the child finds its listening socket at.
+const CHILD_FD: RawFd = 3;
+
+/// Serve the inherited sockets when run as a child process, and do nothing
+/// otherwise.
+#[test]
+fn child() {
+    let listeners = match env::var(CHILD).as_deref() {
+        Ok("systemd") => Listener::from_systemd().unwrap(),
+        Ok("fd") => vec![unsafe { Listener::from_raw_fd(CHILD_FD) }.unwrap()],
+        _ => return,
+    };
+    println!("listeners: {}", listeners.len());
+    if listeners.is_empty() {
+        return;
+    }
+
+    let pid = std::process::id();
+    let service = move |_req: Request| async move {
+        Ok::<_, http_service::Error>(Response::from(format!("served by {}", pid)))
+    };
+    let mut server = ServerBuilder::new()
+        .build_from_listeners(listeners, service)
+        .unwrap();
+    task::block_on(server.run()).unwrap();
+}
+
+/// Run the `child` test in a child process, with `fd` as its descriptor 3.
+///
+/// Like systemd, the shell sets `LISTEN_PID` to the pid of the process it
+/// runs, unless it is already set.
+fn spawn(mode: &str, fd: RawFd, envs: &[(&str, &str)]) -> Child {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(r#"export LISTEN_PID="${LISTEN_PID:-$$}"; exec "$@""#)
+        .arg("sh")
+        .arg(env::current_exe().unwrap())
+        .args(["child", "--exact", "--nocapture", "--test-threads=1"])
+        .env(CHILD, mode)
+        .envs(envs.iter().copied())
+        .stdout(Stdio::piped());
+    // Safety: only async-signal-safe functions are called.
+    unsafe {
+        command.pre_exec(move || {
+            // The descriptor is inherited once it is no longer closed on exec.
+            let ok = if fd == CHILD_FD {
+                libc::fcntl(fd, libc::F_SETFD, 0) != -1
+            } else {
+                libc::dup2(fd, CHILD_FD) != -1
+            };
+            if ok {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        });
+    }
+    command.spawn().unwrap()
+}
+
+/// Send a request, returning the response body.
+fn get(mut stream: impl Read + Write) -> String {
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut res = Vec::new();
+    let mut buf = [0; 1024];
+    loop {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(
+            n > 0,
+            "connection closed: {:?}",
+            String::from_utf8_lossy(&res)
+        );
+        res.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&res).into_owned();
+        if let Some(end) = text.find("\r\n\r\n") {
+            let len: usize = text[..end]
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length: "))
+                .unwrap()
+                .parse()
+                .unwrap();
+            if res.len() >= end + 4 + len {
+                assert!(text.starts_with("HTTP/1.1 200 OK\r\n"), "{}", text);
+                return text[end + 4..].to_owned();
+            }
+        }
+    }
+}
+
+fn stop(mut child: Child) {
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn serves_an_inherited_tcp_listener() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let child = spawn("fd", listener.as_raw_fd(), &[]);
+
+    // Connections queue up on the socket until the child accepts them.
+    let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    assert_eq!(get(stream), format!("served by {}", child.id()));
+    stop(child);
+}
+
+#[test]
+fn serves_an_inherited_unix_listener() {
+    let path = env::temp_dir().join(format!("http-service-h1-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let child = spawn("fd", listener.as_raw_fd(), &[]);
+
+    let stream = UnixStream::connect(&path).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    assert_eq!(get(stream), format!("served by {}", child.id()));
+    stop(child);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn serves_systemd_sockets() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let child = spawn("systemd", listener.as_raw_fd(), &[("LISTEN_FDS", "1")]);
+
+    let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    assert_eq!(get(stream), format!("served by {}", child.id()));
+    stop(child);
+}
+
+#[test]
+fn ignores_systemd_sockets_meant_for_another_process() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let child = spawn(
+        "systemd",
+        listener.as_raw_fd(),
+        &[("LISTEN_FDS", "1"), ("LISTEN_PID", "1")],
+    );
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("listeners: 0"));
+}
+
+#[test]
+fn leaves_unusable_descriptors_open() {
+    let is_open = |fd| unsafe { libc::fcntl(fd, libc::F_GETFD) } != -1;
+
+    let udp = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .into_raw_fd();
+    assert!(unsafe { Listener::from_raw_fd(udp) }.is_err());
+    assert!(is_open(udp));
+
+    let socket = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::STREAM, None).unwrap();
+    socket
+        .bind(
+            &"127.0.0.1:0"
+                .parse::<std::net::SocketAddr>()
+                .unwrap()
+                .into(),
+        )
+        .unwrap();
+    let not_listening = socket.into_raw_fd();
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    {
+        assert!(unsafe { Listener::from_raw_fd(not_listening) }.is_err());
+    }
+    assert!(is_open(not_listening));
+
+    unsafe {
+        libc::close(udp);
+        libc::close(not_listening);
+    }
+}
@@ -0,0 +1,141 @@
+//! Turning errors returned by services into responses.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_std::io;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::task;
+use futures::future::BoxFuture;
+use http_service::{ConnectionInfo, Error, HttpService, Request, Response};
+use http_service_h1::ServerBuilder;
+use http_types::StatusCode;
+
+/// Start a server, returning its address.
+fn start<S>(builder: ServerBuilder, service: S) -> SocketAddr
+where
+    S: HttpService,
+    S::Connection: Sync,
+{
+    let mut server = builder
+        .bind("127.0.0.1:0".parse().unwrap(), service)
+        .unwrap();
+    let addr = server.local_addr();
+    task::spawn(async move { server.run().await });
+    addr
+}
+
+/// Answer every request with a `418 I'm a teapot` error.
+async fn teapot(_req: Request) -> Result<Response, Error> {
+    Err(Error::from_str(StatusCode::ImATeapot, "short and stout"))
+}
+
+/// Read a single response, whose body has a `Content-Length`.
+async fn read_response(stream: &mut TcpStream) -> String {
+    let mut res = Vec::new();
+    let mut byte = [0; 1];
+    while !res.ends_with(b"\r\n\r\n") {
+        assert_eq!(
+            stream.read(&mut byte).await.unwrap(),
+            1,
+            "connection closed"
+        );
+        res.push(byte[0]);
+    }
+    let head = String::from_utf8(res).unwrap().to_lowercase();
+    let len: usize = head
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length: "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await.unwrap();
+    head + &String::from_utf8(body).unwrap()
+}
+
+/// Read everything until the server closes the connection.
+async fn read_to_close(stream: &mut TcpStream) -> String {
+    let mut res = Vec::new();
+    io::timeout(Duration::from_secs(5), stream.read_to_end(&mut res))
+        .await
+        .unwrap();
+    String::from_utf8(res).unwrap().to_lowercase()
+}
+
+const GET: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+#[test]
+fn answers_service_errors_with_their_status() {
+    task::block_on(async {
+        let addr = start(ServerBuilder::new(), teapot);
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // The connection is kept open after an error response.
+        for _ in 0..2 {
+            stream.write_all(GET).await.unwrap();
+            let res = read_response(&mut stream).await;
+            assert!(res.starts_with("http/1.1 418 "), "{}", res);
+            assert!(!res.contains("\r\nconnection: close\r\n"), "{}", res);
+        }
+    });
+}
+
+#[test]
+fn passes_errors_to_the_configured_handlers() {
+    task::block_on(async {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let builder = ServerBuilder::new()
+            .error_handler({
+                let errors = errors.clone();
+                move |err| errors.lock().unwrap().push(err.to_string())
+            })
+            .error_response(|err| {
+                let mut res = Response::new(StatusCode::BadGateway);
+                res.set_body(format!("{} error", err.status()));
+                res
+            });
+        let addr = start(builder, teapot);
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(GET).await.unwrap();
+
+        let res = read_response(&mut stream).await;
+        assert!(res.starts_with("http/1.1 502 bad gateway\r\n"), "{}", res);
+        assert!(res.ends_with("\r\n\r\n418 error"), "{}", res);
+        assert_eq!(*errors.lock().unwrap(), ["short and stout"]);
+    });
+}
+
+/// A service refusing every connection.
+struct Refuse;
+
+impl HttpService for Refuse {
+    type Connection = ();
+    type ConnectionError = Error;
+    type ConnectionFuture = BoxFuture<'static, Result<(), Error>>;
+    type ResponseError = Error;
+    type ResponseFuture = BoxFuture<'static, Result<Response, Error>>;
+
+    fn connect(&self, _info: &ConnectionInfo) -> Self::ConnectionFuture {
+        Box::pin(async { Err(Error::from_str(StatusCode::Forbidden, "go away")) })
+    }
+
+    fn respond(&self, _conn: (), _req: Request) -> Self::ResponseFuture {
+        unreachable!("the connection was refused")
+    }
+}
+
+#[test]
+fn closes_connections_the_service_refuses() {
+    task::block_on(async {
+        let addr = start(ServerBuilder::new(), Refuse);
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(GET).await.unwrap();
+
+        let res = read_to_close(&mut stream).await;
+        assert!(res.starts_with("http/1.1 403 forbidden\r\n"), "{}", res);
+        assert!(res.contains("\r\nconnection: close\r\n"), "{}", res);
+    });
+}
@@ -0,0 +1,133 @@
+//! Handing listening sockets over to a replacement server.
+#![cfg(unix)]
+
+use std::env;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use async_std::io;
+use async_std::task;
+use http_service::{Request, Response};
+use http_service_h1::{Listener, ServerBuilder};
+
+/// A path for the handoff socket of a test, in the temporary directory.
+fn socket_path(test: &str) -> PathBuf {
+    let name = format!("http-service-h1-{}-{}.sock", test, std::process::id());
+    let path = env::temp_dir().join(name);
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Answer every request with `name`.
+fn named(name: &'static str) -> impl Fn(Request) -> futures::future::Ready<io::Result<Response>> {
+    move |_req| futures::future::ready(Ok(Response::from(name)))
+}
+
+/// Take the listeners of the server with a handoff socket at `path`, retrying
+/// until it listens.
+fn take_over(path: &Path) -> Vec<Listener> {
+    let start = Instant::now();
+    loop {
+        match Listener::take_over(path) {
+            Ok(listeners) => return listeners,
+            Err(err) if start.elapsed() > Duration::from_secs(5) => panic!("{}", err),
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+}
+
+/// Send a request on a new connection, returning the response body.
+fn get(addr: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut res = Vec::new();
+    let mut buf = [0; 1024];
+    loop {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(
+            n > 0,
+            "connection closed: {:?}",
+            String::from_utf8_lossy(&res)
+        );
+        res.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&res).into_owned();
+        if let Some(end) = text.find("\r\n\r\n") {
+            let len: usize = text[..end]
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length: "))
+                .unwrap()
+                .parse()
+                .unwrap();
+            if res.len() >= end + 4 + len {
+                assert!(text.starts_with("HTTP/1.1 200 OK\r\n"), "{}", text);
+                return text[end + 4..].to_owned();
+            }
+        }
+    }
+}
+
+#[test]
+fn hands_listeners_over_to_a_replacement() {
+    let path = socket_path("handoff");
+    let mut old = ServerBuilder::new()
+        .handoff_path(&path)
+        .bind("127.0.0.1:0".parse().unwrap(), named("old"))
+        .unwrap();
+    let addr = old.local_addr();
+    let old = task::spawn(async move { old.run().await });
+    assert_eq!(get(addr), "old");
+
+    let listeners = take_over(&path);
+    assert_eq!(listeners.len(), 1);
+    // The old server shuts down once the replacement has the listener.
+    task::block_on(io::timeout(Duration::from_secs(5), old)).unwrap();
+
+    let mut new = ServerBuilder::new()
+        .build_from_listeners(listeners, named("new"))
+        .unwrap();
+    task::spawn(async move { new.run().await });
+    assert_eq!(get(addr), "new");
+}
+
+#[test]
+fn keeps_listeners_the_peer_never_acknowledges() {
+    let path = socket_path("no-ack");
+    let mut old = ServerBuilder::new()
+        .handoff_path(&path)
+        .bind("127.0.0.1:0".parse().unwrap(), named("old"))
+        .unwrap();
+    let addr = old.local_addr();
+    let old = task::spawn(async move { old.run().await });
+
+    // Receive the sockets, then hang up without acknowledging them.
+    let start = Instant::now();
+    let mut peer = loop {
+        match UnixStream::connect(&path) {
+            Ok(peer) => break peer,
+            Err(err) if start.elapsed() > Duration::from_secs(5) => panic!("{}", err),
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    let mut buf = [0; 64];
+    assert!(peer.read(&mut buf).unwrap() > 0);
+    drop(peer);
+
+    // The old server keeps serving, and can still hand its listener over.
+    assert_eq!(get(addr), "old");
+    let listeners = take_over(&path);
+    task::block_on(io::timeout(Duration::from_secs(5), old)).unwrap();
+    let mut new = ServerBuilder::new()
+        .build_from_listeners(listeners, named("new"))
+        .unwrap();
+    task::spawn(async move { new.run().await });
+    assert_eq!(get(addr), "new");
+}
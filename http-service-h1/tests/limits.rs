@@ -0,0 +1,171 @@
+//! Limiting connections, requests per connection and requests in flight.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::io;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::task;
+use futures::channel::{mpsc, oneshot};
+use futures::FutureExt;
+use http_service::{Request, Response};
+use http_service_h1::{LimitAction, ServerBuilder};
+
+/// Start a server answering with "hello", returning its address.
+fn start(builder: ServerBuilder) -> SocketAddr {
+    let service = |_req: Request| async { Ok::<_, http_service::Error>(Response::from("hello")) };
+    let mut server = builder
+        .bind("127.0.0.1:0".parse().unwrap(), service)
+        .unwrap();
+    let addr = server.local_addr();
+    task::spawn(async move { server.run().await });
+    addr
+}
+
+/// Read a single response, whose body has a `Content-Length`.
+async fn read_response(stream: &mut TcpStream) -> String {
+    let mut res = Vec::new();
+    let mut byte = [0; 1];
+    while !res.ends_with(b"\r\n\r\n") {
+        assert_eq!(
+            stream.read(&mut byte).await.unwrap(),
+            1,
+            "connection closed"
+        );
+        res.push(byte[0]);
+    }
+    let head = String::from_utf8(res).unwrap().to_lowercase();
+    let len: usize = head
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length: "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await.unwrap();
+    head + &String::from_utf8(body).unwrap()
+}
+
+/// Read everything until the server closes the connection.
+async fn read_to_close(stream: &mut TcpStream) -> String {
+    let mut res = Vec::new();
+    io::timeout(Duration::from_secs(5), stream.read_to_end(&mut res))
+        .await
+        .unwrap();
+    String::from_utf8(res).unwrap().to_lowercase()
+}
+
+const GET: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+#[test]
+fn closes_connections_after_the_last_allowed_request() {
+    task::block_on(async {
+        let addr = start(ServerBuilder::new().max_requests_per_connection(Some(3)));
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        for _ in 0..2 {
+            stream.write_all(GET).await.unwrap();
+            let res = read_response(&mut stream).await;
+            assert!(res.starts_with("http/1.1 200 ok\r\n"), "{}", res);
+            assert!(!res.contains("\r\nconnection: close\r\n"), "{}", res);
+        }
+
+        stream.write_all(GET).await.unwrap();
+        let res = read_to_close(&mut stream).await;
+        assert!(res.starts_with("http/1.1 200 ok\r\n"), "{}", res);
+        assert!(res.contains("\r\nconnection: close\r\n"), "{}", res);
+    });
+}
+
+#[test]
+fn rejects_requests_over_the_in_flight_limit() {
+    task::block_on(async {
+        let (arrived, mut has_arrived) = mpsc::unbounded();
+        let (release, released) = oneshot::channel::<()>();
+        let released = released.shared();
+        let service = move |_req: Request| {
+            let arrived = arrived.clone();
+            let released = released.clone();
+            async move {
+                arrived.unbounded_send(()).unwrap();
+                let _ = released.await;
+                Ok::<_, http_service::Error>(Response::from("finished"))
+            }
+        };
+        let mut server = ServerBuilder::new()
+            .max_in_flight_requests(Some(1))
+            .request_limit_action(LimitAction::Reject)
+            .bind("127.0.0.1:0".parse().unwrap(), service)
+            .unwrap();
+        let addr = server.local_addr();
+        task::spawn(async move { server.run().await });
+
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        first.write_all(GET).await.unwrap();
+        has_arrived.next().await.unwrap();
+
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        second.write_all(GET).await.unwrap();
+        let res = read_response(&mut second).await;
+        assert!(
+            res.starts_with("http/1.1 503 service unavailable\r\n"),
+            "{}",
+            res
+        );
+
+        release.send(()).unwrap();
+        let res = read_response(&mut first).await;
+        assert!(res.starts_with("http/1.1 200 ok\r\n"), "{}", res);
+    });
+}
+
+#[test]
+fn holds_connections_over_the_limit_until_one_closes() {
+    task::block_on(async {
+        let addr = start(
+            ServerBuilder::new()
+                .max_connections(Some(1))
+                .connection_limit_action(LimitAction::Wait),
+        );
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        first.write_all(GET).await.unwrap();
+        read_response(&mut first).await;
+
+        // The second connection isn't served while the first is open.
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        second.write_all(GET).await.unwrap();
+        let mut byte = [0; 1];
+        let read = io::timeout(Duration::from_millis(300), second.read(&mut byte)).await;
+        assert_eq!(read.unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+        drop(first);
+        let res = io::timeout(Duration::from_secs(5), async {
+            Ok(read_response(&mut second).await)
+        })
+        .await
+        .unwrap();
+        assert!(res.starts_with("http/1.1 200 ok\r\n"), "{}", res);
+    });
+}
+
+#[test]
+fn closes_connections_over_the_limit() {
+    task::block_on(async {
+        let addr = start(
+            ServerBuilder::new()
+                .max_connections(Some(1))
+                .connection_limit_action(LimitAction::Reject),
+        );
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        first.write_all(GET).await.unwrap();
+        read_response(&mut first).await;
+
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(read_to_close(&mut second).await, "");
+
+        // The first connection is still served.
+        first.write_all(GET).await.unwrap();
+        let res = read_response(&mut first).await;
+        assert!(res.starts_with("http/1.1 200 ok\r\n"), "{}", res);
+    });
+}
@@ -0,0 +1,118 @@
+//! Shutting a server down gracefully.
+
+use std::time::{Duration, Instant};
+
+use async_std::io;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::task;
+use futures::channel::{mpsc, oneshot};
+use futures::FutureExt;
+use http_service::{Request, Response};
+use http_service_h1::ServerBuilder;
+
+const GET: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+/// Read everything until the server closes the connection.
+async fn read_to_close(stream: &mut TcpStream) -> String {
+    let mut res = Vec::new();
+    io::timeout(Duration::from_secs(5), stream.read_to_end(&mut res))
+        .await
+        .unwrap();
+    String::from_utf8(res).unwrap()
+}
+
+#[test]
+fn finishes_in_flight_requests() {
+    task::block_on(async {
+        let (arrived, mut has_arrived) = mpsc::unbounded();
+        let (release, released) = oneshot::channel::<()>();
+        let released = released.shared();
+        let service = move |_req: Request| {
+            let arrived = arrived.clone();
+            let released = released.clone();
+            async move {
+                arrived.unbounded_send(()).unwrap();
+                let _ = released.await;
+                Ok::<_, http_service::Error>(Response::from("finished"))
+            }
+        };
+        let mut server = ServerBuilder::new()
+            .bind("127.0.0.1:0".parse().unwrap(), service)
+            .unwrap();
+        let addr = server.local_addr();
+        let shutdown = server.shutdown_handle();
+        let run = task::spawn(async move { server.run().await });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(GET).await.unwrap();
+        has_arrived.next().await.unwrap();
+        shutdown.shutdown();
+
+        // Answer only once the server has stopped accepting connections.
+        task::sleep(Duration::from_millis(100)).await;
+        release.send(()).unwrap();
+        let res = read_to_close(&mut stream).await;
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+        assert!(res.ends_with("finished"), "{}", res);
+        io::timeout(Duration::from_secs(5), run).await.unwrap();
+    });
+}
+
+#[test]
+fn closes_idle_keep_alive_connections() {
+    task::block_on(async {
+        let service =
+            |_req: Request| async { Ok::<_, http_service::Error>(Response::from("hello")) };
+        let mut server = ServerBuilder::new()
+            .bind("127.0.0.1:0".parse().unwrap(), service)
+            .unwrap();
+        let addr = server.local_addr();
+        let shutdown = server.shutdown_handle();
+        let run = task::spawn(async move { server.run().await });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(GET).await.unwrap();
+        let mut buf = [0; 1024];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(buf[..n].starts_with(b"HTTP/1.1 200 OK\r\n"));
+
+        shutdown.shutdown();
+        assert_eq!(read_to_close(&mut stream).await, "");
+        io::timeout(Duration::from_secs(5), run).await.unwrap();
+    });
+}
+
+#[test]
+fn stops_waiting_once_the_shutdown_timeout_expires() {
+    task::block_on(async {
+        let (arrived, mut has_arrived) = mpsc::unbounded();
+        let service = move |_req: Request| {
+            let arrived = arrived.clone();
+            async move {
+                arrived.unbounded_send(()).unwrap();
+                futures::future::pending::<()>().await;
+                Ok::<_, http_service::Error>(Response::from("never"))
+            }
+        };
+        let mut server = ServerBuilder::new()
+            .shutdown_timeout(Duration::from_millis(200))
+            .bind("127.0.0.1:0".parse().unwrap(), service)
+            .unwrap();
+        let addr = server.local_addr();
+        let shutdown = server.shutdown_handle();
+        let run = task::spawn(async move { server.run().await });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(GET).await.unwrap();
+        has_arrived.next().await.unwrap();
+
+        let start = Instant::now();
+        shutdown.shutdown();
+        io::timeout(Duration::from_secs(5), run).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // The hanging connection is closed without a response.
+        assert_eq!(read_to_close(&mut stream).await, "");
+    });
+}
@@ -0,0 +1,212 @@
+//! Timing out slow clients and services, and refusing oversized heads.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::io;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::task;
+use http_service::{Request, Response};
+use http_service_h1::ServerBuilder;
+
+/// Start a server answering with the request body, returning its address.
+fn start(builder: ServerBuilder) -> SocketAddr {
+    let service = |req: Request| async move {
+        let body = req.body_string().await?;
+        Ok::<_, http_service::Error>(Response::from(body))
+    };
+    let mut server = builder
+        .bind("127.0.0.1:0".parse().unwrap(), service)
+        .unwrap();
+    let addr = server.local_addr();
+    task::spawn(async move { server.run().await });
+    addr
+}
+
+/// Read a single response, whose body has a `Content-Length`.
+async fn read_response(stream: &mut TcpStream) -> String {
+    let mut res = Vec::new();
+    let mut byte = [0; 1];
+    while !res.ends_with(b"\r\n\r\n") {
+        assert_eq!(
+            stream.read(&mut byte).await.unwrap(),
+            1,
+            "connection closed"
+        );
+        res.push(byte[0]);
+    }
+    let head = String::from_utf8(res).unwrap().to_lowercase();
+    let len: usize = head
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length: "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await.unwrap();
+    head + &String::from_utf8(body).unwrap()
+}
+
+/// Read everything until the server closes the connection.
+async fn read_to_close(stream: &mut TcpStream) -> String {
+    let mut res = Vec::new();
+    io::timeout(Duration::from_secs(5), stream.read_to_end(&mut res))
+        .await
+        .unwrap();
+    String::from_utf8(res).unwrap().to_lowercase()
+}
+
+const GET: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+#[test]
+fn answers_slow_request_heads_with_408() {
+    task::block_on(async {
+        let addr = start(ServerBuilder::new().header_timeout(Some(Duration::from_millis(200))));
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+
+        let res = read_to_close(&mut stream).await;
+        assert!(
+            res.starts_with("http/1.1 408 request timeout\r\n"),
+            "{}",
+            res
+        );
+    });
+}
+
+#[test]
+fn answers_oversized_request_heads_with_431() {
+    task::block_on(async {
+        let addr = start(ServerBuilder::new().max_header_size(1024));
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let head = format!(
+            "GET / HTTP/1.1\r\nHost: localhost\r\nX-Large: {}\r\n\r\n",
+            "a".repeat(2000)
+        );
+        stream.write_all(head.as_bytes()).await.unwrap();
+
+        let res = read_to_close(&mut stream).await;
+        assert!(res.starts_with("http/1.1 431 "), "{}", res);
+    });
+}
+
+#[test]
+fn answers_heads_over_the_default_limit_with_431() {
+    task::block_on(async {
+        let addr = start(ServerBuilder::new());
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        // Short lines, which `async-h1` would give up on by itself once they
+        // add up to 8 KiB.
+        let mut head = String::from("GET / HTTP/1.1\r\nHost: localhost\r\n");
+        for i in 0..100 {
+            head += &format!("X-Header-{}: {}\r\n", i, "a".repeat(80));
+        }
+        head += "\r\n";
+        stream.write_all(head.as_bytes()).await.unwrap();
+
+        let res = read_to_close(&mut stream).await;
+        assert!(res.starts_with("http/1.1 431 "), "{}", res);
+    });
+}
+
+#[test]
+fn closes_idle_keep_alive_connections() {
+    task::block_on(async {
+        let addr = start(ServerBuilder::new().idle_timeout(Some(Duration::from_millis(200))));
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(GET).await.unwrap();
+        let res = read_response(&mut stream).await;
+        assert!(res.starts_with("http/1.1 200 ok\r\n"), "{}", res);
+
+        // Nothing more is sent before the connection is closed.
+        assert_eq!(read_to_close(&mut stream).await, "");
+    });
+}
+
+#[test]
+fn answers_slow_request_bodies_with_408() {
+    task::block_on(async {
+        let addr = start(ServerBuilder::new().body_timeout(Some(Duration::from_millis(200))));
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nab")
+            .await
+            .unwrap();
+
+        let res = read_to_close(&mut stream).await;
+        assert!(
+            res.starts_with("http/1.1 408 request timeout\r\n"),
+            "{}",
+            res
+        );
+        assert!(res.contains("\r\nconnection: close\r\n"), "{}", res);
+    });
+}
+
+#[test]
+fn answers_slow_responses_with_503() {
+    task::block_on(async {
+        let service = |_req: Request| async {
+            task::sleep(Duration::from_secs(10)).await;
+            Ok::<_, http_service::Error>(Response::from("too late"))
+        };
+        let mut server = ServerBuilder::new()
+            .request_timeout(Some(Duration::from_millis(200)))
+            .bind("127.0.0.1:0".parse().unwrap(), service)
+            .unwrap();
+        let addr = server.local_addr();
+        task::spawn(async move { server.run().await });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(GET).await.unwrap();
+        let res = read_to_close(&mut stream).await;
+        assert!(
+            res.starts_with("http/1.1 503 service unavailable\r\n"),
+            "{}",
+            res
+        );
+        assert!(res.contains("\r\nconnection: close\r\n"), "{}", res);
+    });
+}
+
+#[test]
+fn does_not_count_idle_time_against_the_header_timeout() {
+    task::block_on(async {
+        let addr = start(
+            ServerBuilder::new()
+                .header_timeout(Some(Duration::from_millis(200)))
+                .idle_timeout(Some(Duration::from_secs(10))),
+        );
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(GET).await.unwrap();
+        assert!(read_response(&mut stream)
+            .await
+            .starts_with("http/1.1 200 ok\r\n"));
+
+        task::sleep(Duration::from_millis(500)).await;
+        stream.write_all(GET).await.unwrap();
+        assert!(read_response(&mut stream)
+            .await
+            .starts_with("http/1.1 200 ok\r\n"));
+    });
+}
+
+#[test]
+#[ignore = "waits for longer than async-h1's 60 second head timeout"]
+fn serves_keep_alive_connections_idle_past_async_h1s_head_timeout() {
+    task::block_on(async {
+        let addr = start(ServerBuilder::new().idle_timeout(Some(Duration::from_secs(120))));
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(GET).await.unwrap();
+        assert!(read_response(&mut stream)
+            .await
+            .starts_with("http/1.1 200 ok\r\n"));
+
+        task::sleep(Duration::from_secs(65)).await;
+        stream.write_all(GET).await.unwrap();
+        assert!(read_response(&mut stream)
+            .await
+            .starts_with("http/1.1 200 ok\r\n"));
+    });
+}
@@ -0,0 +1,113 @@
+//! Serving HTTPS on loopback with certificates signed by a test CA.
+#![cfg(feature = "tls")]
+
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_std::net::TcpStream;
+use async_std::task;
+use futures_rustls::client::TlsStream;
+use futures_rustls::pki_types::pem::PemObject;
+use futures_rustls::pki_types::{CertificateDer, ServerName};
+use futures_rustls::rustls::crypto::ring;
+use futures_rustls::rustls::{ClientConfig, RootCertStore};
+use futures_rustls::TlsConnector;
+use http_service::{Request, Response};
+use http_service_h1::{ServerBuilder, SniConfig, TlsConfig};
+use http_types::{Method, Url};
+
+const CA: &[u8] = include_bytes!("certs/ca.pem");
+const LOCALHOST_CERT: &[u8] = include_bytes!("certs/localhost.pem");
+const LOCALHOST_KEY: &[u8] = include_bytes!("certs/localhost.key");
+const EXAMPLE_CERT: &[u8] = include_bytes!("certs/example.test.pem");
+const EXAMPLE_KEY: &[u8] = include_bytes!("certs/example.test.key");
+
+/// Start a server answering with the URL of each request.
+fn start(tls: TlsConfig) -> SocketAddr {
+    let service = |req: Request| async move {
+        Ok::<_, http_service::Error>(Response::from(req.url().to_string()))
+    };
+    let mut server = ServerBuilder::new()
+        .tls(tls)
+        .bind("127.0.0.1:0".parse().unwrap(), service)
+        .unwrap();
+    let addr = server.local_addr();
+    task::spawn(async move { server.run().await });
+    addr
+}
+
+/// Open a connection that trusts the test CA only.
+async fn connect(addr: SocketAddr, name: &str) -> std::io::Result<TlsStream<TcpStream>> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(CA) {
+        roots.add(cert.unwrap()).unwrap();
+    }
+    let mut config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    let connector = TlsConnector::from(Arc::new(config));
+    let name = ServerName::try_from(name.to_owned()).unwrap();
+    let stream = TcpStream::connect(addr).await?;
+    connector.connect(name, stream).await
+}
+
+async fn get(stream: TlsStream<TcpStream>, host: &str) -> String {
+    let url = Url::parse(&format!("https://{}/hello", host)).unwrap();
+    let res = async_h1::connect(stream, http_types::Request::new(Method::Get, url))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 200);
+    res.body_string().await.unwrap()
+}
+
+#[test]
+fn serves_https() {
+    task::block_on(async {
+        let addr = start(TlsConfig::from_pem(LOCALHOST_CERT, LOCALHOST_KEY).unwrap());
+        let stream = connect(addr, "localhost").await.unwrap();
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"http/1.1"[..]));
+        let url = get(stream, "localhost").await;
+        assert!(url.starts_with("https://"), "{}", url);
+        assert!(url.ends_with("/hello"), "{}", url);
+    });
+}
+
+#[test]
+fn picks_the_certificate_by_server_name() {
+    task::block_on(async {
+        let mut sni = SniConfig::new();
+        sni.add_pem("localhost", LOCALHOST_CERT, LOCALHOST_KEY)
+            .unwrap()
+            .add_pem("Example.Test", EXAMPLE_CERT, EXAMPLE_KEY)
+            .unwrap();
+        let addr = start(sni.build().unwrap());
+
+        // The client only accepts a certificate for the name it asked for.
+        for name in &["localhost", "example.test", "EXAMPLE.test"] {
+            let stream = connect(addr, name).await.unwrap();
+            assert!(get(stream, name).await.starts_with("https://"));
+        }
+
+        // Without a default certificate, other names are refused.
+        assert!(connect(addr, "other.test").await.is_err());
+    });
+}
+
+#[test]
+fn falls_back_to_the_default_certificate() {
+    task::block_on(async {
+        let mut sni = SniConfig::new();
+        sni.add_pem("example.test", EXAMPLE_CERT, EXAMPLE_KEY)
+            .unwrap()
+            .set_default_pem(LOCALHOST_CERT, LOCALHOST_KEY)
+            .unwrap();
+        let addr = start(sni.build().unwrap());
+
+        let stream = connect(addr, "localhost").await.unwrap();
+        assert!(get(stream, "localhost").await.starts_with("https://"));
+    });
+}
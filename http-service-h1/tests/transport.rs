@@ -0,0 +1,124 @@
+//! Serving connections over a custom transport.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_std::io::{self, Read, Write};
+use async_std::prelude::*;
+use async_std::task;
+use futures::channel::mpsc;
+use http_service::{ConnectionInfo, PeerAddr, Request, Response};
+use http_service_h1::{Server, Transport};
+
+/// One end of an in-memory duplex stream.
+struct Pipe {
+    incoming: mpsc::UnboundedReceiver<Vec<u8>>,
+    buffered: Vec<u8>,
+    outgoing: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+/// Create both ends of an in-memory duplex stream.
+fn pipe() -> (Pipe, Pipe) {
+    let (a_sender, a_receiver) = mpsc::unbounded();
+    let (b_sender, b_receiver) = mpsc::unbounded();
+    let a = Pipe {
+        incoming: b_receiver,
+        buffered: Vec::new(),
+        outgoing: a_sender,
+    };
+    let b = Pipe {
+        incoming: a_receiver,
+        buffered: Vec::new(),
+        outgoing: b_sender,
+    };
+    (a, b)
+}
+
+impl Read for Pipe {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.buffered.is_empty() {
+            match Pin::new(&mut self.incoming).poll_next(cx) {
+                Poll::Ready(Some(data)) => self.buffered = data,
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let n = buf.len().min(self.buffered.len());
+        buf[..n].copy_from_slice(&self.buffered[..n]);
+        self.buffered.drain(..n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl Write for Pipe {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.outgoing.unbounded_send(buf.to_vec()) {
+            Ok(()) => Poll::Ready(Ok(buf.len())),
+            Err(_) => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.outgoing.close_channel();
+        Poll::Ready(Ok(()))
+    }
+}
+
+const PEER: &str = "192.0.2.1:4000";
+
+impl Transport for Pipe {
+    fn connection_info(&self) -> ConnectionInfo {
+        let mut info = ConnectionInfo::new();
+        info.set_peer_addr(Some(PEER.parse().unwrap()));
+        info
+    }
+}
+
+#[test]
+fn serves_requests_over_an_in_memory_stream() {
+    task::block_on(async {
+        let (sender, incoming) = mpsc::unbounded::<io::Result<Pipe>>();
+        let service = |req: Request| async move {
+            let peer = req.local().get::<PeerAddr>().map(|peer| peer.addr());
+            Ok::<_, http_service::Error>(Response::from(format!("hello {:?}", peer)))
+        };
+        let mut server = Server::new("http://localhost".into(), incoming, service);
+        let shutdown = server.shutdown_handle();
+        let run = task::spawn(async move { server.run().await });
+
+        let (mut client, stream) = pipe();
+        sender.unbounded_send(Ok(stream)).unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        // Hang up once the request is sent.
+        client.outgoing.close_channel();
+
+        // The server closes its end once the client has hung up.
+        let mut res = String::new();
+        io::timeout(Duration::from_secs(5), client.read_to_string(&mut res))
+            .await
+            .unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+        let peer: SocketAddr = PEER.parse().unwrap();
+        assert!(res.ends_with(&format!("hello {:?}", Some(peer))), "{}", res);
+
+        shutdown.shutdown();
+        io::timeout(Duration::from_secs(5), run).await.unwrap();
+    });
+}
@@ -0,0 +1,111 @@
+//! Serving Unix domain sockets.
+// Peer credentials include the pid on these platforms only.
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::time::Duration;
+
+use async_std::io;
+use async_std::os::unix::net::UnixStream;
+use async_std::prelude::*;
+use async_std::task;
+use futures::future::{self, BoxFuture, Either};
+use http_service::{ConnectionInfo, Error, HttpService, PeerCredentials, Request, Response};
+use http_service_h1::{Server, UnixListener};
+
+/// Answer with the credentials of the peer, as told to `connect`.
+struct Credentials;
+
+impl HttpService for Credentials {
+    type Connection = Option<PeerCredentials>;
+    type ConnectionError = Error;
+    type ConnectionFuture = BoxFuture<'static, Result<Self::Connection, Error>>;
+    type ResponseError = Error;
+    type ResponseFuture = BoxFuture<'static, Result<Response, Error>>;
+
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture {
+        let credentials = info.peer_credentials();
+        Box::pin(async move { Ok(credentials) })
+    }
+
+    fn respond(&self, credentials: Self::Connection, _req: Request) -> Self::ResponseFuture {
+        let body = match credentials {
+            Some(c) => format!("{} {} {:?}", c.uid(), c.gid(), c.pid()),
+            None => "unknown".to_owned(),
+        };
+        Box::pin(async move { Ok(Response::from(body)) })
+    }
+}
+
+#[test]
+fn serves_a_unix_socket_and_cleans_up_after_itself() {
+    task::block_on(async {
+        let path =
+            env::temp_dir().join(format!("http-service-h1-unix-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        // A socket file left behind by a process that is gone is replaced.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let listener = UnixListener::bind(&path).await.unwrap();
+        let err = UnixListener::bind(&path).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        listener.set_permissions(0o600).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // The service is told the credentials of the connecting process.
+        let mut server = Server::new("http://localhost".into(), listener.incoming(), Credentials);
+        let client = async {
+            let mut stream = UnixStream::connect(&path).await.unwrap();
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .await
+                .unwrap();
+            let mut res = Vec::new();
+            let mut byte = [0; 1];
+            while !res.ends_with(b"\r\n\r\n") {
+                let read = io::timeout(Duration::from_secs(5), stream.read(&mut byte));
+                assert_eq!(read.await.unwrap(), 1, "connection closed");
+                res.push(byte[0]);
+            }
+            let head = String::from_utf8(res).unwrap();
+            let len: usize = head
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length: "))
+                .unwrap()
+                .parse()
+                .unwrap();
+            let mut body = vec![0; len];
+            stream.read_exact(&mut body).await.unwrap();
+            head + &String::from_utf8(body).unwrap()
+        };
+        let res = match future::select(Box::pin(server.run()), Box::pin(client)).await {
+            Either::Left((res, _)) => panic!("server stopped: {:?}", res),
+            Either::Right((res, _)) => res,
+        };
+        drop(server);
+        let (uid, gid, pid) = unsafe { (libc::getuid(), libc::getgid(), libc::getpid()) };
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+        assert!(
+            res.ends_with(&format!("{} {} Some({})", uid, gid, pid)),
+            "{}",
+            res
+        );
+
+        // A socket file bound by someone else in the meantime is left alone.
+        fs::remove_file(&path).unwrap();
+        let replacement = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        drop(listener);
+        assert!(path.exists());
+        drop(replacement);
+
+        // The listener's own socket file is removed once it is dropped.
+        let listener = UnixListener::bind(&path).await.unwrap();
+        drop(listener);
+        assert!(!path.exists());
+    });
+}
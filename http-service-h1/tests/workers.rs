@@ -0,0 +1,131 @@
+//! Serving connections with a thread per core.
+
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use async_std::io;
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::task;
+use http_service::{local_service_fn, Request, Response};
+use http_service_h1::ServerBuilder;
+
+/// Send a request on the connection, returning the whole response.
+async fn get(stream: &mut TcpStream) -> String {
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut res = Vec::new();
+    let mut byte = [0; 1];
+    while !res.ends_with(b"\r\n\r\n") {
+        let read = io::timeout(Duration::from_secs(5), stream.read(&mut byte));
+        assert_eq!(read.await.unwrap(), 1, "connection closed");
+        res.push(byte[0]);
+    }
+    let head = String::from_utf8(res).unwrap().to_lowercase();
+    let len: usize = head
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length: "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await.unwrap();
+    head + &String::from_utf8(body).unwrap()
+}
+
+#[test]
+fn shares_one_service_between_workers() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let service = {
+        let hits = hits.clone();
+        move |_req: Request| {
+            let hit = hits.fetch_add(1, Ordering::SeqCst) + 1;
+            async move { Ok::<_, http_service::Error>(Response::from(hit.to_string())) }
+        }
+    };
+    let server = ServerBuilder::new()
+        .workers(Some(2))
+        .bind_workers("127.0.0.1:0".parse().unwrap(), service)
+        .unwrap();
+    assert_eq!(server.workers(), 2);
+    let addr = server.local_addr();
+    let metrics = server.metrics();
+    let shutdown = server.shutdown_handle();
+    let run = thread::spawn(move || server.run());
+
+    task::block_on(async {
+        for _ in 0..10 {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let res = get(&mut stream).await;
+            assert!(res.starts_with("http/1.1 200 ok\r\n"), "{}", res);
+        }
+    });
+    shutdown.shutdown();
+    run.join().unwrap().unwrap();
+
+    // Every worker counted its requests in the same service and metrics.
+    assert_eq!(hits.load(Ordering::SeqCst), 10);
+    assert_eq!(metrics.connections_accepted(), 10);
+    assert_eq!(metrics.connections_open(), 0);
+    assert_eq!(metrics.requests(), 10);
+    assert_eq!(metrics.requests_in_flight(), 0);
+}
+
+#[test]
+fn serves_services_that_are_not_send() {
+    let server = ServerBuilder::new()
+        .workers(Some(1))
+        .build_local("127.0.0.1:0".parse().unwrap(), || {
+            let hits = Rc::new(Cell::new(0));
+            local_service_fn(move |_req: Request| {
+                let hits = hits.clone();
+                async move {
+                    hits.set(hits.get() + 1);
+                    Ok::<_, http_service::Error>(Response::from(hits.get().to_string()))
+                }
+            })
+        })
+        .unwrap();
+    let addr = server.local_addr();
+    let shutdown = server.shutdown_handle();
+    let run = thread::spawn(move || server.run());
+
+    task::block_on(async {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        for hit in 1..=3 {
+            let res = get(&mut stream).await;
+            assert!(res.ends_with(&format!("\r\n\r\n{}", hit)), "{}", res);
+        }
+    });
+    shutdown.shutdown();
+    run.join().unwrap().unwrap();
+}
+
+#[test]
+fn stops_every_worker_once_one_panics() {
+    let workers = AtomicUsize::new(0);
+    let server = ServerBuilder::new()
+        .workers(Some(4))
+        .build_local("127.0.0.1:0".parse().unwrap(), move || {
+            if workers.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("worker failed to start");
+            }
+            |_req: Request| async { Ok::<_, http_service::Error>(Response::from("hello")) }
+        })
+        .unwrap();
+
+    // The other workers stop on their own, and the panic is passed on.
+    let (sender, stopped) = mpsc::channel();
+    thread::spawn(move || {
+        let run = panic::catch_unwind(AssertUnwindSafe(|| server.run()));
+        sender.send(run.is_err()).unwrap();
+    });
+    assert!(stopped.recv_timeout(Duration::from_secs(5)).unwrap());
+}
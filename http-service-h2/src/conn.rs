@@ -0,0 +1,194 @@
+//! Serving individual connections.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::io;
+use bytes::Bytes;
+use futures::future::{self, Either};
+use h2::server::SendResponse;
+use h2::RecvStream;
+use http_service::{Error, HttpService, Response};
+use http_service_server::accept::ListenerConfig;
+use http_service_server::error::{ErrorHandler, ErrorResponse};
+use http_service_server::shutdown::{Signal, Tracker};
+use http_types::Version;
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+use crate::convert;
+#[cfg(feature = "tls")]
+use crate::TlsConfig;
+use crate::Transport;
+
+/// Server settings needed by each connection.
+pub(crate) struct ConnConfig {
+    pub(crate) addr: String,
+    pub(crate) h2: h2::server::Builder,
+    pub(crate) shutdown: Signal,
+    pub(crate) force_close: Signal,
+    /// Tracks the tasks serving each stream, alongside the connections.
+    pub(crate) streams: Arc<Tracker>,
+    pub(crate) header_timeout: Option<Duration>,
+    pub(crate) error_handler: ErrorHandler,
+    pub(crate) error_response: ErrorResponse,
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<TlsConfig>,
+}
+
+impl ListenerConfig for ConnConfig {
+    fn addr(&self) -> &str {
+        &self.addr
+    }
+}
+
+impl ConnConfig {
+    /// Report an error from the service, and turn it into a response.
+    fn error_response(&self, err: Error) -> Response {
+        (self.error_handler)(&err);
+        (self.error_response)(&err)
+    }
+}
+
+/// Accept a new connection.
+pub(crate) async fn accept<S, T>(
+    service: Arc<S>,
+    stream: T,
+    config: Arc<ConnConfig>,
+) -> Result<(), Error>
+where
+    S: HttpService,
+    <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+    <S as HttpService>::Connection: Sync,
+    T: Transport,
+{
+    #[cfg(feature = "tls")]
+    {
+        if let Some(tls) = &config.tls {
+            // A stalled handshake is bounded by the header timeout.
+            let accept = tls.acceptor().accept(stream);
+            let stream = match config.header_timeout {
+                Some(timeout) => io::timeout(timeout, accept).await,
+                None => accept.await,
+            }
+            .map_err(|err| {
+                log::debug!("error during TLS handshake: {}", err);
+                err
+            })?;
+            return serve(service, stream, config).await;
+        }
+    }
+
+    serve(service, stream, config).await
+}
+
+/// Serve HTTP/2 on an established connection.
+async fn serve<S, T>(service: Arc<S>, stream: T, config: Arc<ConnConfig>) -> Result<(), Error>
+where
+    S: HttpService,
+    <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+    <S as HttpService>::Connection: Sync,
+    T: Transport,
+{
+    let mut info = stream.connection_info();
+    info.set_protocol(Some(Version::Http2_0));
+
+    let handshake = config.h2.handshake(stream.compat());
+    let handshake = async { handshake.await.map_err(io::Error::other) };
+    let mut h2 = match config.header_timeout {
+        Some(timeout) => io::timeout(timeout, handshake).await,
+        None => handshake.await,
+    }
+    .map_err(|err| {
+        log::debug!("error during HTTP/2 handshake: {}", err);
+        err
+    })?;
+
+    // If the service refuses the connection, the error is sent in response to
+    // every request the client has already sent, and the connection is closed.
+    let conn = match service.connect(&info).await {
+        Ok(conn) => Ok(conn),
+        Err(err) => {
+            let err = err.into();
+            (config.error_handler)(&err);
+            h2.graceful_shutdown();
+            Err(Arc::new(err))
+        }
+    };
+
+    // Once a shutdown starts, a GOAWAY frame tells the client to stop opening
+    // streams. The connection closes once the open streams are finished, or
+    // when it is forcibly closed.
+    let mut shutdown = config.shutdown.wait();
+    let mut force_close = config.force_close.wait();
+    let mut draining = false;
+    loop {
+        let next = future::poll_fn(|cx| h2.poll_accept(cx));
+        let signal = if draining {
+            &mut force_close
+        } else {
+            &mut shutdown
+        };
+        let next = match future::select(next, signal).await {
+            Either::Left((next, _)) => next,
+            Either::Right(_) if draining => return Ok(()),
+            Either::Right(_) => {
+                h2.graceful_shutdown();
+                draining = true;
+                continue;
+            }
+        };
+        let (req, send) = match next {
+            Some(Ok(stream)) => stream,
+            Some(Err(err)) => {
+                log::debug!("error serving connection: {}", err);
+                return Err(err.into());
+            }
+            None => return Ok(()),
+        };
+
+        // Streams are served concurrently, while this task keeps driving the
+        // connection. A shutdown waits for them like for connections, and
+        // forcibly closing the connections abandons them.
+        let respond = respond(service.clone(), conn.clone(), req, send, config.clone());
+        let token = config.streams.track();
+        let force_closed = config.force_close.wait();
+        async_std::task::spawn(async move {
+            let _token = token;
+            future::select(Box::pin(respond), force_closed).await;
+        });
+    }
+}
+
+/// Respond to a single stream.
+async fn respond<S>(
+    service: Arc<S>,
+    conn: Result<S::Connection, Arc<Error>>,
+    req: http::Request<RecvStream>,
+    mut send: SendResponse<Bytes>,
+    config: Arc<ConnConfig>,
+) where
+    S: HttpService,
+{
+    let res = match (convert::request(req, &config.addr), conn) {
+        (Ok(req), Ok(conn)) => match service.respond(conn, req).await {
+            Ok(res) => res,
+            Err(err) => config.error_response(err.into()),
+        },
+        (Ok(_), Err(err)) => (config.error_response)(&err),
+        (Err(err), _) => config.error_response(err),
+    };
+
+    let sent = async move {
+        let (head, body) = convert::response(res)?;
+        let end_of_stream = body.is_empty() == Some(true);
+        let stream = send.send_response(head, end_of_stream)?;
+        if !end_of_stream {
+            convert::send_body(stream, body).await?;
+        }
+        Ok::<_, Error>(())
+    };
+    if let Err(err) = sent.await {
+        log::debug!("error sending response: {}", err);
+    }
+}
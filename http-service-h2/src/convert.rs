@@ -0,0 +1,90 @@
+//! Conversions between `http-types` and the `http` types used by `h2`.
+
+use std::cmp;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_std::io::{self, BufReader};
+use async_std::prelude::*;
+use bytes::{Buf, Bytes};
+use futures::future;
+use h2::{RecvStream, SendStream};
+use http_service::{Body, Error, Request};
+use http_service_server::convert::{self, CHUNK_SIZE};
+use http_types::Version;
+
+pub(crate) use http_service_server::convert::response;
+
+/// Convert a request received by `h2`.
+///
+/// `base` is used for requests that carry no `:authority`, such as requests
+/// from clients that only send a `Host` header.
+pub(crate) fn request(req: http::Request<RecvStream>, base: &str) -> Result<Request, Error> {
+    let (parts, body) = req.into_parts();
+    let body = if body.is_end_stream() {
+        None
+    } else {
+        let body = BufReader::new(RecvBody {
+            stream: body,
+            chunk: Bytes::new(),
+        });
+        Some(Body::from_reader(body, convert::content_length(&parts)))
+    };
+    convert::request(parts, body, Version::Http2_0, base)
+}
+
+/// Send a response body, respecting the peer's flow control window.
+pub(crate) async fn send_body(mut stream: SendStream<Bytes>, mut body: Body) -> Result<(), Error> {
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let n = body.read(&mut buf).await?;
+        if n == 0 {
+            stream.send_data(Bytes::new(), true)?;
+            return Ok(());
+        }
+
+        let mut chunk = Bytes::copy_from_slice(&buf[..n]);
+        while !chunk.is_empty() {
+            stream.reserve_capacity(chunk.len());
+            let capacity = match future::poll_fn(|cx| stream.poll_capacity(cx)).await {
+                Some(capacity) => capacity?,
+                None => return Err(io::Error::from(io::ErrorKind::ConnectionReset).into()),
+            };
+            let data = chunk.split_to(cmp::min(capacity, chunk.len()));
+            stream.send_data(data, false)?;
+        }
+    }
+}
+
+/// A request body received by `h2`.
+///
+/// Received data is released back to the peer's flow control window as it is
+/// read, so a peer can only send ahead of the service by the window size.
+struct RecvBody {
+    stream: RecvStream,
+    chunk: Bytes,
+}
+
+impl io::Read for RecvBody {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        while self.chunk.is_empty() {
+            match self.stream.poll_data(cx) {
+                Poll::Ready(Some(Ok(data))) => {
+                    let _ = self.stream.flow_control().release_capacity(data.len());
+                    self.chunk = data;
+                }
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(io::Error::other(err))),
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let n = cmp::min(buf.len(), self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk[..n]);
+        self.chunk.advance(n);
+        Poll::Ready(Ok(n))
+    }
+}
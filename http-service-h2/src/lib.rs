@@ -0,0 +1,429 @@
+//! `HttpService` server that uses h2 as backend.
+//!
+//! Connections speak HTTP/2 from the start: over plain TCP the client must
+//! know in advance that the server speaks HTTP/2 ("prior knowledge" h2c),
+//! and over TLS the protocol is agreed on through ALPN.
+
+#![forbid(future_incompatible, rust_2018_idioms)]
+#![deny(missing_debug_implementations, nonstandard_style)]
+#![warn(missing_docs, missing_doc_code_examples)]
+#![cfg_attr(test, deny(warnings))]
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use http_service::{Error, HttpService, Response};
+use http_service_server::accept::{self, Acceptor, Stopped};
+use http_service_server::error::{self, AcceptErrorHandler, ErrorHandler, ErrorResponse};
+use http_service_server::limit::Limiter;
+use http_service_server::shutdown::{Signal, Tracker};
+
+use async_std::io;
+use async_std::net::SocketAddr;
+use async_std::prelude::*;
+use async_std::stream::Stream;
+use async_std::sync::Arc;
+
+mod conn;
+mod convert;
+#[cfg(feature = "tls")]
+mod tls;
+
+pub use http_service_server::error::AcceptErrorAction;
+pub use http_service_server::limit::LimitAction;
+pub use http_service_server::shutdown::ShutdownHandle;
+pub use http_service_server::transport::Transport;
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
+
+use conn::{accept, ConnConfig};
+
+/// A listening HTTP server that accepts connections in HTTP/2.
+///
+/// Every connection is passed to `HttpService::connect` once, and the
+/// requests multiplexed over it are served concurrently.
+pub struct Server<I, S: HttpService> {
+    incoming: I,
+    service: Arc<S>,
+    addr: String,
+    h2: h2::server::Builder,
+    shutdown: Signal,
+    force_close: Signal,
+    connections: Arc<Tracker>,
+    shutdown_timeout: Duration,
+    header_timeout: Option<Duration>,
+    max_connections: Option<usize>,
+    connection_action: LimitAction,
+    error_handler: ErrorHandler,
+    error_response: ErrorResponse,
+    accept_error_handler: AcceptErrorHandler,
+    max_accept_backoff: Duration,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+}
+
+impl<I, S: HttpService> fmt::Debug for Server<I, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Server")
+            .field("addr", &self.addr)
+            .field("h2", &self.h2)
+            .field("shutdown", &self.shutdown)
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .field("header_timeout", &self.header_timeout)
+            .field("max_connections", &self.max_connections)
+            .field("connection_action", &self.connection_action)
+            .field("max_accept_backoff", &self.max_accept_backoff)
+            .finish()
+    }
+}
+
+impl<I, S, T> Server<I, S>
+where
+    S: HttpService,
+    <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+    <S as HttpService>::Connection: Sync,
+    I: Stream<Item = io::Result<T>> + Unpin + Send + Sync,
+    T: Transport,
+{
+    /// Create a new server, serving the connections yielded by `incoming`.
+    ///
+    /// `addr` is the base URL of requests that don't name their authority.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h2::Server;
+    /// use async_std::net::TcpListener;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("127.0.0.1:3000").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn new(addr: String, incoming: I, service: S) -> Self {
+        let mut h2 = h2::server::Builder::new();
+        h2.max_concurrent_streams(100);
+        Server {
+            service: Arc::new(service),
+            incoming,
+            addr,
+            h2,
+            shutdown: Signal::new(),
+            force_close: Signal::new(),
+            connections: Arc::new(Tracker::default()),
+            shutdown_timeout: Duration::from_secs(30),
+            header_timeout: Some(Duration::from_secs(30)),
+            max_connections: None,
+            connection_action: LimitAction::Wait,
+            error_handler: Arc::new(error::log_error),
+            error_response: Arc::new(error::default_error_response),
+            accept_error_handler: Arc::new(AcceptErrorAction::classify),
+            max_accept_backoff: Duration::from_secs(1),
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Get a handle that can be used to shut the server down.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h2::Server;
+    /// use async_std::net::TcpListener;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("127.0.0.1:3000").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///
+    ///     let handle = server.shutdown_handle();
+    ///     async_std::task::spawn(async move {
+    ///         async_std::task::sleep(std::time::Duration::from_secs(60)).await;
+    ///         handle.shutdown();
+    ///     });
+    ///
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(self.shutdown.clone())
+    }
+
+    /// Set how long a shutdown waits for in-flight requests to finish.
+    ///
+    /// Connections still open when the timeout expires are closed. Defaults to
+    /// 30 seconds.
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) {
+        self.shutdown_timeout = timeout;
+    }
+
+    /// Set how long a new connection may take to complete its handshakes.
+    ///
+    /// The timeout bounds the TLS handshake, and the HTTP/2 connection
+    /// preface and settings exchange that follow it. When it expires, the
+    /// connection is closed. `None` waits forever. Defaults to 30 seconds.
+    pub fn set_header_timeout(&mut self, timeout: Option<Duration>) {
+        self.header_timeout = timeout;
+    }
+
+    /// Set the most connections served at once.
+    ///
+    /// What happens to connections over the limit is set by
+    /// [`set_connection_limit_action`](#method.set_connection_limit_action).
+    /// `None` serves any number of connections, which is the default.
+    ///
+    /// A limit of zero is refused with an `InvalidInput` error when the server
+    /// starts.
+    pub fn set_max_connections(&mut self, max: Option<usize>) {
+        self.max_connections = max;
+    }
+
+    /// Set what happens to connections over the limit.
+    ///
+    /// With [`LimitAction::Wait`], the server stops accepting until a
+    /// connection is closed, leaving new connections in the listener's
+    /// backlog. With [`LimitAction::Reject`], new connections are accepted and
+    /// closed straight away. Defaults to waiting.
+    pub fn set_connection_limit_action(&mut self, action: LimitAction) {
+        self.connection_action = action;
+    }
+
+    /// Set how many streams a client may have open at once on a connection.
+    ///
+    /// Defaults to 100.
+    pub fn set_max_concurrent_streams(&mut self, max: u32) {
+        self.h2.max_concurrent_streams(max);
+    }
+
+    /// Set how much request body data a client may send on a stream before the
+    /// service reads it.
+    ///
+    /// Defaults to 65,535 bytes.
+    pub fn set_initial_window_size(&mut self, size: u32) {
+        self.h2.initial_window_size(size);
+    }
+
+    /// Set how much request body data a client may send on a connection,
+    /// across all its streams, before the service reads it.
+    ///
+    /// Defaults to 65,535 bytes.
+    pub fn set_initial_connection_window_size(&mut self, size: u32) {
+        self.h2.initial_connection_window_size(size);
+    }
+
+    /// Set how the server reacts to errors while accepting connections.
+    ///
+    /// Defaults to [`AcceptErrorAction::classify`]. Every error is logged,
+    /// whatever the action.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h2::{AcceptErrorAction, Server};
+    /// use async_std::net::TcpListener;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("127.0.0.1:3000").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///     // Never give up on the listener.
+    ///     server.set_accept_error_handler(|err| match AcceptErrorAction::classify(err) {
+    ///         AcceptErrorAction::Fatal => AcceptErrorAction::Backoff,
+    ///         action => action,
+    ///     });
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_accept_error_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&io::Error) -> AcceptErrorAction + Send + Sync + 'static,
+    {
+        self.accept_error_handler = Arc::new(handler);
+    }
+
+    /// Set the longest the server waits before accepting again after an error.
+    ///
+    /// Defaults to 1 second.
+    pub fn set_max_accept_backoff(&mut self, max: Duration) {
+        self.max_accept_backoff = max;
+    }
+
+    /// Set the callback that is told about errors returned by the service.
+    ///
+    /// This is called with every connection and response error, before it is
+    /// turned into a response. Defaults to logging the error.
+    pub fn set_error_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&Error) + Send + Sync + 'static,
+    {
+        self.error_handler = Arc::new(handler);
+    }
+
+    /// Set how errors returned by the service are turned into responses.
+    ///
+    /// Defaults to an empty response with the status code carried by the
+    /// error, which is `500 Internal Server Error` unless set otherwise.
+    pub fn set_error_response<F>(&mut self, f: F)
+    where
+        F: Fn(&Error) -> Response + Send + Sync + 'static,
+    {
+        self.error_response = Arc::new(f);
+    }
+
+    /// Serve connections over TLS.
+    ///
+    /// The scheme of the server's address is changed to `https`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h2::{Server, TlsConfig};
+    /// use async_std::net::TcpListener;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let listener = TcpListener::bind("127.0.0.1:3000").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut server = Server::new(addr, listener.incoming(), service);
+    ///     server.set_tls(TlsConfig::from_pem_files("cert.pem", "key.pem")?);
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn set_tls(&mut self, tls: TlsConfig) {
+        if let Some(rest) = self.addr.strip_prefix("http://") {
+            self.addr = format!("https://{}", rest);
+        }
+        self.tls = Some(tls);
+    }
+
+    /// Run the server until it is shut down.
+    ///
+    /// Once a shutdown is requested through a [`ShutdownHandle`], no new
+    /// connections are accepted, and every connection is sent a GOAWAY frame.
+    /// Connections are closed once their open streams are finished. This
+    /// method returns when all connections are closed, or when the shutdown
+    /// timeout expires.
+    ///
+    /// Errors accepting connections are handled according to the accept error
+    /// handler; only errors it deems fatal are returned, once the open
+    /// connections are closed as in a shutdown.
+    pub async fn run(&mut self) -> io::Result<()> {
+        if self.max_connections == Some(0) {
+            let msg = "the limit on connections must be at least 1";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        let config = Arc::new(ConnConfig {
+            addr: self.addr.clone(),
+            h2: self.h2.clone(),
+            shutdown: self.shutdown.clone(),
+            force_close: self.force_close.clone(),
+            streams: self.connections.clone(),
+            header_timeout: self.header_timeout,
+            error_handler: self.error_handler.clone(),
+            error_response: self.error_response.clone(),
+            #[cfg(feature = "tls")]
+            tls: self.tls.clone(),
+        });
+        let incoming = (&mut self.incoming).map(|stream| (config.clone(), stream));
+        let acceptor = Acceptor {
+            shutdown: self.shutdown.clone(),
+            connection_limit: self.max_connections.map(Limiter::new),
+            connection_action: self.connection_action,
+            error_handler: self.accept_error_handler.clone(),
+            max_backoff: self.max_accept_backoff,
+        };
+        let (service, connections) = (&self.service, &self.connections);
+        let stopped = acceptor
+            .run(incoming, |stream, config, permit| {
+                let token = connections.track();
+                let accept = accept(service.clone(), stream, config);
+                async_std::task::spawn(async move {
+                    let _token = token;
+                    let _permit = permit;
+                    accept.await
+                });
+            })
+            .await;
+
+        // A fatal error shuts the server down before it is returned.
+        if stopped.is_err() {
+            self.shutdown.fire();
+        }
+        if stopped.as_ref().ok() != Some(&Stopped::Closed) {
+            accept::drain(
+                self.connections.clone(),
+                self.shutdown_timeout,
+                &self.force_close,
+            )
+            .await;
+        }
+
+        stopped.map(|_| ())
+    }
+}
+
+/// Serve the given `HttpService` at the given address over prior-knowledge HTTP/2 (h2c), using
+/// `h2` as backend, and return a `Future` that can be `await`ed on.
+pub async fn serve<S: HttpService>(service: S, addr: SocketAddr) -> io::Result<()>
+where
+    <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+    <S as HttpService>::Connection: Sync,
+{
+    let listener = async_std::net::TcpListener::bind(addr).await?;
+    let addr = format!("http://{}", listener.local_addr()?);
+    let mut server = Server::<_, S>::new(addr, listener.incoming(), service);
+
+    server.run().await
+}
+
+/// Serve the given `HttpService` over HTTP/2 and TLS at the given address, using `h2` as backend,
+/// and return a `Future` that can be `await`ed on.
+#[cfg(feature = "tls")]
+pub async fn serve_tls<S: HttpService>(
+    service: S,
+    addr: SocketAddr,
+    tls: TlsConfig,
+) -> io::Result<()>
+where
+    <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+    <S as HttpService>::Connection: Sync,
+{
+    let listener = async_std::net::TcpListener::bind(addr).await?;
+    let addr = format!("https://{}", listener.local_addr()?);
+    let mut server = Server::<_, S>::new(addr, listener.incoming(), service);
+    server.set_tls(tls);
+
+    server.run().await
+}
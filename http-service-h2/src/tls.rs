@@ -0,0 +1,75 @@
+//! TLS support, using rustls.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_std::io;
+use futures_rustls::rustls::crypto::ring;
+use futures_rustls::rustls::ServerConfig;
+use futures_rustls::TlsAcceptor;
+use http_service_server::error::invalid_data;
+use http_service_server::tls::parse_pem;
+
+/// The ALPN protocol ID of HTTP/2 over TLS.
+const ALPN_H2: &[u8] = b"h2";
+
+/// TLS settings for a [`Server`](struct.Server.html).
+///
+/// Clients pick HTTP/2 through ALPN. Clients that don't use ALPN are assumed
+/// to speak HTTP/2 anyway; clients that only offer other protocols are refused
+/// during the handshake.
+///
+/// # Examples
+///
+/// ```no_run
+/// use http_service_h2::TlsConfig;
+///
+/// let tls = TlsConfig::from_pem_files("cert.pem", "key.pem")?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct TlsConfig {
+    acceptor: TlsAcceptor,
+}
+
+impl TlsConfig {
+    /// Use a single certificate chain and private key, read from PEM files.
+    pub fn from_pem_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> io::Result<Self> {
+        let cert = std::fs::read(cert)?;
+        let key = std::fs::read(key)?;
+        Self::from_pem(&cert, &key)
+    }
+
+    /// Use a single certificate chain and private key, in PEM format.
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> io::Result<Self> {
+        let (certs, key) = parse_pem(cert, key)?;
+        let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_data)?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(invalid_data)?;
+        config.alpn_protocols = vec![ALPN_H2.to_vec()];
+        Ok(Self::from_rustls(Arc::new(config)))
+    }
+
+    /// Use an existing rustls configuration.
+    ///
+    /// The configuration should advertise `h2` through ALPN.
+    pub fn from_rustls(config: Arc<ServerConfig>) -> Self {
+        Self {
+            acceptor: TlsAcceptor::from(config),
+        }
+    }
+
+    pub(crate) fn acceptor(&self) -> &TlsAcceptor {
+        &self.acceptor
+    }
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConfig").finish()
+    }
+}
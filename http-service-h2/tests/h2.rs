@@ -0,0 +1,223 @@
+//! Serving prior-knowledge HTTP/2 over loopback TCP.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_std::io;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use bytes::Bytes;
+use futures::channel::mpsc;
+use futures::{FutureExt, StreamExt};
+use h2::client::SendRequest;
+use http_service::{HttpService, Request, Response};
+use http_service_h2::{Server, ShutdownHandle};
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+type Client = SendRequest<Bytes>;
+
+/// Answer with the method, URL, version and body length of each request.
+async fn echo(req: Request) -> Result<Response, http_service::Error> {
+    let head = format!("{} {} {:?} ", req.method(), req.url(), req.version());
+    let body = req.body_string().await?;
+    Ok(Response::from(head + &body.len().to_string()))
+}
+
+/// Start a server on loopback, returning its address, a handle to shut it
+/// down, and the task running it.
+fn start<S>(service: S) -> (SocketAddr, ShutdownHandle, task::JoinHandle<io::Result<()>>)
+where
+    S: HttpService,
+    <<S as HttpService>::ResponseFuture as std::future::Future>::Output: Send,
+    <S as HttpService>::Connection: Sync,
+{
+    let listener = task::block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sender, incoming) = mpsc::unbounded::<io::Result<TcpStream>>();
+    task::spawn(async move {
+        while let Some(stream) = listener.incoming().next().await {
+            if sender.unbounded_send(stream).is_err() {
+                break;
+            }
+        }
+    });
+    let mut server = Server::new(format!("http://{}", addr), incoming, service);
+    let shutdown = server.shutdown_handle();
+    let run = task::spawn(async move { server.run().await });
+    (addr, shutdown, run)
+}
+
+/// Open a connection, speaking HTTP/2 from the start.
+async fn connect(addr: SocketAddr) -> Client {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (client, conn) = h2::client::handshake(stream.compat()).await.unwrap();
+    task::spawn(async move {
+        let _ = conn.await;
+    });
+    client
+}
+
+/// Send a request, returning the status and body of the response.
+async fn send(client: &Client, method: &str, path: &str, body: Vec<u8>) -> (u16, String) {
+    let req = http::Request::builder()
+        .method(method)
+        .uri(format!("http://localhost{}", path))
+        .body(())
+        .unwrap();
+    let mut client = client.clone().ready().await.unwrap();
+    let (res, mut stream) = client.send_request(req, body.is_empty()).unwrap();
+    if !body.is_empty() {
+        stream.send_data(Bytes::from(body), true).unwrap();
+    }
+
+    let (head, mut body) = res.await.unwrap().into_parts();
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.unwrap();
+        body.flow_control().release_capacity(chunk.len()).unwrap();
+        data.extend_from_slice(&chunk);
+    }
+    (head.status.as_u16(), String::from_utf8(data).unwrap())
+}
+
+#[test]
+fn serves_prior_knowledge_requests() {
+    task::block_on(async {
+        let (addr, _shutdown, _run) = start(echo);
+        let client = connect(addr).await;
+
+        let (status, body) = send(&client, "GET", "/hello?a=b", Vec::new()).await;
+        assert_eq!(status, 200);
+        assert_eq!(body, "GET http://localhost/hello?a=b Some(Http2_0) 0");
+
+        let (status, body) = send(&client, "POST", "/echo", b"ping".to_vec()).await;
+        assert_eq!(status, 200);
+        assert_eq!(body, "POST http://localhost/echo Some(Http2_0) 4");
+    });
+}
+
+#[test]
+fn multiplexes_streams_over_one_connection() {
+    task::block_on(async {
+        // Every request waits until all of them have arrived, which only
+        // happens if they are served concurrently.
+        let (arrived, mut all_arrived) = mpsc::unbounded();
+        let (release, released) = futures::channel::oneshot::channel::<()>();
+        let released = released.shared();
+        let service = move |_req: Request| {
+            let arrived = arrived.clone();
+            let released = released.clone();
+            async move {
+                arrived.unbounded_send(()).unwrap();
+                let _ = released.await;
+                Ok::<_, http_service::Error>(Response::from("done"))
+            }
+        };
+        let (addr, _shutdown, _run) = start(service);
+        let client = connect(addr).await;
+
+        let requests = (0..10).map(|_| {
+            let client = client.clone();
+            task::spawn(async move { send(&client, "GET", "/", Vec::new()).await })
+        });
+        let requests: Vec<_> = requests.collect();
+        for _ in 0..10 {
+            all_arrived.next().await.unwrap();
+        }
+        release.send(()).unwrap();
+        for request in requests {
+            assert_eq!(request.await, (200, "done".to_owned()));
+        }
+    });
+}
+
+#[test]
+fn reads_bodies_larger_than_the_initial_window() {
+    task::block_on(async {
+        let (addr, _shutdown, _run) = start(echo);
+        let client = connect(addr).await;
+
+        // The client stalls unless the server releases the capacity it read.
+        let body = vec![b'x'; 1_000_000];
+        let send = send(&client, "PUT", "/", body);
+        let (status, body) = io::timeout(Duration::from_secs(10), async {
+            Ok::<_, io::Error>(send.await)
+        })
+        .await
+        .unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body, "PUT http://localhost/ Some(Http2_0) 1000000");
+    });
+}
+
+#[test]
+fn sends_goaway_on_shutdown_and_finishes_open_streams() {
+    task::block_on(async {
+        let (arrived, mut has_arrived) = mpsc::unbounded();
+        let (release, released) = futures::channel::oneshot::channel::<()>();
+        let released = released.shared();
+        let service = move |_req: Request| {
+            let arrived = arrived.clone();
+            let released = released.clone();
+            async move {
+                arrived.unbounded_send(()).unwrap();
+                let _ = released.await;
+                Ok::<_, http_service::Error>(Response::from("finished"))
+            }
+        };
+        let (addr, shutdown, run) = start(service);
+        let client = connect(addr).await;
+
+        let open = {
+            let client = client.clone();
+            task::spawn(async move { send(&client, "GET", "/", Vec::new()).await })
+        };
+        has_arrived.next().await.unwrap();
+        shutdown.shutdown();
+
+        // Once the GOAWAY frame arrives, the client refuses to open streams.
+        let goaway = async {
+            loop {
+                let req = http::Request::get("http://localhost/").body(()).unwrap();
+                if let Err(err) = client.clone().send_request(req, true) {
+                    assert!(err.is_go_away() && err.is_remote(), "{}", err);
+                    return Ok(());
+                }
+                task::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        io::timeout(Duration::from_secs(10), goaway).await.unwrap();
+
+        // The open stream still gets its response.
+        release.send(()).unwrap();
+        assert_eq!(open.await, (200, "finished".to_owned()));
+        io::timeout(Duration::from_secs(10), run).await.unwrap();
+    });
+}
+
+#[test]
+fn closes_connections_that_never_complete_the_handshake() {
+    task::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, incoming) = mpsc::unbounded::<io::Result<TcpStream>>();
+        let mut server = Server::new(format!("http://{}", addr), incoming, echo);
+        server.set_header_timeout(Some(Duration::from_millis(100)));
+        task::spawn(async move { server.run().await });
+
+        // The client connects but never sends the connection preface.
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        sender.unbounded_send(Ok(stream)).unwrap();
+        let mut buf = [0; 1024];
+        let read = async {
+            loop {
+                if client.read(&mut buf).await? == 0 {
+                    return Ok(());
+                }
+            }
+        };
+        io::timeout(Duration::from_secs(5), read).await.unwrap();
+    });
+}
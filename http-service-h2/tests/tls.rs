@@ -0,0 +1,86 @@
+//! Serving HTTP/2 over TLS on loopback, negotiated through ALPN.
+#![cfg(feature = "tls")]
+
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_std::io;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use futures_rustls::pki_types::pem::PemObject;
+use futures_rustls::pki_types::{CertificateDer, ServerName};
+use futures_rustls::rustls::crypto::ring;
+use futures_rustls::rustls::{ClientConfig, RootCertStore};
+use futures_rustls::TlsConnector;
+use http_service::{Request, Response};
+use http_service_h2::{Server, TlsConfig};
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+const CA: &[u8] = include_bytes!("certs/ca.pem");
+const CERT: &[u8] = include_bytes!("certs/localhost.pem");
+const KEY: &[u8] = include_bytes!("certs/localhost.key");
+
+/// Start a server answering with the URL and version of each request.
+fn start() -> SocketAddr {
+    let listener = task::block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sender, incoming) = mpsc::unbounded::<io::Result<TcpStream>>();
+    task::spawn(async move {
+        while let Some(stream) = listener.incoming().next().await {
+            if sender.unbounded_send(stream).is_err() {
+                break;
+            }
+        }
+    });
+    let service = |req: Request| async move {
+        let body = format!("{} {:?}", req.url(), req.version());
+        Ok::<_, http_service::Error>(Response::from(body))
+    };
+    let mut server = Server::new(format!("http://{}", addr), incoming, service);
+    server.set_tls(TlsConfig::from_pem(CERT, KEY).unwrap());
+    task::spawn(async move { server.run().await });
+    addr
+}
+
+#[test]
+fn negotiates_h2_through_alpn() {
+    task::block_on(async {
+        let addr = start();
+
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_slice_iter(CA) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let mut config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        let connector = TlsConnector::from(Arc::new(config));
+        let name = ServerName::try_from("localhost").unwrap();
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let stream = connector.connect(name, stream).await.unwrap();
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+
+        let (client, conn) = h2::client::handshake(stream.compat()).await.unwrap();
+        task::spawn(async move {
+            let _ = conn.await;
+        });
+        let req = http::Request::get("https://localhost/hello")
+            .body(())
+            .unwrap();
+        let mut client = client.ready().await.unwrap();
+        let (res, _) = client.send_request(req, true).unwrap();
+        let (head, mut body) = res.await.unwrap().into_parts();
+        assert_eq!(head.status, 200);
+        let mut data = Vec::new();
+        while let Some(chunk) = body.data().await {
+            data.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(data, b"https://localhost/hello Some(Http2_0)");
+    });
+}
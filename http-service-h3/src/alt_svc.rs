@@ -0,0 +1,88 @@
+//! Advertising HTTP/3 to clients of other servers.
+
+use std::time::Duration;
+
+use http_service::{HttpService, Layer, MapResponse, Response, ServiceExt};
+use http_types::headers::HeaderName;
+
+type AddHeader = Box<dyn Fn(Response) -> Response + Send + Sync>;
+
+/// A layer that tells clients that HTTP/3 is available, through the
+/// `Alt-Svc` header.
+///
+/// Clients first reach a server over HTTP/1 or HTTP/2, and only switch to
+/// HTTP/3 once they learn about it from this header. Apply the layer to the
+/// service served by those servers. Responses that already have an `Alt-Svc`
+/// header are left unchanged.
+///
+/// # Examples
+///
+/// ```no_run
+/// use http_service::{Request, Response, ServiceBuilder};
+/// use http_service_h3::AltSvc;
+///
+/// let service = ServiceBuilder::new()
+///     .layer(AltSvc::new(443))
+///     .service(|_req: Request| async {
+///         Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+///     });
+///
+/// async_std::task::block_on(async move {
+///     let addr = "127.0.0.1:443".parse()?;
+///     http_service_h1::serve(service, addr).await?;
+///     Ok::<(), Box<dyn std::error::Error>>(())
+/// })?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct AltSvc {
+    port: u16,
+    max_age: Duration,
+}
+
+impl AltSvc {
+    /// Advertise HTTP/3 on the given UDP port, on the same host.
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            max_age: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    /// Set how long clients may remember the advertisement.
+    ///
+    /// Defaults to 24 hours.
+    pub fn set_max_age(&mut self, max_age: Duration) -> &mut Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// The value of the `Alt-Svc` header.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use http_service_h3::AltSvc;
+    ///
+    /// assert_eq!(AltSvc::new(443).header_value(), r#"h3=":443"; ma=86400"#);
+    /// ```
+    pub fn header_value(&self) -> String {
+        format!(r#"h3=":{}"; ma={}"#, self.port, self.max_age.as_secs())
+    }
+}
+
+impl<S: HttpService> Layer<S> for AltSvc {
+    type Service = MapResponse<S, AddHeader>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let name: HeaderName = "alt-svc".parse().unwrap();
+        let value = self.header_value();
+        inner.map_response(Box::new(move |mut res: Response| {
+            if res.header(&name).is_none() {
+                // The name and value are both valid, so this can't fail.
+                let _ = res.insert_header(name.clone(), value.as_str());
+            }
+            res
+        }) as AddHeader)
+    }
+}
@@ -0,0 +1,187 @@
+//! Serving individual connections.
+
+use std::cmp;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::future::{self, Either};
+use h3::server::RequestResolver;
+use http_service::{ConnectionInfo, Error, HttpService, Response};
+use http_service_server::error::{ErrorHandler, ErrorResponse};
+use http_service_server::shutdown::{Signal, Tracker};
+use http_types::Version;
+use quinn::VarInt;
+
+use crate::convert;
+
+type H3Connection = h3::server::Connection<h3_quinn::Connection, Bytes>;
+
+/// The error code closing a connection that is no longer needed.
+const H3_NO_ERROR: VarInt = VarInt::from_u32(0x100);
+
+/// The least time a connection is kept open after its last response.
+const MIN_LINGER: Duration = Duration::from_millis(100);
+
+/// Server settings needed by each connection.
+pub(crate) struct ConnConfig {
+    pub(crate) addr: String,
+    pub(crate) local_addr: Option<SocketAddr>,
+    pub(crate) shutdown: Signal,
+    pub(crate) force_close: Signal,
+    pub(crate) error_handler: ErrorHandler,
+    pub(crate) error_response: ErrorResponse,
+}
+
+impl ConnConfig {
+    /// Report an error from the service, and turn it into a response.
+    fn error_response(&self, err: Error) -> Response {
+        (self.error_handler)(&err);
+        (self.error_response)(&err)
+    }
+}
+
+/// Accept a new connection, completing the QUIC handshake.
+pub(crate) async fn accept<S>(
+    service: Arc<S>,
+    incoming: quinn::Incoming,
+    config: Arc<ConnConfig>,
+) -> Result<(), Error>
+where
+    S: HttpService,
+    <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+    <S as HttpService>::Connection: Sync,
+{
+    let quic = incoming.await.map_err(|err| {
+        log::debug!("error during QUIC handshake: {}", err);
+        err
+    })?;
+
+    let mut info = ConnectionInfo::new();
+    info.set_peer_addr(Some(quic.remote_address()));
+    info.set_local_addr(config.local_addr);
+    info.set_secure(true);
+    info.set_protocol(Some(Version::Http3_0));
+
+    let mut h3: H3Connection = h3::server::Connection::new(h3_quinn::Connection::new(quic.clone()))
+        .await
+        .map_err(|err| {
+            log::debug!("error during HTTP/3 handshake: {}", err);
+            err
+        })?;
+
+    // If the service refuses the connection, the error is sent in response to
+    // every request the client has already sent, and the connection is closed.
+    let conn = match service.connect(&info).await {
+        Ok(conn) => Ok(conn),
+        Err(err) => {
+            let err = err.into();
+            (config.error_handler)(&err);
+            h3.shutdown(0).await?;
+            Err(Arc::new(err))
+        }
+    };
+
+    // Once a shutdown starts, a GOAWAY frame tells the client to stop sending
+    // requests. The connection closes once the requests in progress are
+    // finished, or when it is forcibly closed.
+    let requests = Arc::new(Tracker::default());
+    let mut shutdown = config.shutdown.wait();
+    let mut force_close = config.force_close.wait();
+    let mut draining = false;
+    let mut forced = false;
+    loop {
+        let next = Box::pin(h3.accept());
+        let stop = async {
+            if draining {
+                let idle = requests.wait_idle();
+                forced = matches!(
+                    future::select(&mut force_close, idle).await,
+                    Either::Left(_)
+                );
+            } else {
+                (&mut shutdown).await;
+            }
+        };
+        futures::pin_mut!(stop);
+        let next = match future::select(next, stop).await {
+            Either::Left((next, _)) => Some(next),
+            Either::Right(_) => None,
+        };
+        let next = match next {
+            Some(next) => next,
+            None if draining => break,
+            None => {
+                h3.shutdown(0).await?;
+                draining = true;
+                continue;
+            }
+        };
+        let resolver = match next {
+            Ok(Some(resolver)) => resolver,
+            Ok(None) => break,
+            Err(err) => {
+                log::debug!("error serving connection: {}", err);
+                return Err(err.into());
+            }
+        };
+
+        // Requests are served concurrently, while this task keeps driving the
+        // connection.
+        let token = requests.track();
+        let respond = respond(service.clone(), conn.clone(), resolver, config.clone());
+        async_std::task::spawn(async move {
+            let _token = token;
+            respond.await
+        });
+    }
+
+    // Closing the connection discards response data the client hasn't
+    // received yet, so give it a few round trips, unless it hangs up first.
+    if !forced {
+        let linger = cmp::max(quic.rtt() * 4, MIN_LINGER);
+        let linger = Box::pin(async_std::task::sleep(linger));
+        future::select(Box::pin(quic.closed()), linger).await;
+    }
+    quic.close(H3_NO_ERROR, b"");
+    Ok(())
+}
+
+/// Respond to a single request.
+async fn respond<S>(
+    service: Arc<S>,
+    conn: Result<S::Connection, Arc<Error>>,
+    resolver: RequestResolver<h3_quinn::Connection, Bytes>,
+    config: Arc<ConnConfig>,
+) where
+    S: HttpService,
+{
+    let (req, stream) = match resolver.resolve_request().await {
+        Ok(req) => req,
+        Err(err) => {
+            log::debug!("error reading request: {}", err);
+            return;
+        }
+    };
+    let (mut send, recv) = stream.split();
+
+    let res = match (convert::request(req, recv, &config.addr), conn) {
+        (Ok(req), Ok(conn)) => match service.respond(conn, req).await {
+            Ok(res) => res,
+            Err(err) => config.error_response(err.into()),
+        },
+        (Ok(_), Err(err)) => (config.error_response)(&err),
+        (Err(err), _) => config.error_response(err),
+    };
+
+    let sent = async move {
+        let (head, body) = convert::response(res)?;
+        send.send_response(head).await?;
+        convert::send_body(&mut send, body).await
+    };
+    if let Err(err) = sent.await {
+        log::debug!("error sending response: {}", err);
+    }
+}
@@ -0,0 +1,85 @@
+//! Conversions between `http-types` and the `http` types used by `h3`.
+
+use std::cmp;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use async_std::io::{self, BufReader};
+use async_std::prelude::*;
+use bytes::{Buf, Bytes};
+use h3::server::RequestStream;
+use http_service::{Body, Error, Request};
+use http_service_server::convert::{self, CHUNK_SIZE};
+use http_types::Version;
+
+pub(crate) use http_service_server::convert::response;
+
+type RecvStream = RequestStream<h3_quinn::RecvStream, Bytes>;
+type SendStream = RequestStream<h3_quinn::SendStream<Bytes>, Bytes>;
+
+/// Convert a request received by `h3`.
+///
+/// `base` is used for requests that carry no `:authority`.
+pub(crate) fn request(
+    req: http::Request<()>,
+    body: RecvStream,
+    base: &str,
+) -> Result<Request, Error> {
+    let (parts, ()) = req.into_parts();
+    let len = convert::content_length(&parts);
+    let body = BufReader::new(RecvBody {
+        stream: Mutex::new(body),
+        chunk: Bytes::new(),
+    });
+    let body = Body::from_reader(body, len);
+    convert::request(parts, Some(body), Version::Http3_0, base)
+}
+
+/// Send a response body, then finish the stream.
+///
+/// QUIC applies flow control to every chunk as it is sent.
+pub(crate) async fn send_body(stream: &mut SendStream, mut body: Body) -> Result<(), Error> {
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let n = body.read(&mut buf).await?;
+        if n == 0 {
+            stream.finish().await?;
+            return Ok(());
+        }
+        stream.send_data(Bytes::copy_from_slice(&buf[..n])).await?;
+    }
+}
+
+/// A request body received by `h3`.
+///
+/// The stream is only ever used by one task; the lock makes it `Sync`, as
+/// `Body` requires.
+struct RecvBody {
+    stream: Mutex<RecvStream>,
+    chunk: Bytes,
+}
+
+impl io::Read for RecvBody {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        while self.chunk.is_empty() {
+            let data = self.stream.lock().unwrap().poll_recv_data(cx);
+            match data {
+                Poll::Ready(Ok(Some(mut data))) => {
+                    self.chunk = data.copy_to_bytes(data.remaining());
+                }
+                Poll::Ready(Ok(None)) => return Poll::Ready(Ok(0)),
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(io::Error::other(err))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let n = cmp::min(buf.len(), self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk[..n]);
+        self.chunk.advance(n);
+        Poll::Ready(Ok(n))
+    }
+}
@@ -0,0 +1,242 @@
+//! `HttpService` server that uses h3 over QUIC as backend.
+//!
+//! Clients usually discover HTTP/3 through the `Alt-Svc` header of responses
+//! sent over HTTP/1 or HTTP/2, so an HTTP/3 server is normally run alongside
+//! one of those, with the [`AltSvc`](struct.AltSvc.html) layer applied to its
+//! service.
+
+#![forbid(future_incompatible, rust_2018_idioms)]
+#![deny(missing_debug_implementations, nonstandard_style)]
+#![warn(missing_docs, missing_doc_code_examples)]
+#![cfg_attr(test, deny(warnings))]
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use futures::future::{self, Either};
+use http_service::{Error, HttpService, Response};
+use http_service_server::error::{self, ErrorHandler, ErrorResponse};
+use http_service_server::shutdown::{Signal, Tracker};
+
+use async_std::io;
+use async_std::net::SocketAddr;
+use async_std::sync::Arc;
+
+mod alt_svc;
+mod conn;
+mod convert;
+mod tls;
+
+pub use alt_svc::AltSvc;
+pub use http_service_server::shutdown::ShutdownHandle;
+pub use tls::TlsConfig;
+
+use conn::{accept, ConnConfig};
+
+/// A listening HTTP server that accepts connections in HTTP/3, over QUIC.
+///
+/// Every connection is passed to `HttpService::connect` once, and the
+/// requests multiplexed over it are served concurrently.
+pub struct Server<S: HttpService> {
+    endpoint: quinn::Endpoint,
+    service: Arc<S>,
+    addr: String,
+    shutdown: Signal,
+    force_close: Signal,
+    connections: Arc<Tracker>,
+    shutdown_timeout: Duration,
+    error_handler: ErrorHandler,
+    error_response: ErrorResponse,
+}
+
+impl<S: HttpService> fmt::Debug for Server<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Server")
+            .field("addr", &self.addr)
+            .field("shutdown", &self.shutdown)
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .finish()
+    }
+}
+
+impl<S> Server<S>
+where
+    S: HttpService,
+    <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+    <S as HttpService>::Connection: Sync,
+{
+    /// Create a new server, listening on the given UDP address.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use http_service::{Request, Response};
+    /// use http_service_h3::{Server, TlsConfig};
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let tls = TlsConfig::from_pem_files("cert.pem", "key.pem")?;
+    ///     let mut server = Server::bind("127.0.0.1:443".parse()?, tls, service)?;
+    ///     server.run().await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn bind(addr: SocketAddr, tls: TlsConfig, service: S) -> io::Result<Self> {
+        let config = quinn::ServerConfig::with_crypto(tls.quic_config());
+        let socket = std::net::UdpSocket::bind(addr)?;
+        let endpoint = quinn::Endpoint::new(
+            quinn::EndpointConfig::default(),
+            Some(config),
+            socket,
+            Arc::new(quinn::SmolRuntime),
+        )?;
+        let addr = format!("https://{}", endpoint.local_addr()?);
+        Ok(Server {
+            endpoint,
+            service: Arc::new(service),
+            addr,
+            shutdown: Signal::new(),
+            force_close: Signal::new(),
+            connections: Arc::new(Tracker::default()),
+            shutdown_timeout: Duration::from_secs(30),
+            error_handler: Arc::new(error::log_error),
+            error_response: Arc::new(error::default_error_response),
+        })
+    }
+
+    /// Get the address the server is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+
+    /// Get a layer advertising this server to clients of other servers.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use async_std::net::TcpListener;
+    /// use http_service::{Request, Response, ServiceBuilder};
+    /// use http_service_h3::TlsConfig;
+    ///
+    /// let service = |_req: Request| async {
+    ///     Ok::<Response, async_std::io::Error>(Response::from("Hello World"))
+    /// };
+    ///
+    /// async_std::task::block_on(async move {
+    ///     let tls = TlsConfig::from_pem_files("cert.pem", "key.pem")?;
+    ///     let mut h3 = http_service_h3::Server::bind("127.0.0.1:8443".parse()?, tls, service)?;
+    ///
+    ///     let service = ServiceBuilder::new().layer(h3.alt_svc()?).service(service);
+    ///     let listener = TcpListener::bind("127.0.0.1:8443").await?;
+    ///     let addr = format!("http://{}", listener.local_addr()?);
+    ///     let mut h1 = http_service_h1::Server::new(addr, listener.incoming(), service);
+    ///
+    ///     futures::future::try_join(h3.run(), h1.run()).await?;
+    ///     Ok::<(), Box<dyn std::error::Error>>(())
+    /// })?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn alt_svc(&self) -> io::Result<AltSvc> {
+        Ok(AltSvc::new(self.local_addr()?.port()))
+    }
+
+    /// Get a handle that can be used to shut the server down.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(self.shutdown.clone())
+    }
+
+    /// Set how long a shutdown waits for in-flight requests to finish.
+    ///
+    /// Connections still open when the timeout expires are closed. Defaults to
+    /// 30 seconds.
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) {
+        self.shutdown_timeout = timeout;
+    }
+
+    /// Set the callback that is told about errors returned by the service.
+    ///
+    /// This is called with every connection and response error, before it is
+    /// turned into a response. Defaults to logging the error.
+    pub fn set_error_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&Error) + Send + Sync + 'static,
+    {
+        self.error_handler = Arc::new(handler);
+    }
+
+    /// Set how errors returned by the service are turned into responses.
+    ///
+    /// Defaults to an empty response with the status code carried by the
+    /// error, which is `500 Internal Server Error` unless set otherwise.
+    pub fn set_error_response<F>(&mut self, f: F)
+    where
+        F: Fn(&Error) -> Response + Send + Sync + 'static,
+    {
+        self.error_response = Arc::new(f);
+    }
+
+    /// Run the server until it is shut down.
+    ///
+    /// Once a shutdown is requested through a [`ShutdownHandle`], new
+    /// connections are refused, and every connection is sent a GOAWAY frame.
+    /// Connections are closed once their requests in progress are finished.
+    /// This method returns when all connections are closed, or when the
+    /// shutdown timeout expires.
+    pub async fn run(&mut self) -> io::Result<()> {
+        let config = Arc::new(ConnConfig {
+            addr: self.addr.clone(),
+            local_addr: self.endpoint.local_addr().ok(),
+            shutdown: self.shutdown.clone(),
+            force_close: self.force_close.clone(),
+            error_handler: self.error_handler.clone(),
+            error_response: self.error_response.clone(),
+        });
+        let mut shutdown = self.shutdown.wait();
+        loop {
+            let next = Box::pin(self.endpoint.accept());
+            let incoming = match future::select(&mut shutdown, next).await {
+                Either::Left(_) => break,
+                Either::Right((Some(incoming), _)) => incoming,
+                Either::Right((None, _)) => return Ok(()),
+            };
+            let token = self.connections.track();
+            let accept = accept(self.service.clone(), incoming, config.clone());
+            async_std::task::spawn(async move {
+                let _token = token;
+                accept.await
+            });
+        }
+        self.endpoint.set_server_config(None);
+
+        let connections = self.connections.clone();
+        let drained = async_std::future::timeout(self.shutdown_timeout, async move {
+            connections.wait_idle().await;
+        });
+        if drained.await.is_err() {
+            self.force_close.fire();
+        }
+
+        // Let the peers know that the connections are closed.
+        self.endpoint.close(0u32.into(), b"");
+        self.endpoint.wait_idle().await;
+
+        Ok(())
+    }
+}
+
+/// Serve the given `HttpService` over HTTP/3 at the given UDP address, using `h3` and `quinn` as
+/// backend, and return a `Future` that can be `await`ed on.
+pub async fn serve<S: HttpService>(service: S, addr: SocketAddr, tls: TlsConfig) -> io::Result<()>
+where
+    <<S as HttpService>::ResponseFuture as Future>::Output: Send,
+    <S as HttpService>::Connection: Sync,
+{
+    let mut server = Server::bind(addr, tls, service)?;
+
+    server.run().await
+}
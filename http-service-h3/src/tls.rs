@@ -0,0 +1,77 @@
+//! TLS settings, using rustls.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_std::io;
+use http_service_server::error::invalid_data;
+use http_service_server::tls::parse_pem;
+use quinn::crypto::rustls::QuicServerConfig;
+use quinn::rustls::crypto::ring;
+use quinn::rustls::version::TLS13;
+use quinn::rustls::ServerConfig;
+
+/// The ALPN protocol ID of HTTP/3.
+const ALPN_H3: &[u8] = b"h3";
+
+/// TLS settings for a [`Server`](struct.Server.html).
+///
+/// QUIC always uses TLS 1.3, and clients pick HTTP/3 through ALPN.
+///
+/// # Examples
+///
+/// ```no_run
+/// use http_service_h3::TlsConfig;
+///
+/// let tls = TlsConfig::from_pem_files("cert.pem", "key.pem")?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct TlsConfig {
+    config: Arc<QuicServerConfig>,
+}
+
+impl TlsConfig {
+    /// Use a single certificate chain and private key, read from PEM files.
+    pub fn from_pem_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> io::Result<Self> {
+        let cert = std::fs::read(cert)?;
+        let key = std::fs::read(key)?;
+        Self::from_pem(&cert, &key)
+    }
+
+    /// Use a single certificate chain and private key, in PEM format.
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> io::Result<Self> {
+        let (certs, key) = parse_pem(cert, key)?;
+        let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_protocol_versions(&[&TLS13])
+            .map_err(invalid_data)?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(invalid_data)?;
+        config.alpn_protocols = vec![ALPN_H3.to_vec()];
+        Self::from_rustls(Arc::new(config))
+    }
+
+    /// Use an existing rustls configuration.
+    ///
+    /// The configuration must support TLS 1.3, and should advertise `h3`
+    /// through ALPN.
+    pub fn from_rustls(config: Arc<ServerConfig>) -> io::Result<Self> {
+        let config = QuicServerConfig::try_from(config).map_err(invalid_data)?;
+        Ok(Self {
+            config: Arc::new(config),
+        })
+    }
+
+    pub(crate) fn quic_config(&self) -> Arc<QuicServerConfig> {
+        self.config.clone()
+    }
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConfig").finish()
+    }
+}
@@ -0,0 +1,182 @@
+//! Serving HTTP/3 over loopback UDP with a certificate signed by a test CA.
+
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_std::task;
+use bytes::{Buf, Bytes};
+use h3::client::SendRequest;
+use http_service::{Request, Response, ServiceBuilder};
+use http_service_h3::{Server, TlsConfig};
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::rustls::crypto::ring;
+use quinn::rustls::pki_types::pem::PemObject;
+use quinn::rustls::pki_types::CertificateDer;
+use quinn::rustls::version::TLS13;
+use quinn::rustls::{ClientConfig, RootCertStore};
+
+const CA: &[u8] = include_bytes!("certs/ca.pem");
+const CERT: &[u8] = include_bytes!("certs/localhost.pem");
+const KEY: &[u8] = include_bytes!("certs/localhost.key");
+
+type Client = SendRequest<h3_quinn::OpenStreams, Bytes>;
+
+/// Answer with the method, URL, version and body of each request.
+async fn echo(req: Request) -> Result<Response, http_service::Error> {
+    let head = format!("{} {} {:?} ", req.method(), req.url(), req.version());
+    let body = req.body_string().await?;
+    Ok(Response::from(head + &body))
+}
+
+/// Start a server on loopback, returning its address, a handle to shut it
+/// down, and the task running it.
+fn start() -> (
+    SocketAddr,
+    http_service_h3::ShutdownHandle,
+    task::JoinHandle<()>,
+) {
+    let tls = TlsConfig::from_pem(CERT, KEY).unwrap();
+    let mut server = Server::bind("127.0.0.1:0".parse().unwrap(), tls, echo).unwrap();
+    let addr = server.local_addr().unwrap();
+    let shutdown = server.shutdown_handle();
+    let run = task::spawn(async move { server.run().await.unwrap() });
+    (addr, shutdown, run)
+}
+
+/// Open an HTTP/3 connection that trusts the test CA only.
+async fn connect(addr: SocketAddr) -> Client {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(CA) {
+        roots.add(cert.unwrap()).unwrap();
+    }
+    let mut tls = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_protocol_versions(&[&TLS13])
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+    let config = quinn::ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls).unwrap()));
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut endpoint = quinn::Endpoint::new(
+        quinn::EndpointConfig::default(),
+        None,
+        socket,
+        Arc::new(quinn::SmolRuntime),
+    )
+    .unwrap();
+    endpoint.set_default_client_config(config);
+    let quic = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+
+    let (mut driver, client) = h3::client::new(h3_quinn::Connection::new(quic))
+        .await
+        .unwrap();
+    task::spawn(async move {
+        let _endpoint = endpoint;
+        futures::future::poll_fn(|cx| driver.poll_close(cx)).await;
+    });
+    client
+}
+
+/// Send a request, returning the status and body of the response.
+async fn send(client: &mut Client, method: &str, path: &str, body: &str) -> (u16, String) {
+    let req = http::Request::builder()
+        .method(method)
+        .uri(format!("https://localhost{}", path))
+        .body(())
+        .unwrap();
+    let mut stream = client.send_request(req).await.unwrap();
+    if !body.is_empty() {
+        stream
+            .send_data(Bytes::copy_from_slice(body.as_bytes()))
+            .await
+            .unwrap();
+    }
+    stream.finish().await.unwrap();
+
+    let res = stream.recv_response().await.unwrap();
+    let mut body = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+        body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+    (res.status().as_u16(), String::from_utf8(body).unwrap())
+}
+
+#[test]
+fn serves_requests() {
+    task::block_on(async {
+        let (addr, _shutdown, _run) = start();
+        let mut client = connect(addr).await;
+
+        let (status, body) = send(&mut client, "GET", "/hello?a=b", "").await;
+        assert_eq!(status, 200);
+        assert_eq!(body, "GET https://localhost/hello?a=b Some(Http3_0) ");
+
+        let (status, body) = send(&mut client, "POST", "/echo", "ping").await;
+        assert_eq!(status, 200);
+        assert_eq!(body, "POST https://localhost/echo Some(Http3_0) ping");
+    });
+}
+
+#[test]
+fn multiplexes_requests_over_one_connection() {
+    task::block_on(async {
+        let (addr, _shutdown, _run) = start();
+        let client = connect(addr).await;
+
+        let requests = (0..10).map(|i| {
+            let mut client = client.clone();
+            async move {
+                let body = "x".repeat(i * 10_000);
+                let (status, echoed) = send(&mut client, "PUT", "/", &body).await;
+                assert_eq!(status, 200);
+                assert!(echoed.ends_with(&body));
+            }
+        });
+        futures::future::join_all(requests).await;
+    });
+}
+
+#[test]
+fn shuts_down() {
+    task::block_on(async {
+        let (addr, shutdown, run) = start();
+        let mut client = connect(addr).await;
+        assert_eq!(send(&mut client, "GET", "/", "").await.0, 200);
+
+        shutdown.shutdown();
+        run.await;
+    });
+}
+
+#[test]
+fn is_advertised_by_an_h1_server() {
+    task::block_on(async {
+        let tls = TlsConfig::from_pem(CERT, KEY).unwrap();
+        let h3 = Server::bind("127.0.0.1:0".parse().unwrap(), tls, echo).unwrap();
+        let port = h3.local_addr().unwrap().port();
+
+        let service = ServiceBuilder::new()
+            .layer(h3.alt_svc().unwrap())
+            .service(echo);
+        let mut h1 = http_service_h1::ServerBuilder::new()
+            .bind("127.0.0.1:0".parse().unwrap(), service)
+            .unwrap();
+        let h1_addr = h1.local_addr();
+        task::spawn(async move { h1.run().await });
+
+        let stream = async_std::net::TcpStream::connect(h1_addr).await.unwrap();
+        let url = http_types::Url::parse(&format!("http://{}/", h1_addr)).unwrap();
+        let req = http_types::Request::new(http_types::Method::Get, url);
+        let res = async_h1::connect(stream, req).await.unwrap();
+        let alt_svc = res.header(&"alt-svc".parse().unwrap()).unwrap();
+        assert!(
+            alt_svc[0]
+                .as_str()
+                .starts_with(&format!("h3=\":{}\"", port)),
+            "{:?}",
+            alt_svc
+        );
+    });
+}
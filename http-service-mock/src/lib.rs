@@ -5,30 +5,84 @@
 #![warn(missing_docs, missing_doc_code_examples)]
 #![cfg_attr(test, deny(warnings))]
 
+use std::net::SocketAddr;
+
 use futures::{executor::block_on, prelude::*};
-use http_service::{HttpService, Request, Response};
+use http_service::{ConnectionInfo, HttpService, LocalAddr, PeerAddr, Request, Response};
 
 /// A harness for sending simulated requests to an HTTP service
+///
+/// Like a server, it inserts the peer and local addresses of the simulated
+/// connection into every request, as `PeerAddr` and `LocalAddr` extensions.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{PeerAddr, Request, Response};
+/// use http_types::{Method, Url};
+///
+/// let service = |req: Request| async move {
+///     let peer = req.local().get::<PeerAddr>().unwrap().addr();
+///     Ok::<_, http_service::Error>(Response::from(peer.to_string()))
+/// };
+///
+/// let mut server = http_service_mock::make_server(service)?;
+/// server.set_peer_addr(Some("192.0.2.1:4000".parse()?));
+/// let req = Request::new(Method::Get, Url::parse("http://localhost/")?);
+/// let mut res = server.simulate(req)?;
+/// let body = futures::executor::block_on(res.body_string())?;
+/// assert_eq!(body, "192.0.2.1:4000");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
 #[derive(Debug)]
 pub struct TestBackend<T: HttpService> {
     service: T,
     connection: T::Connection,
+    peer_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
 }
 
 impl<T: HttpService> TestBackend<T> {
-    fn wrap(service: T) -> Result<Self, <T::ConnectionFuture as TryFuture>::Error> {
-        let connection = block_on(service.connect().into_future())?;
+    fn wrap(
+        service: T,
+        info: &ConnectionInfo,
+    ) -> Result<Self, <T::ConnectionFuture as TryFuture>::Error> {
+        let connection = block_on(service.connect(info).into_future())?;
         Ok(Self {
             service,
             connection,
+            peer_addr: info.peer_addr(),
+            local_addr: info.local_addr(),
         })
     }
 
+    /// Set the peer address of the simulated requests.
+    ///
+    /// Defaults to the peer address of the connection the server was made
+    /// with. The service isn't told of the change through `connect`.
+    pub fn set_peer_addr(&mut self, addr: Option<SocketAddr>) {
+        self.peer_addr = addr;
+    }
+
+    /// Set the local address of the simulated requests.
+    ///
+    /// Defaults to the local address of the connection the server was made
+    /// with. The service isn't told of the change through `connect`.
+    pub fn set_local_addr(&mut self, addr: Option<SocketAddr>) {
+        self.local_addr = addr;
+    }
+
     /// Send a request to the simulated server
     pub fn simulate(
         &mut self,
-        req: Request,
+        mut req: Request,
     ) -> Result<Response, <T::ResponseFuture as TryFuture>::Error> {
+        if let Some(addr) = self.peer_addr {
+            req.local_mut().insert(PeerAddr::new(addr));
+        }
+        if let Some(addr) = self.local_addr {
+            req.local_mut().insert(LocalAddr::new(addr));
+        }
         block_on(
             self.service
                 .respond(self.connection.clone(), req)
@@ -41,5 +95,14 @@ impl<T: HttpService> TestBackend<T> {
 pub fn make_server<T: HttpService>(
     service: T,
 ) -> Result<TestBackend<T>, <T::ConnectionFuture as TryFuture>::Error> {
-    TestBackend::wrap(service)
+    TestBackend::wrap(service, &ConnectionInfo::new())
+}
+
+/// Construct a simulated http server from the given service, connecting with
+/// the given connection information.
+pub fn make_server_with_info<T: HttpService>(
+    service: T,
+    info: &ConnectionInfo,
+) -> Result<TestBackend<T>, <T::ConnectionFuture as TryFuture>::Error> {
+    TestBackend::wrap(service, info)
 }
@@ -0,0 +1,149 @@
+//! Accepting connections.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::io;
+use async_std::prelude::*;
+use async_std::stream::Stream;
+use futures::future::{self, Either};
+
+use crate::error::{AcceptErrorAction, AcceptErrorHandler};
+use crate::limit::{LimitAction, Limiter, Permit};
+use crate::shutdown::{Signal, Tracker};
+
+/// The first wait after an accept error that calls for a backoff.
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+
+/// The settings of the listener a connection was accepted from.
+pub trait ListenerConfig {
+    /// The address of the listener, for logging.
+    fn addr(&self) -> &str;
+}
+
+impl<T: ListenerConfig + ?Sized> ListenerConfig for Arc<T> {
+    fn addr(&self) -> &str {
+        (**self).addr()
+    }
+}
+
+/// The settings of a loop accepting connections.
+#[derive(Clone)]
+pub struct Acceptor {
+    /// Stops the loop once fired.
+    pub shutdown: Signal,
+    /// Limits the number of open connections.
+    pub connection_limit: Option<Arc<Limiter>>,
+    /// What to do with connections over the limit.
+    pub connection_action: LimitAction,
+    /// Decides how to react to errors accepting connections.
+    pub error_handler: AcceptErrorHandler,
+    /// The longest wait before accepting again after an error.
+    pub max_backoff: Duration,
+}
+
+impl fmt::Debug for Acceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Acceptor")
+            .field("shutdown", &self.shutdown)
+            .field("connection_limit", &self.connection_limit)
+            .field("connection_action", &self.connection_action)
+            .field("max_backoff", &self.max_backoff)
+            .finish()
+    }
+}
+
+/// Why a loop stopped accepting connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stopped {
+    /// A shutdown was requested.
+    Shutdown,
+    /// The listeners ran out of connections.
+    Closed,
+}
+
+impl Acceptor {
+    /// Accept connections until shut down, passing each to `serve` along with
+    /// the settings of the listener it came from.
+    ///
+    /// Only errors the accept error handler deems fatal are returned.
+    pub async fn run<I, C, T, F>(&self, mut incoming: I, mut serve: F) -> io::Result<Stopped>
+    where
+        I: Stream<Item = (C, io::Result<T>)> + Unpin,
+        C: ListenerConfig,
+        F: FnMut(T, C, Option<Permit>),
+    {
+        let mut shutdown = self.shutdown.wait();
+        let mut backoff = MIN_ACCEPT_BACKOFF;
+        loop {
+            // When waiting for room, connections stay in the listeners'
+            // backlogs until one is closed.
+            let permit = match (&self.connection_limit, self.connection_action) {
+                (Some(limit), LimitAction::Wait) => {
+                    match future::select(&mut shutdown, limit.acquire()).await {
+                        Either::Left(_) => return Ok(Stopped::Shutdown),
+                        Either::Right((permit, _)) => Some(permit),
+                    }
+                }
+                _ => None,
+            };
+            let (config, stream) = match future::select(&mut shutdown, incoming.next()).await {
+                Either::Left(_) => return Ok(Stopped::Shutdown),
+                Either::Right((Some((config, Ok(stream))), _)) => {
+                    backoff = MIN_ACCEPT_BACKOFF;
+                    (config, stream)
+                }
+                Either::Right((Some((config, Err(err))), _)) => {
+                    let addr = config.addr();
+                    match (self.error_handler)(&err) {
+                        AcceptErrorAction::Continue => {
+                            log::debug!("error accepting connection on {}: {}", addr, err);
+                        }
+                        AcceptErrorAction::Backoff => {
+                            log::error!(
+                                "error accepting connection on {}, retrying in {:?}: {}",
+                                addr,
+                                backoff,
+                                err
+                            );
+                            let sleep = Box::pin(async_std::task::sleep(backoff));
+                            if let Either::Left(_) = future::select(&mut shutdown, sleep).await {
+                                return Ok(Stopped::Shutdown);
+                            }
+                            backoff = (backoff * 2).min(self.max_backoff);
+                        }
+                        AcceptErrorAction::Fatal => {
+                            log::error!("fatal error accepting connection on {}: {}", addr, err);
+                            return Err(err);
+                        }
+                    }
+                    continue;
+                }
+                Either::Right((None, _)) => return Ok(Stopped::Closed),
+            };
+            let permit = match (permit, &self.connection_limit) {
+                (None, Some(limit)) => match limit.try_acquire() {
+                    Some(permit) => Some(permit),
+                    None => {
+                        log::debug!("too many connections, closing the new one");
+                        continue;
+                    }
+                },
+                (permit, _) => permit,
+            };
+            serve(stream, config, permit);
+        }
+    }
+}
+
+/// Wait for the tracked connections to close, and force them closed if they
+/// are still open once the timeout expires.
+pub async fn drain(connections: Arc<Tracker>, timeout: Duration, force_close: &Signal) {
+    let drained = async_std::future::timeout(timeout, async move {
+        connections.wait_idle().await;
+    });
+    if drained.await.is_err() {
+        force_close.fire();
+    }
+}
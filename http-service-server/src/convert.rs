@@ -0,0 +1,88 @@
+//! Conversions between `http-types` and the `http` types used by `h2` and
+//! `h3`.
+
+use http_service::{Body, Error, Request, Response};
+use http_types::headers::{CONTENT_LENGTH, CONTENT_TYPE};
+use http_types::{Method, StatusCode, Url, Version};
+
+/// The most data read from a response body before it is sent.
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Headers that are specific to HTTP/1 connections, and are forbidden in
+/// HTTP/2 and HTTP/3 messages.
+const CONNECTION_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Get the length of a request body, as given by its `Content-Length`.
+pub fn content_length(parts: &http::request::Parts) -> Option<usize> {
+    parts
+        .headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|len| len.to_str().ok())
+        .and_then(|len| len.parse().ok())
+}
+
+/// Convert a received request head, and its body if it has one.
+///
+/// `base` is used for requests that carry no `:authority`, such as requests
+/// from clients that only send a `Host` header.
+pub fn request(
+    parts: http::request::Parts,
+    body: Option<Body>,
+    version: Version,
+    base: &str,
+) -> Result<Request, Error> {
+    let method: Method = parts.method.as_str().parse()?;
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    let url = match (parts.uri.scheme_str(), parts.uri.authority()) {
+        (Some(scheme), Some(authority)) => format!("{}://{}{}", scheme, authority, path),
+        _ => format!("{}{}", base.trim_end_matches('/'), path),
+    };
+    let url = Url::parse(&url).map_err(|err| Error::new(StatusCode::BadRequest, err))?;
+
+    let mut req = Request::new(method, url);
+    req.set_version(Some(version));
+    if let Some(body) = body {
+        req.set_body(body);
+    }
+
+    // Setting the body sets a content type, which the request's own headers
+    // replace.
+    req.remove_header(&CONTENT_TYPE);
+    for (name, value) in &parts.headers {
+        let value = value
+            .to_str()
+            .map_err(|err| Error::new(StatusCode::BadRequest, err))?;
+        req.append_header(name.as_str(), value)?;
+    }
+
+    Ok(req)
+}
+
+/// Split a response into the head to send, and its body.
+pub fn response(mut res: Response) -> Result<(http::Response<()>, Body), Error> {
+    let mut head = http::Response::builder().status(res.status() as u16);
+    for (name, values) in res.iter() {
+        if CONNECTION_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        for value in values {
+            head = head.header(name.as_str(), value.as_str());
+        }
+    }
+    let has_len = res.header(&CONTENT_LENGTH).is_some();
+    let body = res.take_body();
+    if let (Some(len), false) = (body.len(), has_len) {
+        head = head.header(http::header::CONTENT_LENGTH, len);
+    }
+    Ok((head.body(())?, body))
+}
@@ -0,0 +1,81 @@
+//! Error handling policies.
+
+use std::sync::Arc;
+
+use async_std::io;
+use http_service::{Error, Response};
+
+/// Told about every error returned by the service.
+pub type ErrorHandler = Arc<dyn Fn(&Error) + Send + Sync>;
+
+/// Turns errors returned by the service into responses.
+pub type ErrorResponse = Arc<dyn Fn(&Error) -> Response + Send + Sync>;
+
+/// Decides how to react to errors accepting connections.
+pub type AcceptErrorHandler = Arc<dyn Fn(&io::Error) -> AcceptErrorAction + Send + Sync>;
+
+/// The default error handler: log the error.
+pub fn log_error(err: &Error) {
+    log::error!("error handling request: {}", err);
+}
+
+/// The default error response: an empty response with the error's status.
+pub fn default_error_response(err: &Error) -> Response {
+    Response::new(err.status())
+}
+
+/// An error for input that can't be parsed.
+pub fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// How the server reacts to an error while accepting a connection.
+///
+/// Set with the `set_accept_error_handler` method of each server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptErrorAction {
+    /// Skip the failed connection and accept the next one immediately.
+    Continue,
+    /// Wait before accepting again, giving the system time to recover.
+    ///
+    /// The wait doubles on every consecutive error, up to the server's maximum
+    /// backoff, and is reset by the next successful accept.
+    Backoff,
+    /// Stop the server, returning the error from its `run` method.
+    Fatal,
+}
+
+impl AcceptErrorAction {
+    /// The default policy for an accept error.
+    ///
+    /// Errors affecting only the connection being accepted, such as
+    /// `ECONNABORTED`, are skipped. Errors showing that the listener itself is
+    /// unusable, such as `EBADF`, are fatal. All others, such as running out of
+    /// file descriptors (`EMFILE`), are assumed to be temporary and back off.
+    pub fn classify(err: &io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut => return AcceptErrorAction::Continue,
+            io::ErrorKind::InvalidInput => return AcceptErrorAction::Fatal,
+            _ => {}
+        }
+
+        #[cfg(unix)]
+        {
+            if let Some(libc::EBADF) | Some(libc::ENOTSOCK) | Some(libc::EOPNOTSUPP) =
+                err.raw_os_error()
+            {
+                return AcceptErrorAction::Fatal;
+            }
+        }
+
+        AcceptErrorAction::Backoff
+    }
+}
@@ -0,0 +1,20 @@
+//! Internals shared by the `http-service` server crates.
+//!
+//! This crate is an implementation detail of `http-service-h1`,
+//! `http-service-h2` and `http-service-h3`, which re-export the parts meant
+//! for their users. Its API carries no stability guarantees.
+
+#![forbid(future_incompatible)]
+#![deny(missing_debug_implementations, nonstandard_style, rust_2018_idioms)]
+#![warn(missing_docs, missing_doc_code_examples)]
+#![cfg_attr(test, deny(warnings))]
+
+pub mod accept;
+#[cfg(feature = "http")]
+pub mod convert;
+pub mod error;
+pub mod limit;
+pub mod shutdown;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod transport;
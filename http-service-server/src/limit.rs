@@ -0,0 +1,259 @@
+//! Limits on connections and requests.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// What the server does when a limit is reached.
+///
+/// Set with the `set_connection_limit_action` and `set_request_limit_action`
+/// methods of each server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum LimitAction {
+    /// Wait until there is room again.
+    Wait,
+    /// Turn away whatever is over the limit.
+    Reject,
+}
+
+/// Counts the users of a limited resource, and makes others wait for room.
+///
+/// Waiters are served in the order they started waiting: a place given up
+/// while others wait is handed to the first of them.
+#[derive(Debug)]
+pub struct Limiter {
+    max: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    used: usize,
+    next_id: u64,
+    waiters: VecDeque<(u64, Waker)>,
+}
+
+impl Limiter {
+    /// Create a limiter with room for `max` users.
+    pub fn new(max: usize) -> Arc<Self> {
+        Arc::new(Self {
+            max,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Take a place, if there is one left.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<Permit> {
+        let mut state = self.state.lock().unwrap();
+        if state.used < self.max {
+            state.used += 1;
+            Some(Permit(self.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// Wait for a place.
+    pub fn acquire(self: &Arc<Self>) -> Acquire {
+        Acquire {
+            limiter: self.clone(),
+            id: None,
+        }
+    }
+
+    /// Give up a place, handing it to the first waiter if there is one.
+    fn release(&self) {
+        let waiter = {
+            let mut state = self.state.lock().unwrap();
+            let waiter = state.waiters.pop_front();
+            if waiter.is_none() {
+                state.used -= 1;
+            }
+            waiter
+        };
+        if let Some((_, waker)) = waiter {
+            waker.wake();
+        }
+    }
+}
+
+/// Holds a place in a [`Limiter`] until it is dropped.
+#[derive(Debug)]
+pub struct Permit(Arc<Limiter>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// Waits for a place in a [`Limiter`].
+///
+/// Once waiting, it holds a single slot in the queue of waiters, which it
+/// leaves when dropped.
+#[derive(Debug)]
+pub struct Acquire {
+    limiter: Arc<Limiter>,
+    /// The slot in the queue of waiters, once waiting.
+    id: Option<u64>,
+}
+
+impl Future for Acquire {
+    type Output = Permit;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit> {
+        let limiter = self.limiter.clone();
+        let mut state = limiter.state.lock().unwrap();
+        match self.id {
+            Some(id) => match state.waiters.iter_mut().find(|(waiter, _)| *waiter == id) {
+                Some((_, waker)) => {
+                    if !waker.will_wake(cx.waker()) {
+                        *waker = cx.waker().clone();
+                    }
+                    Poll::Pending
+                }
+                // Leaving the queue means a place was handed over.
+                None => {
+                    self.id = None;
+                    Poll::Ready(Permit(limiter.clone()))
+                }
+            },
+            // Places are handed over while others wait, so there is only room
+            // when nobody is waiting.
+            None if state.used < limiter.max => {
+                state.used += 1;
+                Poll::Ready(Permit(limiter.clone()))
+            }
+            None => {
+                let id = state.next_id;
+                state.next_id += 1;
+                state.waiters.push_back((id, cx.waker().clone()));
+                self.id = Some(id);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        let id = match self.id {
+            Some(id) => id,
+            None => return,
+        };
+        let handed_over = {
+            let mut state = self.limiter.state.lock().unwrap();
+            match state.waiters.iter().position(|(waiter, _)| *waiter == id) {
+                Some(i) => {
+                    state.waiters.remove(i);
+                    false
+                }
+                None => true,
+            }
+        };
+        // A place handed over to a waiter that gave up goes to the next one.
+        if handed_over {
+            self.limiter.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::task::{waker, ArcWake};
+
+    /// Counts how often it is woken.
+    #[derive(Default)]
+    struct Wakes(AtomicUsize);
+
+    impl ArcWake for Wakes {
+        fn wake_by_ref(this: &Arc<Self>) {
+            this.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    impl Wakes {
+        fn count(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    fn poll(acquire: &mut Acquire, wakes: &Arc<Wakes>) -> Option<Permit> {
+        let waker = waker(wakes.clone());
+        match Pin::new(acquire).poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(permit) => Some(permit),
+            Poll::Pending => None,
+        }
+    }
+
+    #[test]
+    fn hands_places_to_waiters_in_order() {
+        let limiter = Limiter::new(1);
+        let held = limiter.try_acquire().unwrap();
+        let (mut first, mut second) = (limiter.acquire(), limiter.acquire());
+        let (first_wakes, second_wakes) = (Arc::default(), Arc::default());
+        assert!(poll(&mut first, &first_wakes).is_none());
+        assert!(poll(&mut second, &second_wakes).is_none());
+
+        // Only the first waiter is woken, and the place can't be taken from it.
+        drop(held);
+        assert_eq!(first_wakes.count(), 1);
+        assert_eq!(second_wakes.count(), 0);
+        assert!(limiter.try_acquire().is_none());
+        let held = poll(&mut first, &first_wakes).unwrap();
+
+        drop(held);
+        assert_eq!(second_wakes.count(), 1);
+        assert!(poll(&mut second, &second_wakes).is_some());
+        assert!(limiter.try_acquire().is_some());
+    }
+
+    #[test]
+    fn waits_in_a_single_slot() {
+        let limiter = Limiter::new(1);
+        let held = limiter.try_acquire().unwrap();
+        let mut acquire = limiter.acquire();
+        let (old, new) = (Arc::default(), Arc::default());
+        for _ in 0..3 {
+            assert!(poll(&mut acquire, &old).is_none());
+        }
+        assert!(poll(&mut acquire, &new).is_none());
+        assert_eq!(limiter.state.lock().unwrap().waiters.len(), 1);
+
+        // Only the latest waker is woken.
+        drop(held);
+        assert_eq!((old.count(), new.count()), (0, 1));
+    }
+
+    #[test]
+    fn passes_on_places_given_up_by_waiters() {
+        let limiter = Limiter::new(1);
+        let held = limiter.try_acquire().unwrap();
+        let (mut first, mut second) = (limiter.acquire(), limiter.acquire());
+        let wakes = Arc::default();
+        assert!(poll(&mut first, &wakes).is_none());
+        assert!(poll(&mut second, &wakes).is_none());
+
+        // A waiter that leaves the queue gives up its turn.
+        drop(first);
+        assert_eq!(limiter.state.lock().unwrap().waiters.len(), 1);
+
+        // A waiter handed a place it never takes passes it on.
+        let mut third = limiter.acquire();
+        assert!(poll(&mut third, &wakes).is_none());
+        drop(held);
+        drop(second);
+        assert!(poll(&mut third, &wakes).is_some());
+    }
+}
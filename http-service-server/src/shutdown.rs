@@ -0,0 +1,139 @@
+//! Graceful shutdown.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::channel::oneshot;
+use futures::future::{FutureExt, Shared};
+use futures::task::AtomicWaker;
+
+/// A handle to stop a running server.
+///
+/// Created by the `shutdown_handle` method of each server. Handles can be
+/// cloned and moved to other tasks, such as a signal handler.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    signal: Signal,
+}
+
+impl ShutdownHandle {
+    #[doc(hidden)]
+    pub fn new(signal: Signal) -> Self {
+        Self { signal }
+    }
+
+    /// Start shutting down the server.
+    ///
+    /// The server stops accepting connections, and waits for in-flight
+    /// requests to finish before the server's `run` returns. Calling this more than
+    /// once has no further effect.
+    pub fn shutdown(&self) {
+        self.signal.fire();
+    }
+}
+
+/// A one-off event that any number of tasks can wait on.
+#[derive(Clone)]
+pub struct Signal {
+    fired: Arc<AtomicBool>,
+    sender: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    receiver: Shared<oneshot::Receiver<()>>,
+}
+
+impl Signal {
+    /// Create a signal that hasn't fired yet.
+    pub fn new() -> Self {
+        let (sender, receiver) = oneshot::channel();
+        Self {
+            fired: Arc::new(AtomicBool::new(false)),
+            sender: Arc::new(Mutex::new(Some(sender))),
+            receiver: receiver.shared(),
+        }
+    }
+
+    /// Fire the signal, waking every waiting task.
+    pub fn fire(&self) {
+        self.fired.store(true, Ordering::SeqCst);
+        // Dropping the sender completes every clone of the receiver.
+        self.sender.lock().unwrap().take();
+    }
+
+    /// Whether the signal has fired.
+    pub fn is_fired(&self) -> bool {
+        self.fired.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the signal is fired.
+    pub fn wait(&self) -> impl Future<Output = ()> + Unpin + Send + 'static {
+        self.receiver.clone().map(|_| ())
+    }
+}
+
+impl Default for Signal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signal")
+            .field("fired", &self.is_fired())
+            .finish()
+    }
+}
+
+/// Counts the live connections of a server.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    count: AtomicUsize,
+    waker: AtomicWaker,
+}
+
+impl Tracker {
+    /// Register a new connection, which stays live until the token is dropped.
+    pub fn track(self: &Arc<Self>) -> Token {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        Token(self.clone())
+    }
+
+    /// Wait until there are no live connections.
+    ///
+    /// Only one task may wait at a time.
+    pub fn wait_idle(&self) -> WaitIdle<'_> {
+        WaitIdle(self)
+    }
+}
+
+/// Keeps a connection registered with a [`Tracker`].
+#[derive(Debug)]
+pub struct Token(Arc<Tracker>);
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.waker.wake();
+        }
+    }
+}
+
+/// Waits for a [`Tracker`] to have no live connections.
+#[derive(Debug)]
+pub struct WaitIdle<'a>(&'a Tracker);
+
+impl Future for WaitIdle<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.0.waker.register(cx.waker());
+        if self.0.count.load(Ordering::SeqCst) == 0 {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
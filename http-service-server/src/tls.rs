@@ -0,0 +1,22 @@
+//! Loading certificates and keys for rustls.
+
+use async_std::io;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+
+use crate::error::invalid_data;
+
+/// Parse a certificate chain and its private key, in PEM format.
+pub fn parse_pem(
+    cert: &[u8],
+    key: &[u8],
+) -> io::Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let certs = CertificateDer::pem_slice_iter(cert)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(invalid_data)?;
+    if certs.is_empty() {
+        return Err(invalid_data("no certificates found"));
+    }
+    let key = PrivateKeyDer::from_pem_slice(key).map_err(invalid_data)?;
+    Ok((certs, key))
+}
@@ -0,0 +1,202 @@
+//! Transports that connections can be served over.
+
+use async_std::io;
+use async_std::net::TcpStream;
+use http_service::ConnectionInfo;
+
+/// A byte stream that HTTP can be served over.
+///
+/// The server accepts connections from any stream of transports, such as TCP
+/// connections, Unix domain sockets, TLS streams, or in-memory pipes.
+///
+/// # Examples
+///
+/// Serving over a custom transport only requires implementing the I/O traits,
+/// and optionally describing the connection:
+///
+/// ```
+/// use async_std::io::{self, Read, Write};
+/// use async_std::net::{SocketAddr, TcpStream};
+/// use http_service::ConnectionInfo;
+/// use http_service_server::transport::Transport;
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll};
+///
+/// /// A connection tunnelled through a relay, from a client it knows.
+/// struct Tunnel {
+///     relay: TcpStream,
+///     client: SocketAddr,
+/// }
+///
+/// impl Read for Tunnel {
+///     fn poll_read(
+///         mut self: Pin<&mut Self>,
+///         cx: &mut Context<'_>,
+///         buf: &mut [u8],
+///     ) -> Poll<io::Result<usize>> {
+///         Pin::new(&mut self.relay).poll_read(cx, buf)
+///     }
+/// }
+///
+/// impl Write for Tunnel {
+///     fn poll_write(
+///         mut self: Pin<&mut Self>,
+///         cx: &mut Context<'_>,
+///         buf: &[u8],
+///     ) -> Poll<io::Result<usize>> {
+///         Pin::new(&mut self.relay).poll_write(cx, buf)
+///     }
+///
+///     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+///         Pin::new(&mut self.relay).poll_flush(cx)
+///     }
+///
+///     fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+///         Pin::new(&mut self.relay).poll_close(cx)
+///     }
+/// }
+///
+/// impl Transport for Tunnel {
+///     fn connection_info(&self) -> ConnectionInfo {
+///         let mut info = ConnectionInfo::new();
+///         info.set_peer_addr(Some(self.client));
+///         info
+///     }
+/// }
+/// ```
+pub trait Transport: io::Read + io::Write + Send + Unpin + 'static {
+    /// Information about the connection, passed to `HttpService::connect`.
+    ///
+    /// Defaults to no information.
+    fn connection_info(&self) -> ConnectionInfo {
+        ConnectionInfo::new()
+    }
+}
+
+impl Transport for Box<dyn Transport> {
+    fn connection_info(&self) -> ConnectionInfo {
+        (**self).connection_info()
+    }
+}
+
+impl Transport for TcpStream {
+    fn connection_info(&self) -> ConnectionInfo {
+        let mut info = ConnectionInfo::new();
+        info.set_peer_addr(self.peer_addr().ok());
+        info.set_local_addr(self.local_addr().ok());
+        info
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::os::unix::io::AsRawFd;
+
+    use async_std::io;
+    use async_std::os::unix::net::UnixStream;
+    use http_service::{ConnectionInfo, PeerCredentials};
+
+    use super::Transport;
+
+    impl Transport for UnixStream {
+        fn connection_info(&self) -> ConnectionInfo {
+            let mut info = ConnectionInfo::new();
+            match peer_credentials(self) {
+                Ok(credentials) => info.set_peer_credentials(Some(credentials)),
+                Err(err) => log::debug!("failed to get peer credentials: {}", err),
+            }
+            info
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn peer_credentials(stream: &impl AsRawFd) -> io::Result<PeerCredentials> {
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: `cred` and `len` are valid for writes, and `len` holds the size
+        // of `cred`.
+        let ret = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PeerCredentials::new(cred.uid, cred.gid, Some(cred.pid)))
+    }
+
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "openbsd",
+        target_os = "netbsd"
+    ))]
+    fn peer_credentials(stream: &impl AsRawFd) -> io::Result<PeerCredentials> {
+        let mut uid = 0;
+        let mut gid = 0;
+        // SAFETY: `uid` and `gid` are valid for writes.
+        let ret = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PeerCredentials::new(uid, gid, None))
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "openbsd",
+        target_os = "netbsd"
+    )))]
+    fn peer_credentials(_stream: &impl AsRawFd) -> io::Result<PeerCredentials> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "peer credentials are not supported on this platform",
+        ))
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl<T: Transport> Transport for futures_rustls::server::TlsStream<T> {
+    fn connection_info(&self) -> ConnectionInfo {
+        let mut info = self.get_ref().0.connection_info();
+        info.set_secure(true);
+        info
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Transport for tokio_util::compat::Compat<tokio::net::TcpStream> {
+    fn connection_info(&self) -> ConnectionInfo {
+        let stream = self.get_ref();
+        let mut info = ConnectionInfo::new();
+        info.set_peer_addr(stream.peer_addr().ok());
+        info.set_local_addr(stream.local_addr().ok());
+        info
+    }
+}
+
+#[cfg(feature = "smol")]
+impl Transport for smol::net::TcpStream {
+    fn connection_info(&self) -> ConnectionInfo {
+        let mut info = ConnectionInfo::new();
+        info.set_peer_addr(self.peer_addr().ok());
+        info.set_local_addr(self.local_addr().ok());
+        info
+    }
+}
@@ -0,0 +1,186 @@
+//! Type-erased services.
+
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use http_types::StatusCode;
+
+use crate::{ConnectionInfo, Error, HttpService, Request, Response};
+
+/// An owned, boxed future which can be sent across threads.
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// A type-erased `HttpService`.
+///
+/// `HttpService` has associated types, so services of different types can't be
+/// stored together. A `BoxService` erases the connection, the futures and the
+/// errors of the service it wraps, so any service can be converted into the
+/// same type. Errors are converted into [`Error`](type.Error.html).
+///
+/// Cloning a `BoxService` is cheap; clones share the same underlying service.
+///
+/// The object-safe trait behind `BoxService` is private. Any `HttpService`
+/// can be boxed with [`BoxService::new`](#method.new), so implementing it by
+/// hand would gain nothing, and keeping it private leaves it free to change
+/// along with `HttpService`.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{BoxService, Request, Response};
+///
+/// let services = vec![
+///     BoxService::new(|_req: Request| async {
+///         Ok::<_, http_service::Error>(Response::from("hello"))
+///     }),
+///     BoxService::new(|_req: Request| async {
+///         Ok::<_, std::io::Error>(Response::from("world"))
+///     }),
+/// ];
+/// ```
+#[derive(Clone)]
+pub struct BoxService {
+    inner: Arc<dyn DynService>,
+}
+
+impl BoxService {
+    /// Erase the type of the given service.
+    pub fn new<S>(service: S) -> Self
+    where
+        S: HttpService,
+        S::Connection: Sync,
+    {
+        BoxService {
+            inner: Arc::new(Erased(service)),
+        }
+    }
+}
+
+impl HttpService for BoxService {
+    type Connection = BoxConnection;
+    type ConnectionError = Error;
+    type ConnectionFuture = BoxFuture<Result<BoxConnection, Error>>;
+    type ResponseError = Error;
+    type ResponseFuture = BoxFuture<Result<Response, Error>>;
+
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture {
+        self.inner.connect(info)
+    }
+
+    fn respond(&self, conn: Self::Connection, req: Request) -> Self::ResponseFuture {
+        self.inner.respond(conn, req)
+    }
+}
+
+impl fmt::Debug for BoxService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxService").finish()
+    }
+}
+
+/// The connection state of a [`BoxService`](struct.BoxService.html).
+///
+/// A connection only works with the service that created it; requests on it
+/// to any other service fail with `500 Internal Server Error`.
+#[derive(Clone)]
+pub struct BoxConnection(Arc<dyn Any + Send + Sync>);
+
+impl fmt::Debug for BoxConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxConnection").finish()
+    }
+}
+
+/// The object-safe counterpart of `HttpService`.
+trait DynService: Send + Sync + 'static {
+    fn connect(&self, info: &ConnectionInfo) -> BoxFuture<Result<BoxConnection, Error>>;
+    fn respond(&self, conn: BoxConnection, req: Request) -> BoxFuture<Result<Response, Error>>;
+}
+
+struct Erased<S>(S);
+
+impl<S> DynService for Erased<S>
+where
+    S: HttpService,
+    S::Connection: Sync,
+{
+    fn connect(&self, info: &ConnectionInfo) -> BoxFuture<Result<BoxConnection, Error>> {
+        let fut = self.0.connect(info);
+        Box::pin(async move {
+            match fut.await {
+                Ok(conn) => Ok(BoxConnection(Arc::new(conn))),
+                Err(err) => Err(err.into()),
+            }
+        })
+    }
+
+    fn respond(&self, conn: BoxConnection, req: Request) -> BoxFuture<Result<Response, Error>> {
+        let conn = match conn.0.downcast_ref::<S::Connection>() {
+            Some(conn) => conn.clone(),
+            None => {
+                let err = Error::from_str(
+                    StatusCode::InternalServerError,
+                    "BoxConnection used with a different service",
+                );
+                return Box::pin(async move { Err(err) });
+            }
+        };
+        let fut = self.0.respond(conn, req);
+        Box::pin(async move { fut.await.map_err(Into::into) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::executor::block_on;
+    use futures::future::{self, Ready};
+    use http_types::{Method, Url};
+
+    /// Counts the requests on each connection.
+    struct Counter;
+
+    impl HttpService for Counter {
+        type Connection = Arc<AtomicUsize>;
+        type ConnectionError = Error;
+        type ConnectionFuture = Ready<Result<Self::Connection, Error>>;
+        type ResponseError = Error;
+        type ResponseFuture = Ready<Result<Response, Error>>;
+
+        fn connect(&self, _info: &ConnectionInfo) -> Self::ConnectionFuture {
+            future::ok(Arc::default())
+        }
+
+        fn respond(&self, conn: Self::Connection, _req: Request) -> Self::ResponseFuture {
+            let n = conn.fetch_add(1, Ordering::SeqCst) + 1;
+            future::ok(Response::from(n.to_string()))
+        }
+    }
+
+    fn request() -> Request {
+        Request::new(Method::Get, Url::parse("http://localhost/").unwrap())
+    }
+
+    #[test]
+    fn refuses_connections_of_other_services() {
+        block_on(async {
+            let counter = BoxService::new(Counter);
+            let hello =
+                BoxService::new(|_req: Request| async { Ok::<_, Error>(Response::from("hello")) });
+            let info = ConnectionInfo::new();
+
+            let conn = counter.connect(&info).await.unwrap();
+            let res = counter.respond(conn.clone(), request()).await.unwrap();
+            assert_eq!(res.body_string().await.unwrap(), "1");
+
+            let err = hello.respond(conn, request()).await.unwrap_err();
+            assert_eq!(err.status(), StatusCode::InternalServerError);
+        });
+    }
+}
@@ -0,0 +1,239 @@
+//! Information about individual connections.
+
+use std::net::SocketAddr;
+
+use http_types::Version;
+
+/// Information about a connection, passed to `HttpService::connect`.
+///
+/// Servers fill in whatever they know about the connection; every field is
+/// optional, since not every transport has addresses or a known protocol.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::ConnectionInfo;
+///
+/// let mut info = ConnectionInfo::new();
+/// info.set_peer_addr(Some("127.0.0.1:4000".parse().unwrap()));
+/// assert!(!info.is_secure());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionInfo {
+    peer_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+    secure: bool,
+    protocol: Option<Version>,
+    peer_credentials: Option<PeerCredentials>,
+    proxy_tlvs: Vec<ProxyTlv>,
+}
+
+impl ConnectionInfo {
+    /// Create a new `ConnectionInfo` with no information set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the address of the remote end of the connection.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Set the address of the remote end of the connection.
+    pub fn set_peer_addr(&mut self, addr: Option<SocketAddr>) {
+        self.peer_addr = addr;
+    }
+
+    /// Get the address of the local end of the connection.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Set the address of the local end of the connection.
+    pub fn set_local_addr(&mut self, addr: Option<SocketAddr>) {
+        self.local_addr = addr;
+    }
+
+    /// Returns `true` if the connection is encrypted, e.g. using TLS.
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Set whether the connection is encrypted.
+    pub fn set_secure(&mut self, secure: bool) {
+        self.secure = secure;
+    }
+
+    /// Get the HTTP version spoken on the connection.
+    pub fn protocol(&self) -> Option<Version> {
+        self.protocol
+    }
+
+    /// Set the HTTP version spoken on the connection.
+    pub fn set_protocol(&mut self, protocol: Option<Version>) {
+        self.protocol = protocol;
+    }
+
+    /// Get the credentials of the process at the remote end of the
+    /// connection.
+    ///
+    /// Only known for local connections, such as Unix domain sockets.
+    pub fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.peer_credentials
+    }
+
+    /// Set the credentials of the process at the remote end of the
+    /// connection.
+    pub fn set_peer_credentials(&mut self, credentials: Option<PeerCredentials>) {
+        self.peer_credentials = credentials;
+    }
+
+    /// Get the TLVs sent by a proxy in a PROXY protocol header.
+    ///
+    /// When a server accepts the PROXY protocol, the peer and local addresses
+    /// are those of the client's original connection, and the proxy may add
+    /// more information, such as the TLS server name the client asked for.
+    pub fn proxy_tlvs(&self) -> &[ProxyTlv] {
+        &self.proxy_tlvs
+    }
+
+    /// Set the TLVs sent by a proxy in a PROXY protocol header.
+    pub fn set_proxy_tlvs(&mut self, tlvs: Vec<ProxyTlv>) {
+        self.proxy_tlvs = tlvs;
+    }
+}
+
+/// The address of the remote end of the connection a request arrived on.
+///
+/// Servers insert it into the extensions of every request, when the
+/// connection has an address.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{PeerAddr, Request, Response};
+///
+/// let service = |req: Request| async move {
+///     let peer = req.local().get::<PeerAddr>().map(|peer| peer.addr());
+///     Ok::<_, http_service::Error>(Response::from(format!("hello {:?}", peer)))
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerAddr(SocketAddr);
+
+impl PeerAddr {
+    /// Create a new `PeerAddr`.
+    pub fn new(addr: SocketAddr) -> Self {
+        PeerAddr(addr)
+    }
+
+    /// The address of the peer.
+    pub fn addr(&self) -> SocketAddr {
+        self.0
+    }
+}
+
+/// The address of the local end of the connection a request arrived on.
+///
+/// Servers insert it into the extensions of every request, when the
+/// connection has an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalAddr(SocketAddr);
+
+impl LocalAddr {
+    /// Create a new `LocalAddr`.
+    pub fn new(addr: SocketAddr) -> Self {
+        LocalAddr(addr)
+    }
+
+    /// The local address.
+    pub fn addr(&self) -> SocketAddr {
+        self.0
+    }
+}
+
+/// The credentials of a process connected over a local socket.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{ConnectionInfo, PeerCredentials};
+///
+/// let mut info = ConnectionInfo::new();
+/// info.set_peer_credentials(Some(PeerCredentials::new(1000, 1000, Some(4321))));
+/// assert_eq!(info.peer_credentials().unwrap().uid(), 1000);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    uid: u32,
+    gid: u32,
+    pid: Option<i32>,
+}
+
+impl PeerCredentials {
+    /// Create a new `PeerCredentials`.
+    pub fn new(uid: u32, gid: u32, pid: Option<i32>) -> Self {
+        Self { uid, gid, pid }
+    }
+
+    /// The user ID of the peer process.
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// The group ID of the peer process.
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// The process ID of the peer, if the platform reports it.
+    pub fn pid(&self) -> Option<i32> {
+        self.pid
+    }
+}
+
+/// A type-length-value field of a PROXY protocol version 2 header.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{ConnectionInfo, ProxyTlv};
+///
+/// let mut info = ConnectionInfo::new();
+/// info.set_proxy_tlvs(vec![ProxyTlv::new(0x02, b"example.com".to_vec())]);
+/// let authority = info.proxy_tlvs().iter().find(|tlv| tlv.kind() == ProxyTlv::AUTHORITY);
+/// assert_eq!(authority.unwrap().value(), b"example.com");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyTlv {
+    kind: u8,
+    value: Vec<u8>,
+}
+
+impl ProxyTlv {
+    /// The application protocol negotiated by the client, such as `h2`.
+    pub const ALPN: u8 = 0x01;
+    /// The host name the client connected to, such as from TLS SNI.
+    pub const AUTHORITY: u8 = 0x02;
+    /// A unique identifier of the connection.
+    pub const UNIQUE_ID: u8 = 0x05;
+    /// Information about the client's TLS connection.
+    pub const SSL: u8 = 0x20;
+    /// The network namespace the connection was accepted in.
+    pub const NETNS: u8 = 0x30;
+
+    /// Create a new `ProxyTlv`.
+    pub fn new(kind: u8, value: Vec<u8>) -> Self {
+        Self { kind, value }
+    }
+
+    /// The type of the field.
+    pub fn kind(&self) -> u8 {
+        self.kind
+    }
+
+    /// The value of the field.
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
@@ -0,0 +1,350 @@
+//! Combinators for `HttpService`.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use pin_project_lite::pin_project;
+
+use crate::{BoxService, ConnectionInfo, Error, HttpService, Request, Response};
+
+/// Extension methods for `HttpService`.
+///
+/// These make small adjustments to a service without writing a new wrapper
+/// type by hand.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{Request, Response, ServiceExt};
+///
+/// let service = (|_req: Request| async { Ok::<_, http_service::Error>(Response::from("hello")) })
+///     .map_response(|mut res: Response| {
+///         res.insert_header("x-powered-by", "http-service").unwrap();
+///         res
+///     });
+/// ```
+pub trait ServiceExt: HttpService + Sized {
+    /// Rewrite each request before it is passed to this service.
+    fn map_request<F>(self, f: F) -> MapRequest<Self, F>
+    where
+        F: Fn(Request) -> Request + Send + Sync + 'static,
+    {
+        MapRequest { service: self, f }
+    }
+
+    /// Rewrite each response produced by this service.
+    fn map_response<F>(self, f: F) -> MapResponse<Self, F>
+    where
+        F: Fn(Response) -> Response + Send + Sync + 'static,
+    {
+        MapResponse {
+            service: self,
+            f: Arc::new(f),
+        }
+    }
+
+    /// Run an async computation on each successful response produced by this
+    /// service.
+    fn and_then<F, Fut>(self, f: F) -> AndThen<Self, F>
+    where
+        F: Fn(Response) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Response, Self::ResponseError>> + Send + 'static,
+    {
+        AndThen {
+            service: self,
+            f: Arc::new(f),
+        }
+    }
+
+    /// Convert the response errors produced by this service.
+    fn map_err<F, E>(self, f: F) -> MapErr<Self, F>
+    where
+        F: Fn(Self::ResponseError) -> E + Send + Sync + 'static,
+        E: Into<Error> + Send,
+    {
+        MapErr {
+            service: self,
+            f: Arc::new(f),
+        }
+    }
+
+    /// Erase the type of this service.
+    ///
+    /// See [`BoxService`](struct.BoxService.html) for details.
+    fn boxed(self) -> BoxService
+    where
+        Self::Connection: Sync,
+    {
+        BoxService::new(self)
+    }
+}
+
+impl<S: HttpService> ServiceExt for S {}
+
+/// A service that rewrites requests before passing them on.
+///
+/// Created by [`ServiceExt::map_request`](trait.ServiceExt.html#method.map_request).
+pub struct MapRequest<S, F> {
+    service: S,
+    f: F,
+}
+
+impl<S, F> HttpService for MapRequest<S, F>
+where
+    S: HttpService,
+    F: Fn(Request) -> Request + Send + Sync + 'static,
+{
+    type Connection = S::Connection;
+    type ConnectionError = S::ConnectionError;
+    type ConnectionFuture = S::ConnectionFuture;
+    type ResponseError = S::ResponseError;
+    type ResponseFuture = S::ResponseFuture;
+
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture {
+        self.service.connect(info)
+    }
+
+    fn respond(&self, conn: Self::Connection, req: Request) -> Self::ResponseFuture {
+        self.service.respond(conn, (self.f)(req))
+    }
+}
+
+impl<S: fmt::Debug, F> fmt::Debug for MapRequest<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapRequest")
+            .field("service", &self.service)
+            .finish()
+    }
+}
+
+/// A service that rewrites the responses of the service it wraps.
+///
+/// Created by [`ServiceExt::map_response`](trait.ServiceExt.html#method.map_response).
+pub struct MapResponse<S, F> {
+    service: S,
+    f: Arc<F>,
+}
+
+impl<S, F> HttpService for MapResponse<S, F>
+where
+    S: HttpService,
+    F: Fn(Response) -> Response + Send + Sync + 'static,
+{
+    type Connection = S::Connection;
+    type ConnectionError = S::ConnectionError;
+    type ConnectionFuture = S::ConnectionFuture;
+    type ResponseError = S::ResponseError;
+    type ResponseFuture = MapResponseFuture<S::ResponseFuture, F>;
+
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture {
+        self.service.connect(info)
+    }
+
+    fn respond(&self, conn: Self::Connection, req: Request) -> Self::ResponseFuture {
+        MapResponseFuture {
+            fut: self.service.respond(conn, req),
+            f: self.f.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, F> fmt::Debug for MapResponse<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapResponse")
+            .field("service", &self.service)
+            .finish()
+    }
+}
+
+pin_project! {
+    /// The response future of [`MapResponse`](struct.MapResponse.html).
+    pub struct MapResponseFuture<Fut, F> {
+        #[pin]
+        fut: Fut,
+        f: Arc<F>,
+    }
+}
+
+impl<Fut, F, E> Future for MapResponseFuture<Fut, F>
+where
+    Fut: Future<Output = Result<Response, E>>,
+    F: Fn(Response) -> Response,
+{
+    type Output = Result<Response, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        match this.fut.poll(cx) {
+            Poll::Ready(res) => {
+                let f = this.f;
+                Poll::Ready(res.map(|res| f(res)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<Fut, F> fmt::Debug for MapResponseFuture<Fut, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapResponseFuture").finish()
+    }
+}
+
+/// A service that runs an async computation on each response of the service
+/// it wraps.
+///
+/// Created by [`ServiceExt::and_then`](trait.ServiceExt.html#method.and_then).
+pub struct AndThen<S, F> {
+    service: S,
+    f: Arc<F>,
+}
+
+impl<S, F, Fut> HttpService for AndThen<S, F>
+where
+    S: HttpService,
+    F: Fn(Response) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Response, S::ResponseError>> + Send + 'static,
+{
+    type Connection = S::Connection;
+    type ConnectionError = S::ConnectionError;
+    type ConnectionFuture = S::ConnectionFuture;
+    type ResponseError = S::ResponseError;
+    type ResponseFuture = AndThenFuture<S::ResponseFuture, Fut, F>;
+
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture {
+        self.service.connect(info)
+    }
+
+    fn respond(&self, conn: Self::Connection, req: Request) -> Self::ResponseFuture {
+        AndThenFuture {
+            first: self.service.respond(conn, req),
+            second: None,
+            f: self.f.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, F> fmt::Debug for AndThen<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AndThen")
+            .field("service", &self.service)
+            .finish()
+    }
+}
+
+pin_project! {
+    /// The response future of [`AndThen`](struct.AndThen.html).
+    pub struct AndThenFuture<Fut1, Fut2, F> {
+        #[pin]
+        first: Fut1,
+        #[pin]
+        second: Option<Fut2>,
+        f: Arc<F>,
+    }
+}
+
+impl<Fut1, Fut2, F, E> Future for AndThenFuture<Fut1, Fut2, F>
+where
+    Fut1: Future<Output = Result<Response, E>>,
+    Fut2: Future<Output = Result<Response, E>>,
+    F: Fn(Response) -> Fut2,
+{
+    type Output = Result<Response, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            let mut this = self.as_mut().project();
+            if let Some(second) = this.second.as_mut().as_pin_mut() {
+                return second.poll(cx);
+            }
+            match this.first.poll(cx) {
+                Poll::Ready(Ok(res)) => this.second.set(Some((this.f)(res))),
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<Fut1, Fut2, F> fmt::Debug for AndThenFuture<Fut1, Fut2, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AndThenFuture").finish()
+    }
+}
+
+/// A service that converts the response errors of the service it wraps.
+///
+/// Created by [`ServiceExt::map_err`](trait.ServiceExt.html#method.map_err).
+pub struct MapErr<S, F> {
+    service: S,
+    f: Arc<F>,
+}
+
+impl<S, F, E> HttpService for MapErr<S, F>
+where
+    S: HttpService,
+    F: Fn(S::ResponseError) -> E + Send + Sync + 'static,
+    E: Into<Error> + Send,
+{
+    type Connection = S::Connection;
+    type ConnectionError = S::ConnectionError;
+    type ConnectionFuture = S::ConnectionFuture;
+    type ResponseError = E;
+    type ResponseFuture = MapErrFuture<S::ResponseFuture, F>;
+
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture {
+        self.service.connect(info)
+    }
+
+    fn respond(&self, conn: Self::Connection, req: Request) -> Self::ResponseFuture {
+        MapErrFuture {
+            fut: self.service.respond(conn, req),
+            f: self.f.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, F> fmt::Debug for MapErr<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapErr")
+            .field("service", &self.service)
+            .finish()
+    }
+}
+
+pin_project! {
+    /// The response future of [`MapErr`](struct.MapErr.html).
+    pub struct MapErrFuture<Fut, F> {
+        #[pin]
+        fut: Fut,
+        f: Arc<F>,
+    }
+}
+
+impl<Fut, F, E1, E2> Future for MapErrFuture<Fut, F>
+where
+    Fut: Future<Output = Result<Response, E1>>,
+    F: Fn(E1) -> E2,
+{
+    type Output = Result<Response, E2>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        match this.fut.poll(cx) {
+            Poll::Ready(res) => {
+                let f = this.f;
+                Poll::Ready(res.map_err(|err| f(err)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<Fut, F> fmt::Debug for MapErrFuture<Fut, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapErrFuture").finish()
+    }
+}
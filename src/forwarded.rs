@@ -0,0 +1,498 @@
+//! Resolving the client of requests passed on by proxies.
+
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http_types::headers::HeaderName;
+use ipnet::IpNet;
+use pin_project_lite::pin_project;
+
+use crate::{ConnectionInfo, HttpService, Layer, Request};
+
+/// A layer that resolves the client, scheme and host of requests passed on
+/// by trusted proxies.
+///
+/// Proxies describe the request they received in the RFC 7239 `Forwarded`
+/// header, or in the `X-Forwarded-For`, `X-Forwarded-Proto` and
+/// `X-Forwarded-Host` headers, each appending to what earlier proxies sent.
+/// These headers are only believed when they come from a trusted proxy, since
+/// clients can send them too.
+///
+/// Starting from the peer of the connection, the hops listed in the headers
+/// are followed backwards for as long as they are trusted proxies. The first
+/// untrusted hop is the client; its address is inserted into the request as a
+/// [`ClientIp`](struct.ClientIp.html) extension, and the scheme and host its
+/// proxy reports replace those of the request URL. `Forwarded` is used if
+/// present, and the `X-Forwarded-*` headers otherwise.
+///
+/// Connections without a peer address, such as over Unix domain sockets, are
+/// assumed to come from a trusted proxy.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{ClientIp, ForwardedLayer, Request, Response, ServiceBuilder};
+///
+/// let service = ServiceBuilder::new()
+///     .layer(ForwardedLayer::new(vec!["10.0.0.0/8".parse().unwrap()]))
+///     .service(|req: Request| async move {
+///         let client = req.local().get::<ClientIp>().map(|client| client.ip());
+///         let body = format!("{:?} via {}", client, req.url());
+///         Ok::<_, http_service::Error>(Response::from(body))
+///     });
+/// ```
+#[derive(Debug, Clone)]
+pub struct ForwardedLayer {
+    trusted: Arc<Vec<IpNet>>,
+}
+
+impl ForwardedLayer {
+    /// Create a new `ForwardedLayer`, trusting the proxies in the given
+    /// networks.
+    pub fn new(trusted: Vec<IpNet>) -> Self {
+        Self {
+            trusted: Arc::new(trusted),
+        }
+    }
+}
+
+impl<S> Layer<S> for ForwardedLayer {
+    type Service = Forwarded<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Forwarded {
+            service: inner,
+            trusted: self.trusted.clone(),
+        }
+    }
+}
+
+/// A service that resolves the client, scheme and host of requests passed on
+/// by trusted proxies.
+///
+/// Created by [`ForwardedLayer`](struct.ForwardedLayer.html).
+pub struct Forwarded<S> {
+    service: S,
+    trusted: Arc<Vec<IpNet>>,
+}
+
+impl<S: HttpService> HttpService for Forwarded<S> {
+    type Connection = ForwardedConnection<S::Connection>;
+    type ConnectionError = S::ConnectionError;
+    type ConnectionFuture = ForwardedConnect<S::ConnectionFuture>;
+    type ResponseError = S::ResponseError;
+    type ResponseFuture = S::ResponseFuture;
+
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture {
+        ForwardedConnect {
+            fut: self.service.connect(info),
+            peer: info.peer_addr().map(|addr| addr.ip()),
+        }
+    }
+
+    fn respond(&self, conn: Self::Connection, mut req: Request) -> Self::ResponseFuture {
+        self.resolve(conn.peer, &mut req);
+        self.service.respond(conn.inner, req)
+    }
+}
+
+impl<S> Forwarded<S> {
+    fn is_trusted(&self, ip: Option<IpAddr>) -> bool {
+        match ip {
+            Some(ip) => {
+                let ip = match ip {
+                    IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+                    ip => ip,
+                };
+                self.trusted.iter().any(|net| net.contains(&ip))
+            }
+            None => true,
+        }
+    }
+
+    fn resolve(&self, peer: Option<IpAddr>, req: &mut Request) {
+        let mut client = Hop {
+            ip: peer,
+            ..Hop::default()
+        };
+        if self.is_trusted(peer) {
+            let hops = forwarded_hops(req).unwrap_or_else(|| x_forwarded_hops(req));
+            // The proxy closest to the client is the one whose hop precedes
+            // the first untrusted one.
+            for hop in hops.into_iter().rev() {
+                let trusted = hop.ip.is_some() && self.is_trusted(hop.ip);
+                client = hop;
+                if !trusted {
+                    break;
+                }
+            }
+        }
+
+        if let Some(proto) = &client.proto {
+            let proto = proto.to_ascii_lowercase();
+            if proto == "http" || proto == "https" {
+                let _ = req.url_mut().set_scheme(&proto);
+            }
+        }
+        if let Some(host) = &client.host {
+            set_host(req, host);
+        }
+        if let Some(ip) = client.ip {
+            req.local_mut().insert(ClientIp(ip));
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Forwarded<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Forwarded")
+            .field("service", &self.service)
+            .field("trusted", &self.trusted)
+            .finish()
+    }
+}
+
+/// The connection of a [`Forwarded`](struct.Forwarded.html) service.
+#[derive(Debug, Clone)]
+pub struct ForwardedConnection<C> {
+    inner: C,
+    peer: Option<IpAddr>,
+}
+
+pin_project! {
+    /// The connection future of [`Forwarded`](struct.Forwarded.html).
+    pub struct ForwardedConnect<Fut> {
+        #[pin]
+        fut: Fut,
+        peer: Option<IpAddr>,
+    }
+}
+
+impl<Fut, C, E> Future for ForwardedConnect<Fut>
+where
+    Fut: Future<Output = Result<C, E>>,
+{
+    type Output = Result<ForwardedConnection<C>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let peer = *this.peer;
+        match this.fut.poll(cx) {
+            Poll::Ready(res) => Poll::Ready(res.map(|inner| ForwardedConnection { inner, peer })),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<Fut> fmt::Debug for ForwardedConnect<Fut> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ForwardedConnect").finish()
+    }
+}
+
+/// The address of the client that sent a request.
+///
+/// Inserted into the extensions of every request by a
+/// [`Forwarded`](struct.Forwarded.html) service, when the address is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(IpAddr);
+
+impl ClientIp {
+    /// Create a new `ClientIp`.
+    pub fn new(ip: IpAddr) -> Self {
+        ClientIp(ip)
+    }
+
+    /// The IP address of the client.
+    pub fn ip(&self) -> IpAddr {
+        self.0
+    }
+}
+
+/// A hop of a request through proxies, as reported by the next proxy.
+#[derive(Debug, Default)]
+struct Hop {
+    ip: Option<IpAddr>,
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+/// All values of a header, as one comma-separated list.
+fn header_list(req: &Request, name: &str) -> Vec<String> {
+    let name: HeaderName = match name.parse() {
+        Ok(name) => name,
+        Err(_) => return Vec::new(),
+    };
+    req.header(&name)
+        .into_iter()
+        .flatten()
+        .flat_map(|value| value.as_str().split(','))
+        .map(|item| item.trim().to_owned())
+        .collect()
+}
+
+/// The hops in the `Forwarded` header, from the client to the last proxy.
+fn forwarded_hops(req: &Request) -> Option<Vec<Hop>> {
+    let elements = header_list(req, "Forwarded");
+    if elements.is_empty() {
+        return None;
+    }
+    let hops = elements
+        .iter()
+        .map(|element| {
+            let mut hop = Hop::default();
+            for pair in element.split(';') {
+                let mut pair = pair.splitn(2, '=');
+                let key = pair.next().unwrap_or("").trim().to_ascii_lowercase();
+                let value = pair.next().unwrap_or("").trim().trim_matches('"');
+                match key.as_str() {
+                    "for" => hop.ip = parse_node(value),
+                    "proto" => hop.proto = Some(value.to_owned()),
+                    "host" => hop.host = Some(value.to_owned()),
+                    _ => {}
+                }
+            }
+            hop
+        })
+        .collect();
+    Some(hops)
+}
+
+/// The hops in the `X-Forwarded-*` headers, from the client to the last proxy.
+///
+/// A proto or host is taken from the same position as its address, counting
+/// from the last proxy, or else from the first value.
+fn x_forwarded_hops(req: &Request) -> Vec<Hop> {
+    let ips = header_list(req, "X-Forwarded-For");
+    let protos = header_list(req, "X-Forwarded-Proto");
+    let hosts = header_list(req, "X-Forwarded-Host");
+    let at = |list: &[String], from_end: usize| {
+        list.len()
+            .checked_sub(from_end + 1)
+            .and_then(|i| list.get(i))
+            .or_else(|| list.first())
+            .cloned()
+    };
+    ips.iter()
+        .enumerate()
+        .map(|(i, ip)| {
+            let from_end = ips.len() - 1 - i;
+            Hop {
+                ip: parse_node(ip),
+                proto: at(&protos, from_end),
+                host: at(&hosts, from_end),
+            }
+        })
+        .collect()
+}
+
+/// Parse a node such as `192.0.2.43`, `192.0.2.43:47011` or
+/// `[2001:db8:cafe::17]:4711`.
+///
+/// Obfuscated identifiers and `unknown` have no address.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    let ip = node.strip_prefix('[')?.split(']').next()?;
+    ip.parse().ok()
+}
+
+/// Set the host and port of the request URL from a `Host`-style value.
+fn set_host(req: &mut Request, host: &str) {
+    let (name, port) = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => match host[i + 1..].parse::<u16>() {
+            Ok(port) => (&host[..i], Some(port)),
+            Err(_) => return,
+        },
+        _ => (host, None),
+    };
+    let url = req.url_mut();
+    let old = url.clone();
+    if url.set_host(Some(name)).is_err() || url.set_port(port).is_err() {
+        *url = old;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_types::{Method, Url};
+
+    /// Resolve a request from `peer` through proxies in `10.0.0.0/8` and
+    /// `2001:db8::/32`, returning the client address and the request URL.
+    fn resolve(peer: Option<&str>, headers: &[(&str, &str)]) -> (Option<String>, String) {
+        let trusted = vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "2001:db8::/32".parse().unwrap(),
+        ];
+        resolve_with(trusted, peer, headers)
+    }
+
+    fn resolve_with(
+        trusted: Vec<IpNet>,
+        peer: Option<&str>,
+        headers: &[(&str, &str)],
+    ) -> (Option<String>, String) {
+        let forwarded = ForwardedLayer::new(trusted).layer(());
+        let mut req = Request::new(Method::Get, Url::parse("http://localhost/path").unwrap());
+        for (name, value) in headers {
+            req.append_header(*name, *value).unwrap();
+        }
+        forwarded.resolve(peer.map(|peer| peer.parse().unwrap()), &mut req);
+        let client = req.local().get::<ClientIp>().map(|ip| ip.ip().to_string());
+        (client, req.url().to_string())
+    }
+
+    fn some(ip: &str) -> Option<String> {
+        Some(ip.to_owned())
+    }
+
+    #[test]
+    fn walks_trusted_hops_from_the_right() {
+        // The first hop is whatever the client claimed, and is ignored.
+        let headers = [("X-Forwarded-For", "192.0.2.1, 203.0.113.9, 10.0.0.2")];
+        let (client, _) = resolve(Some("10.0.0.1"), &headers);
+        assert_eq!(client, some("203.0.113.9"));
+
+        let headers = [
+            ("Forwarded", "for=192.0.2.1, for=203.0.113.9"),
+            ("Forwarded", "for=10.0.0.2"),
+        ];
+        let (client, _) = resolve(Some("10.0.0.1"), &headers);
+        assert_eq!(client, some("203.0.113.9"));
+
+        // When every hop is trusted, the first one is the client.
+        let headers = [("X-Forwarded-For", "10.0.0.3, 10.0.0.2")];
+        let (client, _) = resolve(Some("10.0.0.1"), &headers);
+        assert_eq!(client, some("10.0.0.3"));
+    }
+
+    #[test]
+    fn ignores_headers_from_untrusted_peers() {
+        let headers = [
+            ("X-Forwarded-For", "192.0.2.1"),
+            ("X-Forwarded-Proto", "https"),
+            ("X-Forwarded-Host", "example.com"),
+        ];
+        let (client, url) = resolve(Some("203.0.113.9"), &headers);
+        assert_eq!(client, some("203.0.113.9"));
+        assert_eq!(url, "http://localhost/path");
+
+        let (client, url) = resolve_with(Vec::new(), Some("10.0.0.1"), &headers);
+        assert_eq!(client, some("10.0.0.1"));
+        assert_eq!(url, "http://localhost/path");
+    }
+
+    #[test]
+    fn trusts_peers_without_an_address() {
+        let headers = [("X-Forwarded-For", "192.0.2.1")];
+        assert_eq!(resolve(None, &headers).0, some("192.0.2.1"));
+        assert_eq!(resolve(None, &[]).0, None);
+    }
+
+    #[test]
+    fn trusts_ipv4_mapped_peers() {
+        let headers = [("X-Forwarded-For", "192.0.2.1")];
+        assert_eq!(
+            resolve(Some("::ffff:10.0.0.1"), &headers).0,
+            some("192.0.2.1")
+        );
+    }
+
+    #[test]
+    fn stops_at_hops_without_an_address() {
+        for node in &["unknown", "_hidden", "\"_SEVKISEK\""] {
+            let header = format!("for=192.0.2.1, for={}, for=10.0.0.2", node);
+            let (client, _) = resolve(Some("10.0.0.1"), &[("Forwarded", &header)]);
+            assert_eq!(client, None, "{}", node);
+        }
+    }
+
+    #[test]
+    fn parses_forwarded_nodes() {
+        let cases = [
+            ("for=192.0.2.43", "192.0.2.43"),
+            ("for=192.0.2.43:47011", "192.0.2.43"),
+            ("For=\"192.0.2.43:47011\"", "192.0.2.43"),
+            ("for=\"[2001:db8:cafe::17]\"", "2001:db8:cafe::17"),
+            ("for=\"[2001:db8:cafe::17]:4711\"", "2001:db8:cafe::17"),
+            ("for=\"[2001:db8:cafe::17]:_port\"", "2001:db8:cafe::17"),
+        ];
+        for (header, ip) in &cases {
+            let (client, _) = resolve(Some("10.0.0.1"), &[("Forwarded", header)]);
+            assert_eq!(client, some(ip), "{}", header);
+        }
+    }
+
+    #[test]
+    fn takes_the_scheme_and_host_of_the_client_hop() {
+        let header = "for=\"[2001:db8:cafe::17]:4711\";proto=https;host=\"example.com:8443\", \
+                      for=10.0.0.2;proto=http;host=internal";
+        let (client, url) = resolve(Some("10.0.0.1"), &[("Forwarded", header)]);
+        assert_eq!(client, some("2001:db8:cafe::17"));
+        assert_eq!(url, "https://example.com:8443/path");
+
+        let header = "for=192.0.2.1;host=\"[2001:db8::1]:8080\"";
+        let (_, url) = resolve(Some("10.0.0.1"), &[("Forwarded", header)]);
+        assert_eq!(url, "http://[2001:db8::1]:8080/path");
+
+        // Other schemes, and hosts that aren't valid, are ignored.
+        let header = "for=192.0.2.1;proto=ftp;host=\"bad host\"";
+        let (_, url) = resolve(Some("10.0.0.1"), &[("Forwarded", header)]);
+        assert_eq!(url, "http://localhost/path");
+    }
+
+    #[test]
+    fn prefers_forwarded_over_x_forwarded() {
+        let headers = [
+            ("Forwarded", "for=192.0.2.1;proto=https"),
+            ("X-Forwarded-For", "198.51.100.1"),
+            ("X-Forwarded-Proto", "http"),
+        ];
+        let (client, url) = resolve(Some("10.0.0.1"), &headers);
+        assert_eq!(client, some("192.0.2.1"));
+        assert_eq!(url, "https://localhost/path");
+    }
+
+    #[test]
+    fn matches_x_forwarded_values_from_the_right() {
+        // A proto for each hop.
+        let headers = [
+            ("X-Forwarded-For", "192.0.2.1, 10.0.0.2"),
+            ("X-Forwarded-Proto", "https, http"),
+        ];
+        assert_eq!(
+            resolve(Some("10.0.0.1"), &headers).1,
+            "https://localhost/path"
+        );
+
+        // A single proto, set by the proxy the client connected to.
+        let headers = [
+            ("X-Forwarded-For", "192.0.2.1, 10.0.0.2"),
+            ("X-Forwarded-Proto", "https"),
+            ("X-Forwarded-Host", "example.com"),
+        ];
+        assert_eq!(
+            resolve(Some("10.0.0.1"), &headers).1,
+            "https://example.com/path"
+        );
+
+        // More protos than hops.
+        let headers = [
+            ("X-Forwarded-For", "192.0.2.1"),
+            ("X-Forwarded-Proto", "http, https"),
+        ];
+        assert_eq!(
+            resolve(Some("10.0.0.1"), &headers).1,
+            "https://localhost/path"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Composable middleware for `HttpService`.
+
+use std::fmt;
+
+/// Decorates an `HttpService`, producing a new service that wraps it.
+///
+/// A layer is the reusable recipe for a middleware: it holds the middleware's
+/// configuration, and is applied to an inner service to produce the wrapping
+/// service. The wrapping service is responsible for forwarding `connect` and
+/// `respond` to the service it wraps.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{ConnectionInfo, HttpService, Layer, Request, Response, ServiceBuilder};
+///
+/// /// Logs the path of every request before passing it on.
+/// struct LogLayer;
+///
+/// impl<S> Layer<S> for LogLayer {
+///     type Service = Log<S>;
+///
+///     fn layer(&self, inner: S) -> Self::Service {
+///         Log(inner)
+///     }
+/// }
+///
+/// struct Log<S>(S);
+///
+/// impl<S: HttpService> HttpService for Log<S> {
+///     type Connection = S::Connection;
+///     type ConnectionError = S::ConnectionError;
+///     type ConnectionFuture = S::ConnectionFuture;
+///     type ResponseError = S::ResponseError;
+///     type ResponseFuture = S::ResponseFuture;
+///
+///     fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture {
+///         self.0.connect(info)
+///     }
+///
+///     fn respond(&self, conn: Self::Connection, req: Request) -> Self::ResponseFuture {
+///         println!("{}", req.url().path());
+///         self.0.respond(conn, req)
+///     }
+/// }
+///
+/// let service = ServiceBuilder::new()
+///     .layer(LogLayer)
+///     .service(|_req: Request| async { Ok::<_, http_service::Error>(Response::from("hello")) });
+/// ```
+pub trait Layer<S> {
+    /// The service produced by wrapping the inner service.
+    type Service;
+
+    /// Wrap the given service.
+    fn layer(&self, inner: S) -> Self::Service;
+}
+
+/// A layer that returns the inner service unchanged.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Identity {
+    _priv: (),
+}
+
+impl Identity {
+    /// Create a new `Identity` layer.
+    pub fn new() -> Self {
+        Identity { _priv: () }
+    }
+}
+
+impl<S> Layer<S> for Identity {
+    type Service = S;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        inner
+    }
+}
+
+/// Two layers applied one after the other.
+///
+/// `inner` is applied to the service first, and `outer` wraps the result.
+#[derive(Debug, Clone)]
+pub struct Stack<Inner, Outer> {
+    inner: Inner,
+    outer: Outer,
+}
+
+impl<Inner, Outer> Stack<Inner, Outer> {
+    /// Create a new `Stack`.
+    pub fn new(inner: Inner, outer: Outer) -> Self {
+        Stack { inner, outer }
+    }
+}
+
+impl<S, Inner, Outer> Layer<S> for Stack<Inner, Outer>
+where
+    Inner: Layer<S>,
+    Outer: Layer<Inner::Service>,
+{
+    type Service = Outer::Service;
+
+    fn layer(&self, service: S) -> Self::Service {
+        self.outer.layer(self.inner.layer(service))
+    }
+}
+
+/// A layer built from a closure.
+///
+/// Created by [`layer_fn`](fn.layer_fn.html).
+#[derive(Clone, Copy)]
+pub struct LayerFn<F> {
+    f: F,
+}
+
+/// Create a layer from a closure that wraps a service.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{layer_fn, Layer, Request, Response};
+/// # #[allow(dead_code)]
+/// # struct Log<S>(S);
+///
+/// let layer = layer_fn(|inner| Log(inner));
+/// let service = layer.layer(|_req: Request| async {
+///     Ok::<_, http_service::Error>(Response::from("hello"))
+/// });
+/// ```
+pub fn layer_fn<F>(f: F) -> LayerFn<F> {
+    LayerFn { f }
+}
+
+impl<F, S, Out> Layer<S> for LayerFn<F>
+where
+    F: Fn(S) -> Out,
+{
+    type Service = Out;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        (self.f)(inner)
+    }
+}
+
+impl<F> fmt::Debug for LayerFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayerFn").finish()
+    }
+}
+
+/// Build a service from a stack of layers.
+///
+/// Layers are applied in the order they are added: the first layer added is
+/// the outermost, and sees each request first.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{Identity, Request, Response, ServiceBuilder};
+///
+/// // `Identity` stands in for any layer, such as a logger or a timeout.
+/// let service = ServiceBuilder::new()
+///     .layer(Identity::new())
+///     .layer(Identity::new())
+///     .service(|_req: Request| async {
+///         Ok::<_, http_service::Error>(Response::from("hello"))
+///     });
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServiceBuilder<L> {
+    layer: L,
+}
+
+impl ServiceBuilder<Identity> {
+    /// Create a new, empty `ServiceBuilder`.
+    pub fn new() -> Self {
+        ServiceBuilder {
+            layer: Identity::new(),
+        }
+    }
+}
+
+impl Default for ServiceBuilder<Identity> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L> ServiceBuilder<L> {
+    /// Add a layer, wrapping every service the builder produces.
+    ///
+    /// The new layer sits inside all previously added layers.
+    pub fn layer<T>(self, layer: T) -> ServiceBuilder<Stack<T, L>> {
+        ServiceBuilder {
+            layer: Stack::new(layer, self.layer),
+        }
+    }
+
+    /// Wrap the given service in every layer of the stack.
+    pub fn service<S>(&self, service: S) -> L::Service
+    where
+        L: Layer<S>,
+    {
+        self.layer.layer(service)
+    }
+
+    /// Get the layer representing the whole stack.
+    pub fn into_inner(self) -> L {
+        self.layer
+    }
+}
+
+impl<S, L: Layer<S>> Layer<S> for ServiceBuilder<L> {
+    type Service = L::Service;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        self.layer.layer(inner)
+    }
+}
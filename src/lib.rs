@@ -10,6 +10,27 @@ use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+mod boxed;
+mod connection;
+mod ext;
+mod forwarded;
+mod layer;
+mod local;
+mod router;
+
+pub use ipnet::IpNet;
+
+pub use boxed::{BoxConnection, BoxFuture, BoxService};
+pub use connection::{ConnectionInfo, LocalAddr, PeerAddr, PeerCredentials, ProxyTlv};
+pub use ext::{
+    AndThen, AndThenFuture, MapErr, MapErrFuture, MapRequest, MapResponse, MapResponseFuture,
+    ServiceExt,
+};
+pub use forwarded::{ClientIp, Forwarded, ForwardedConnect, ForwardedConnection, ForwardedLayer};
+pub use layer::{layer_fn, Identity, Layer, LayerFn, ServiceBuilder, Stack};
+pub use local::{local_service_fn, LocalHttpService, LocalServiceFn};
+pub use router::{Params, Router, RouterConnection};
+
 /// The raw body of an http request or response.
 pub type Body = http_types::Body;
 
@@ -41,8 +62,9 @@ pub trait HttpService: Send + Sync + 'static {
     /// This method is called each time the server receives a new connection request,
     /// but before actually exchanging any data with the client.
     ///
-    /// Returning an error will result in the server immediately dropping
-    /// the connection.
+    /// Returning an error refuses the connection: the requests the client
+    /// already sent on it are answered with a response made from the error,
+    /// and the connection is closed.
     type ConnectionFuture: Send
         + 'static
         + Future<Output = Result<Self::Connection, Self::ConnectionError>>;
@@ -50,17 +72,19 @@ pub trait HttpService: Send + Sync + 'static {
     /// Initiate a new connection.
     ///
     /// This method is given access to the global service (`&self`), which may provide
-    /// handles to connection pools, thread pools, or other global data.
-    fn connect(&self) -> Self::ConnectionFuture;
+    /// handles to connection pools, thread pools, or other global data, and to
+    /// the information the server has about the connection, such as the peer address.
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture;
 
     /// Response error.
     type ResponseError: Into<Error> + Send;
 
     /// The async computation for producing the response.
     ///
-    /// Returning an error will result in the server immediately dropping
-    /// the connection. It is usually preferable to instead return an HTTP response
-    /// with an error status code.
+    /// Returning an error makes the server send a response made from the
+    /// error, with the status code it carries, and keep serving the
+    /// connection. Servers let the way errors are turned into responses be
+    /// configured.
     type ResponseFuture: Send + 'static + Future<Output = Result<Response, Self::ResponseError>>;
 
     /// Begin handling a single request.
@@ -82,7 +106,7 @@ where
     type ResponseFuture = R;
     type ResponseError = E;
 
-    fn connect(&self) -> Self::ConnectionFuture {
+    fn connect(&self, _info: &ConnectionInfo) -> Self::ConnectionFuture {
         OkFuture(true)
     }
 
@@ -0,0 +1,118 @@
+//! Services that stay on the thread they were created on.
+
+use std::fmt;
+use std::future::Future;
+
+use crate::{ConnectionInfo, Error, HttpService, OkFuture, Request, Response};
+
+/// An async HTTP service that never leaves its thread.
+///
+/// This is [`HttpService`](trait.HttpService.html) without the `Send` and
+/// `Sync` bounds on the service, its connections and its futures, so that it
+/// can hold `Rc` and `RefCell` state. Servers run a local service on the
+/// thread it was created on, such as with one instance of the service per
+/// thread.
+///
+/// Every `HttpService` is a `LocalHttpService`. Closures that aren't `Send`
+/// are turned into a local service by [`local_service_fn`](fn.local_service_fn.html).
+pub trait LocalHttpService: 'static {
+    /// An individual connection.
+    type Connection: 'static + Clone;
+
+    /// Error when trying to connect.
+    type ConnectionError: Into<Error>;
+
+    /// A future for setting up an individual connection.
+    type ConnectionFuture: 'static
+        + Future<Output = Result<Self::Connection, Self::ConnectionError>>;
+
+    /// Initiate a new connection.
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture;
+
+    /// Response error.
+    type ResponseError: Into<Error>;
+
+    /// The async computation for producing the response.
+    type ResponseFuture: 'static + Future<Output = Result<Response, Self::ResponseError>>;
+
+    /// Begin handling a single request.
+    fn respond(&self, conn: Self::Connection, req: Request) -> Self::ResponseFuture;
+}
+
+impl<S: HttpService> LocalHttpService for S {
+    type Connection = S::Connection;
+    type ConnectionError = S::ConnectionError;
+    type ConnectionFuture = S::ConnectionFuture;
+    type ResponseError = S::ResponseError;
+    type ResponseFuture = S::ResponseFuture;
+
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture {
+        HttpService::connect(self, info)
+    }
+
+    fn respond(&self, conn: Self::Connection, req: Request) -> Self::ResponseFuture {
+        HttpService::respond(self, conn, req)
+    }
+}
+
+/// Create a local service from a closure handling each request.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{local_service_fn, Request, Response};
+/// use std::cell::Cell;
+/// use std::rc::Rc;
+///
+/// let hits = Rc::new(Cell::new(0));
+/// let service = local_service_fn(move |_req: Request| {
+///     let hits = hits.clone();
+///     async move {
+///         hits.set(hits.get() + 1);
+///         Ok::<_, http_service::Error>(Response::from(format!("hit {}", hits.get())))
+///     }
+/// });
+/// ```
+pub fn local_service_fn<F, R, E>(f: F) -> LocalServiceFn<F>
+where
+    F: 'static + Fn(Request) -> R,
+    R: 'static + Future<Output = Result<Response, E>>,
+    E: Into<Error>,
+{
+    LocalServiceFn { f }
+}
+
+/// A local service handling each request with a closure.
+///
+/// Created by [`local_service_fn`](fn.local_service_fn.html).
+#[derive(Clone)]
+pub struct LocalServiceFn<F> {
+    f: F,
+}
+
+impl<F, R, E> LocalHttpService for LocalServiceFn<F>
+where
+    F: 'static + Fn(Request) -> R,
+    R: 'static + Future<Output = Result<Response, E>>,
+    E: Into<Error>,
+{
+    type Connection = ();
+    type ConnectionError = Error;
+    type ConnectionFuture = OkFuture;
+    type ResponseFuture = R;
+    type ResponseError = E;
+
+    fn connect(&self, _info: &ConnectionInfo) -> Self::ConnectionFuture {
+        OkFuture(true)
+    }
+
+    fn respond(&self, _conn: Self::Connection, req: Request) -> Self::ResponseFuture {
+        (self.f)(req)
+    }
+}
+
+impl<F> fmt::Debug for LocalServiceFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalServiceFn").finish()
+    }
+}
@@ -0,0 +1,681 @@
+//! Method- and path-based request routing.
+
+use std::sync::Arc;
+
+use futures::lock::Mutex;
+use http_types::{Method, StatusCode};
+
+use crate::{
+    BoxConnection, BoxFuture, BoxService, ConnectionInfo, Error, HttpService, Request, Response,
+};
+
+/// A service that dispatches requests to other services based on the request
+/// method and path.
+///
+/// Paths are made of `/`-separated segments. Each segment of a route is either:
+///
+/// - a literal, such as `users`, which must match exactly;
+/// - a named parameter, such as `:id`, which matches any single segment;
+/// - a wildcard, such as `*path`, which matches the rest of the path and must
+///   be the last segment.
+///
+/// When several routes match, literals are preferred over parameters, and
+/// parameters over wildcards. The captured values are available to the
+/// handler through the [`Params`](struct.Params.html) request extension.
+///
+/// Requests that match no route receive a `404 Not Found` response. Requests
+/// whose path matches a route, but not its method, receive a
+/// `405 Method Not Allowed` response with an `Allow` header. `HEAD` requests
+/// are no exception: they are only routed to services registered for `HEAD`,
+/// as answering them with a `GET` route's response would send its body.
+///
+/// # Examples
+///
+/// ```
+/// use http_service::{Params, Request, Response, Router};
+///
+/// let router = Router::new()
+///     .get("/", |_req: Request| async {
+///         Ok::<_, http_service::Error>(Response::from("index"))
+///     })
+///     .get("/users/:id", |req: Request| async move {
+///         let params = req.local().get::<Params>().unwrap();
+///         let body = format!("user {}", params.get("id").unwrap());
+///         Ok::<_, http_service::Error>(Response::from(body))
+///     });
+///
+/// let app = Router::new().nest("/api", router);
+/// ```
+#[derive(Debug, Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    mounts: Vec<Mount>,
+    services: Vec<BoxService>,
+}
+
+#[derive(Debug)]
+struct Route {
+    pattern: Pattern,
+    method: Method,
+    service: usize,
+}
+
+#[derive(Debug)]
+struct Mount {
+    prefix: Pattern,
+    service: usize,
+}
+
+impl Router {
+    /// Create a new, empty router.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route requests with the given method and path to a service.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a wildcard isn't the last segment of the path.
+    pub fn route<S>(mut self, path: &str, method: Method, service: S) -> Self
+    where
+        S: HttpService,
+        S::Connection: Sync,
+    {
+        let service = self.push(service);
+        self.routes.push(Route {
+            pattern: Pattern::parse(path),
+            method,
+            service,
+        });
+        self
+    }
+
+    /// Route `GET` requests for the given path to a service.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a wildcard isn't the last segment of the path.
+    pub fn get<S>(self, path: &str, service: S) -> Self
+    where
+        S: HttpService,
+        S::Connection: Sync,
+    {
+        self.route(path, Method::Get, service)
+    }
+
+    /// Route `POST` requests for the given path to a service.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a wildcard isn't the last segment of the path.
+    pub fn post<S>(self, path: &str, service: S) -> Self
+    where
+        S: HttpService,
+        S::Connection: Sync,
+    {
+        self.route(path, Method::Post, service)
+    }
+
+    /// Route `PUT` requests for the given path to a service.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a wildcard isn't the last segment of the path.
+    pub fn put<S>(self, path: &str, service: S) -> Self
+    where
+        S: HttpService,
+        S::Connection: Sync,
+    {
+        self.route(path, Method::Put, service)
+    }
+
+    /// Route `PATCH` requests for the given path to a service.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a wildcard isn't the last segment of the path.
+    pub fn patch<S>(self, path: &str, service: S) -> Self
+    where
+        S: HttpService,
+        S::Connection: Sync,
+    {
+        self.route(path, Method::Patch, service)
+    }
+
+    /// Route `DELETE` requests for the given path to a service.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a wildcard isn't the last segment of the path.
+    pub fn delete<S>(self, path: &str, service: S) -> Self
+    where
+        S: HttpService,
+        S::Connection: Sync,
+    {
+        self.route(path, Method::Delete, service)
+    }
+
+    /// Mount a service, such as another `Router`, under the given prefix.
+    ///
+    /// Requests of any method whose path starts with the prefix are passed to
+    /// the service, with the prefix stripped from the request path. The prefix
+    /// may contain named parameters, but not wildcards.
+    ///
+    /// Routes take precedence over mounted services. Of several matching
+    /// prefixes, the longest is preferred, then literals over parameters as
+    /// for routes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefix contains a wildcard.
+    pub fn nest<S>(mut self, prefix: &str, service: S) -> Self
+    where
+        S: HttpService,
+        S::Connection: Sync,
+    {
+        let prefix = Pattern::parse(prefix);
+        assert!(
+            !prefix.has_wildcard(),
+            "nested prefixes may not contain wildcards"
+        );
+        let service = self.push(service);
+        self.mounts.push(Mount { prefix, service });
+        self
+    }
+
+    fn push<S>(&mut self, service: S) -> usize
+    where
+        S: HttpService,
+        S::Connection: Sync,
+    {
+        self.services.push(BoxService::new(service));
+        self.services.len() - 1
+    }
+
+    fn dispatch(&self, method: Method, path: &str) -> Dispatch {
+        let mut best: Option<(&Route, Params)> = None;
+        let mut allow: Vec<Method> = vec![];
+        for route in &self.routes {
+            let params = match route.pattern.matches(path) {
+                Some(params) => params,
+                None => continue,
+            };
+            if route.method != method {
+                if !allow.contains(&route.method) {
+                    allow.push(route.method);
+                }
+                continue;
+            }
+            match &best {
+                Some((current, _)) if current.pattern.rank >= route.pattern.rank => {}
+                _ => best = Some((route, params)),
+            }
+        }
+        if let Some((route, params)) = best {
+            return Dispatch::Route(route.service, params);
+        }
+
+        let mut best: Option<(&Mount, Params, String)> = None;
+        for mount in &self.mounts {
+            let (params, rest) = match mount.prefix.match_prefix(path) {
+                Some(matched) => matched,
+                None => continue,
+            };
+            match &best {
+                Some((current, _, _))
+                    if current.prefix.prefix_rank() >= mount.prefix.prefix_rank() => {}
+                _ => best = Some((mount, params, rest)),
+            }
+        }
+        if let Some((mount, params, rest)) = best {
+            return Dispatch::Mount(mount.service, params, rest);
+        }
+
+        if allow.is_empty() {
+            Dispatch::NotFound
+        } else {
+            Dispatch::MethodNotAllowed(allow)
+        }
+    }
+}
+
+enum Dispatch {
+    Route(usize, Params),
+    Mount(usize, Params, String),
+    MethodNotAllowed(Vec<Method>),
+    NotFound,
+}
+
+impl HttpService for Router {
+    type Connection = RouterConnection;
+    type ConnectionError = Error;
+    type ConnectionFuture = BoxFuture<Result<RouterConnection, Error>>;
+    type ResponseError = Error;
+    type ResponseFuture = BoxFuture<Result<Response, Error>>;
+
+    fn connect(&self, info: &ConnectionInfo) -> Self::ConnectionFuture {
+        let conn = RouterConnection(Arc::new(Connections {
+            info: info.clone(),
+            conns: self.services.iter().map(|_| Mutex::new(None)).collect(),
+        }));
+        Box::pin(async move { Ok(conn) })
+    }
+
+    fn respond(&self, conn: Self::Connection, mut req: Request) -> Self::ResponseFuture {
+        let path = req.url().path().to_owned();
+        let (index, params) = match self.dispatch(req.method(), &path) {
+            Dispatch::Route(service, params) => (service, params),
+            Dispatch::Mount(service, params, rest) => {
+                req.url_mut().set_path(&rest);
+                (service, params)
+            }
+            Dispatch::MethodNotAllowed(allow) => {
+                return Box::pin(async move {
+                    let allow: Vec<String> = allow.iter().map(|m| m.to_string()).collect();
+                    let mut res = Response::new(StatusCode::MethodNotAllowed);
+                    res.insert_header("Allow", allow.join(", ").as_str())?;
+                    Ok(res)
+                });
+            }
+            Dispatch::NotFound => {
+                return Box::pin(async { Ok(Response::new(StatusCode::NotFound)) });
+            }
+        };
+
+        match req.local_mut().get_mut::<Params>() {
+            Some(existing) => existing.0.extend(params.0),
+            None => {
+                req.local_mut().insert(params);
+            }
+        }
+        let service = self.services[index].clone();
+        Box::pin(async move {
+            let conn = conn.0.get(&service, index).await?;
+            service.respond(conn, req).await
+        })
+    }
+}
+
+/// The connection state of a [`Router`](struct.Router.html).
+///
+/// A service is connected to the first time a request on the connection is
+/// dispatched to it, and that connection is reused by later requests. If
+/// connecting fails, the error is returned for that request, and the next
+/// request dispatched to the service tries again.
+#[derive(Debug, Clone)]
+pub struct RouterConnection(Arc<Connections>);
+
+#[derive(Debug)]
+struct Connections {
+    info: ConnectionInfo,
+    /// The connection to each service, once one was made.
+    conns: Vec<Mutex<Option<BoxConnection>>>,
+}
+
+impl Connections {
+    /// Get the connection to a service, connecting if there's none yet.
+    ///
+    /// Concurrent requests wait for the same connection to be made.
+    async fn get(&self, service: &BoxService, index: usize) -> Result<BoxConnection, Error> {
+        let mut conn = self.conns[index].lock().await;
+        match &*conn {
+            Some(conn) => Ok(conn.clone()),
+            None => {
+                let new = service.connect(&self.info).await?;
+                *conn = Some(new.clone());
+                Ok(new)
+            }
+        }
+    }
+}
+
+/// Path parameters captured by a [`Router`](struct.Router.html).
+///
+/// Inserted into the extensions of every request dispatched by a router.
+/// Parameters captured by the prefixes of nested routers are included.
+///
+/// Values are as they appear in the request path, without percent-decoding.
+#[derive(Debug, Clone, Default)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+    /// Get the value of a named parameter or wildcard.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Iterate over all captured names and values.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+#[derive(Debug)]
+enum Segment {
+    Static(String),
+    Param(String),
+    Wildcard(String),
+}
+
+#[derive(Debug)]
+struct Pattern {
+    segments: Vec<Segment>,
+    /// Used to pick the most specific of several matching patterns.
+    rank: Vec<u8>,
+}
+
+impl Pattern {
+    /// Parse a route, panicking if a wildcard isn't its last segment.
+    fn parse(path: &str) -> Self {
+        let segments: Vec<Segment> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                if let Some(name) = s.strip_prefix(':') {
+                    Segment::Param(name.to_owned())
+                } else if let Some(name) = s.strip_prefix('*') {
+                    Segment::Wildcard(name.to_owned())
+                } else {
+                    Segment::Static(s.to_owned())
+                }
+            })
+            .collect();
+        for segment in segments.iter().rev().skip(1) {
+            if let Segment::Wildcard(_) = segment {
+                panic!("wildcards must be the last segment of a route: {}", path);
+            }
+        }
+        let mut rank: Vec<u8> = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Static(_) => 3,
+                Segment::Param(_) => 2,
+                Segment::Wildcard(_) => 1,
+            })
+            .collect();
+        rank.push(4);
+        Pattern { segments, rank }
+    }
+
+    /// The rank of the pattern as a prefix: of several matching prefixes,
+    /// the one with the most segments is the most specific, then the one with
+    /// literals where the others have parameters.
+    fn prefix_rank(&self) -> (usize, &[u8]) {
+        (self.segments.len(), &self.rank[..self.segments.len()])
+    }
+
+    fn has_wildcard(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Wildcard(_)))
+    }
+
+    /// Match the whole path.
+    fn matches(&self, path: &str) -> Option<Params> {
+        match self.match_prefix(path) {
+            Some((params, rest)) if rest == "/" => Some(params),
+            _ => None,
+        }
+    }
+
+    /// Match the start of the path, returning the captures and the rest of
+    /// the path.
+    fn match_prefix(&self, path: &str) -> Option<(Params, String)> {
+        let mut params = Params::default();
+        let mut rest = path.trim_start_matches('/');
+        for segment in &self.segments {
+            if let Segment::Wildcard(name) = segment {
+                params.0.push((name.clone(), rest.to_owned()));
+                rest = "";
+                break;
+            }
+            let (head, tail) = match rest.find('/') {
+                Some(i) => (&rest[..i], &rest[i + 1..]),
+                None => (rest, ""),
+            };
+            match segment {
+                _ if head.is_empty() => return None,
+                Segment::Static(literal) if literal != head => return None,
+                Segment::Param(name) => params.0.push((name.clone(), head.to_owned())),
+                _ => {}
+            }
+            rest = tail.trim_start_matches('/');
+        }
+        Some((params, format!("/{}", rest)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::executor::block_on;
+    use http_types::Url;
+
+    /// A service answering with its name, the path it was given, and the
+    /// captured parameters.
+    fn handler(
+        name: &'static str,
+    ) -> impl Fn(Request) -> BoxFuture<Result<Response, Error>> + Send + Sync + 'static {
+        move |req: Request| {
+            let params = req.local().get::<Params>().map_or(String::new(), |params| {
+                let params: Vec<_> = params.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                params.join(",")
+            });
+            let body = format!("{} {} {}", name, req.url().path(), params);
+            Box::pin(async move { Ok(Response::from(body.trim_end())) })
+        }
+    }
+
+    fn send(router: &Router, method: Method, path: &str) -> Response {
+        let conn = block_on(router.connect(&ConnectionInfo::new())).unwrap();
+        let url = Url::parse(&format!("http://localhost{}", path)).unwrap();
+        block_on(router.respond(conn, Request::new(method, url))).unwrap()
+    }
+
+    fn get(router: &Router, path: &str) -> String {
+        let res = send(router, Method::Get, path);
+        assert_eq!(res.status(), StatusCode::Ok, "GET {}", path);
+        block_on(res.body_string()).unwrap()
+    }
+
+    #[test]
+    fn prefers_literals_over_params_over_wildcards() {
+        let router = Router::new()
+            .get("/users/*rest", handler("wildcard"))
+            .get("/users/:id", handler("param"))
+            .get("/users/me", handler("literal"));
+
+        assert_eq!(get(&router, "/users/me"), "literal /users/me");
+        assert_eq!(get(&router, "/users/42"), "param /users/42 id=42");
+        assert_eq!(
+            get(&router, "/users/42/posts"),
+            "wildcard /users/42/posts rest=42/posts"
+        );
+    }
+
+    #[test]
+    fn prefers_longer_matches() {
+        let router = Router::new()
+            .get("/:a/*rest", handler("short"))
+            .get("/:a/:b/c", handler("long"));
+
+        assert_eq!(get(&router, "/x/y/c"), "long /x/y/c a=x,b=y");
+        assert_eq!(get(&router, "/x/y/d"), "short /x/y/d a=x,rest=y/d");
+    }
+
+    #[test]
+    fn captures_params_and_wildcards() {
+        let router = Router::new()
+            .get("/users/:id/posts/:post", handler("post"))
+            .get("/files/*path", handler("file"));
+
+        assert_eq!(
+            get(&router, "/users/1/posts/2"),
+            "post /users/1/posts/2 id=1,post=2"
+        );
+        assert_eq!(
+            get(&router, "/files/a/b.txt"),
+            "file /files/a/b.txt path=a/b.txt"
+        );
+        assert_eq!(get(&router, "/files"), "file /files path=");
+    }
+
+    #[test]
+    fn ignores_empty_segments() {
+        let router = Router::new().get("/users/:id", handler("user"));
+
+        assert_eq!(get(&router, "/users/42/"), "user /users/42/ id=42");
+        assert_eq!(get(&router, "//users//42"), "user //users//42 id=42");
+    }
+
+    #[test]
+    fn answers_unknown_paths_with_not_found() {
+        let router = Router::new()
+            .get("/", handler("index"))
+            .get("/users/:id", handler("user"));
+
+        assert_eq!(get(&router, "/"), "index /");
+        for path in &["/other", "/users", "/users/42/posts"] {
+            let res = send(&router, Method::Get, path);
+            assert_eq!(res.status(), StatusCode::NotFound, "{}", path);
+        }
+    }
+
+    #[test]
+    fn answers_other_methods_with_method_not_allowed() {
+        let router = Router::new()
+            .get("/items/:id", handler("get"))
+            .post("/items/:id", handler("post"))
+            .get("/items/:id", handler("get again"))
+            .delete("/other", handler("delete"));
+
+        let res = send(&router, Method::Put, "/items/1");
+        assert_eq!(res.status(), StatusCode::MethodNotAllowed);
+        assert_eq!(
+            res.header(&"Allow".parse().unwrap()).unwrap()[0],
+            "GET, POST"
+        );
+
+        let res = send(&router, Method::Post, "/items/1");
+        assert_eq!(res.status(), StatusCode::Ok);
+    }
+
+    #[test]
+    fn strips_the_prefix_of_nested_services() {
+        let api = Router::new().get("/users/:id", handler("api"));
+        let router = Router::new().nest("/api/:version", api);
+
+        assert_eq!(
+            get(&router, "/api/v1/users/7"),
+            "api /users/7 version=v1,id=7"
+        );
+        let res = send(&router, Method::Get, "/api/v1/other");
+        assert_eq!(res.status(), StatusCode::NotFound);
+    }
+
+    #[test]
+    fn prefers_routes_over_nested_services() {
+        let router = Router::new()
+            .nest("/api", handler("mount"))
+            .nest("/api/v1", handler("longer mount"))
+            .get("/api/health", handler("route"));
+
+        assert_eq!(get(&router, "/api/health"), "route /api/health");
+        assert_eq!(get(&router, "/api/other"), "mount /other");
+        assert_eq!(get(&router, "/api/v1/other"), "longer mount /other");
+        assert_eq!(get(&router, "/api"), "mount /");
+
+        // Mounted services take any method, even one a route doesn't.
+        let res = send(&router, Method::Post, "/api/health");
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(block_on(res.body_string()).unwrap(), "mount /health");
+    }
+
+    #[test]
+    fn prefers_longer_nested_prefixes_over_literals() {
+        let router = Router::new()
+            .nest("/api", handler("literal"))
+            .nest("/:version/users", handler("longer"))
+            .nest("/:version/:resource", handler("param"));
+
+        assert_eq!(get(&router, "/api/users/x"), "longer /x version=api");
+        assert_eq!(
+            get(&router, "/api/posts/x"),
+            "param /x version=api,resource=posts"
+        );
+        assert_eq!(get(&router, "/api"), "literal /");
+    }
+
+    #[test]
+    fn routes_head_requests_to_head_routes_only() {
+        let router = Router::new()
+            .get("/page", handler("get"))
+            .route("/both", Method::Head, handler("head"))
+            .get("/both", handler("get"));
+
+        let res = send(&router, Method::Head, "/page");
+        assert_eq!(res.status(), StatusCode::MethodNotAllowed);
+        assert_eq!(
+            send(&router, Method::Head, "/both").status(),
+            StatusCode::Ok
+        );
+    }
+
+    /// A service counting the connections made to it.
+    #[derive(Clone, Default)]
+    struct Counter(Arc<AtomicUsize>);
+
+    impl HttpService for Counter {
+        type Connection = ();
+        type ConnectionError = Error;
+        type ConnectionFuture = BoxFuture<Result<(), Error>>;
+        type ResponseError = Error;
+        type ResponseFuture = BoxFuture<Result<Response, Error>>;
+
+        fn connect(&self, _info: &ConnectionInfo) -> Self::ConnectionFuture {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Ok(()) })
+        }
+
+        fn respond(&self, _conn: (), _req: Request) -> Self::ResponseFuture {
+            Box::pin(async { Ok(Response::new(StatusCode::Ok)) })
+        }
+    }
+
+    #[test]
+    fn connects_to_services_when_first_dispatched_to() {
+        let (a, b) = (Counter::default(), Counter::default());
+        let router = Router::new().get("/a", a.clone()).get("/b", b.clone());
+
+        let conn = block_on(router.connect(&ConnectionInfo::new())).unwrap();
+        let url = Url::parse("http://localhost/a").unwrap();
+        for _ in 0..3 {
+            let req = Request::new(Method::Get, url.clone());
+            block_on(router.respond(conn.clone(), req)).unwrap();
+        }
+        let req = Request::new(Method::Get, Url::parse("http://localhost/c").unwrap());
+        block_on(router.respond(conn, req)).unwrap();
+
+        assert_eq!(a.0.load(Ordering::SeqCst), 1);
+        assert_eq!(b.0.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcards must be the last segment")]
+    fn rejects_wildcards_before_the_last_segment() {
+        Router::new().get("/*path/edit", handler("edit"));
+    }
+
+    #[test]
+    #[should_panic(expected = "nested prefixes may not contain wildcards")]
+    fn rejects_wildcards_in_prefixes() {
+        Router::new().nest("/static/*path", handler("static"));
+    }
+}